@@ -1,865 +1,1206 @@
-use crate::console_debug_plugin::Config;
-use crate::console_debug_plugin::ConfigValue;
-use crate::helpers::tilemap_utils::*;
+use crate::arena::{Arena, Boundary};
+use crate::behavior::{self, ant_state_system, AntState, AntStateChanged, StateTicks};
+use crate::caste::{self, Caste, CasteProfile};
+use crate::config::{
+    config_change_system, Config, ConfigChanged, ConfigFileError, ConfigKey, ConfigValue,
+};
+use crate::food::{self, food_source_system, FoodId, FoodSource, FoodType, NextFoodId};
+use crate::helpers::obstacle_grid::ObstacleGrid;
+use crate::helpers::pheromone_field::PheromoneField;
+use crate::helpers::sim_rng::{AntStream, SimRng};
+use crate::helpers::spatial_index::{spatial_index_system, SpatialIndex};
+use crate::locomotion::{
+    kinematic_locomotion_system, rigid_body_locomotion_system, DefaultLocomotion, DesiredHeading,
+    Locomotion, RigidBodyMotor,
+};
+use crate::pheromone_dynamics::{pheromone_dynamics_system, PheromoneDynamics};
+use crate::scenario::load_scenario;
+use crate::sim_commands::add_sim_commands;
+use crate::snapshot::load_snapshot;
+use crate::stats::{
+    self, colony_stats_system, population_system, stats_export_system, trip_system, ColonyStats,
+    StatsFile,
+};
+use crate::vision::{
+    self, line_of_sight, vision_system, Sight, VISION_DISTANCE, VISION_WALL_AVOIDANCE,
+};
 use bevy::{
-    core::FixedTimestep,
+    ecs::schedule::{ShouldRun, StageLabel},
     prelude::*,
-    render::render_resource::TextureUsages,
-    sprite::collide_aabb::{collide, Collision},
+    sprite::collide_aabb::Collision,
 };
-use bevy_ecs_tilemap::prelude::*;
+use bevy_rapier2d::physics::{step_world_system, PhysicsStages, TimestepMode};
 use bevy_rapier2d::prelude::*;
-use nalgebra::{Point2, Vector2};
-use noise::{HybridMulti, MultiFractal, NoiseFn};
-use rand::prelude::random;
+use nalgebra::{Point2, Translation2, UnitComplex};
+use noise::{HybridMulti, MultiFractal, NoiseFn, Seedable};
 use rand::Rng;
-use rand_chacha::rand_core::SeedableRng;
-use rand_chacha::ChaCha8Rng;
-use std::collections::HashSet;
-
-pub struct AntsPlugin;
-
-const TIME_STEP: f32 = 1.0 / 60.0;
-const BOUNDS_X: f32 = 900.0;
-const BOUNDS_Y: f32 = 600.0;
-const OBSTACLE_TILE_SIZE: f32 = 10.0;
-const OBSTACLE_COLOR: Color = Color::rgb(0.65, 0.16, 0.16);
-const FOOD_SIZE: f32 = 5.0;
-const FOOD_COLOR: Color = Color::rgb(0.0, 0.65, 0.0);
-const TRAIL_SIZE: f32 = 2.5;
-const TRAIL_GOT_FOOD_COLOR: Color = Color::rgb(0.88, 0.18, 0.24);
-const TRAIL_GATHERING_COLOR: Color = Color::rgb(0.28, 0.51, 0.87);
-const HOME_SIZE: f32 = 10.0;
-const HOME_COLOR: Color = Color::rgb(1.0, 1.0, 0.62);
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Simulation of ants, food, trails, homes and obstacles. Spawns no sprites and reads no window
+/// input, so it can run under `MinimalPlugins`; see `AntsRenderPlugin` for presentation.
+pub struct AntsPlugin {
+    pub tick_mode: TickMode,
+    pub scenario: Option<PathBuf>,
+    pub snapshot: Option<PathBuf>,
+    /// Overrides the `sim.seed` of the default world, scenario or snapshot.
+    pub seed: Option<i32>,
+    /// Config entries overriding those of the default world, scenario or snapshot.
+    pub config_overrides: ConfigOverrides,
+    /// TOML file to write the effective config to once startup is done.
+    pub dump_config: Option<PathBuf>,
+    /// CSV or JSON lines file to stream `ColonyStats` samples to.
+    pub stats: Option<PathBuf>,
+    /// Size, shape and boundary of the world. Scenarios and snapshots must match its size.
+    pub arena: Arena,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TickMode {
+    /// Step the simulation every `TIME_STEP` of wall-clock time.
+    FixedTimestep,
+    /// Step the simulation once per `App::update`, as fast as the caller drives it.
+    EveryUpdate,
+}
+
+impl Default for AntsPlugin {
+    fn default() -> AntsPlugin {
+        AntsPlugin {
+            tick_mode: TickMode::FixedTimestep,
+            scenario: None,
+            snapshot: None,
+            seed: None,
+            config_overrides: ConfigOverrides::default(),
+            dump_config: None,
+            stats: None,
+            arena: Arena::default(),
+        }
+    }
+}
+
+pub const TIME_STEP: f32 = 1.0 / 60.0;
+pub const DEFAULT_SEED: i32 = 0;
+pub const OBSTACLE_TILE_SIZE: f32 = 10.0;
+pub const FOOD_SIZE: f32 = 10.0;
+pub const PHEROMONE_CELL_SIZE: f32 = 5.0;
+const SPATIAL_INDEX_CELL_SIZE: f32 = 20.0;
+/// Ticks a frame the simulation runs at most to catch up with wall-clock time; beyond that it
+/// falls behind.
+const MAX_TICKS_PER_FRAME: usize = 100;
+pub const HOME_SIZE: f32 = 10.0;
+/// Number of colonies the pheromone field has channels for.
+pub const MAX_COLONIES: usize = 4;
 // TODO: fix ant size and scale
 const ANT_SIZE: f32 = 5.0;
 
+pub const SIM_SEED: ConfigKey<i32> = ConfigKey {
+    key: "sim.seed",
+    default: DEFAULT_SEED,
+    min: None,
+    max: None,
+    units: "",
+    description: "Seed of all simulation randomness; editing it reseeds the simulation",
+};
+
+pub const ANT_SPEED: ConfigKey<f32> = ConfigKey {
+    key: "ant.speed",
+    default: 40.0,
+    min: Some(0.0),
+    max: None,
+    units: "world units/s",
+    description: "Walking speed before state and caste multipliers",
+};
+
+pub const ANT_WANDERING: ConfigKey<f32> = ConfigKey {
+    key: "ant.wandering",
+    default: 0.02 * std::f32::consts::PI,
+    min: Some(0.0),
+    max: Some(std::f32::consts::PI),
+    units: "radians/tick",
+    description: "Largest random turn an ant makes each tick",
+};
+
+pub const MAP_OCTAVES: ConfigKey<usize> = ConfigKey {
+    key: "map.octaves",
+    default: 4,
+    min: Some(1),
+    max: Some(32),
+    units: "",
+    description: "Noise octaves of generated obstacles",
+};
+
+pub const MAP_FREQUENCY: ConfigKey<f32> = ConfigKey {
+    key: "map.frequency",
+    default: 0.005,
+    min: Some(0.0),
+    max: None,
+    units: "1/world units",
+    description: "Noise frequency of generated obstacles",
+};
+
+pub const MAP_LACUNARITY: ConfigKey<f32> = ConfigKey {
+    key: "map.lacunarity",
+    default: 1.0,
+    min: Some(0.0),
+    max: None,
+    units: "",
+    description: "Frequency multiplier between noise octaves",
+};
+
+pub const MAP_PERSISTENCE: ConfigKey<f32> = ConfigKey {
+    key: "map.persistence",
+    default: 0.2,
+    min: Some(0.0),
+    max: Some(1.0),
+    units: "",
+    description: "Amplitude multiplier between noise octaves",
+};
+
+pub const MAP_THRESHOLD: ConfigKey<f32> = ConfigKey {
+    key: "map.threshold",
+    default: 0.4,
+    min: Some(-1.0),
+    max: Some(1.0),
+    units: "",
+    description: "Noise value from which a tile becomes an obstacle",
+};
+
+pub const MAP_SEED: ConfigKey<i32> = ConfigKey {
+    key: "map.seed",
+    default: 0,
+    min: None,
+    max: None,
+    units: "",
+    description: "Noise seed of generated obstacles",
+};
+
+pub const TRAIL_SPAWN_PERIOD: ConfigKey<f32> = ConfigKey {
+    key: "trail.spawn_period",
+    default: 0.25,
+    min: Some(TIME_STEP),
+    max: None,
+    units: "s",
+    description: "Time between pheromone drops of an ant",
+};
+
+pub const TRAIL_INITIAL_STRENGTH: ConfigKey<f32> = ConfigKey {
+    key: "trail.initial_strength",
+    default: 1.0,
+    min: Some(0.0),
+    max: None,
+    units: "",
+    description: "Pheromone an ant drops at a time",
+};
+
+pub const SENSOR_ANGLE: ConfigKey<f32> = ConfigKey {
+    key: "sensor_angle",
+    default: std::f32::consts::PI / 4.0,
+    min: Some(0.0),
+    max: Some(std::f32::consts::PI),
+    units: "radians",
+    description: "Angle of the side trail sensors from the ant's heading",
+};
+
+pub const SENSOR_DISTANCE: ConfigKey<f32> = ConfigKey {
+    key: "sensor_distance",
+    default: 20.0,
+    min: Some(0.0),
+    max: None,
+    units: "world units",
+    description: "Distance of the trail sensors ahead of the ant",
+};
+
+pub const SENSOR_RADIUS: ConfigKey<f32> = ConfigKey {
+    key: "sensor_radius",
+    default: 7.66,
+    min: Some(0.0),
+    max: None,
+    units: "world units",
+    description: "Radius each trail sensor samples pheromone within",
+};
+
+pub const SENSOR_TURNING_COEFFICIENT: ConfigKey<f32> = ConfigKey {
+    key: "sensor_turning_coefficient",
+    default: 1.0,
+    min: Some(0.0),
+    max: None,
+    units: "",
+    description: "How sharply ants turn towards stronger pheromone",
+};
+
+pub const ANT_ENERGY_PER_SECOND: ConfigKey<f32> = ConfigKey {
+    key: "ant.energy_per_second",
+    default: 0.005,
+    min: Some(0.0),
+    max: None,
+    units: "energy/s",
+    description: "Energy an ant burns standing still",
+};
+
+pub const ANT_ENERGY_PER_DISTANCE: ConfigKey<f32> = ConfigKey {
+    key: "ant.energy_per_distance",
+    default: 0.0001,
+    min: Some(0.0),
+    max: None,
+    units: "energy/world unit",
+    description: "Extra energy an ant burns per distance walked",
+};
+
+pub const ANT_HUNGER: ConfigKey<f32> = ConfigKey {
+    key: "ant.hunger",
+    default: 0.3,
+    min: Some(0.0),
+    max: Some(1.0),
+    units: "energy",
+    description: "Energy below which an ant heads home to eat",
+};
+
+pub const HOME_ENERGY_PER_FOOD: ConfigKey<f32> = ConfigKey {
+    key: "home.energy_per_food",
+    default: 0.5,
+    min: Some(0.0),
+    max: Some(1.0),
+    units: "energy",
+    description: "Energy an ant gains per unit of stored food it eats",
+};
+
+pub const HOME_FOOD_PER_ANT: ConfigKey<usize> = ConfigKey {
+    key: "home.food_per_ant",
+    default: 5,
+    min: Some(1),
+    max: None,
+    units: "food",
+    description: "Stored food a home turns into each new ant",
+};
+
+pub const HOME_FOOD_RESERVE: ConfigKey<usize> = ConfigKey {
+    key: "home.food_reserve",
+    default: 5,
+    min: Some(0),
+    max: None,
+    units: "food",
+    description: "Stored food a home keeps back from raising ants",
+};
+
 impl Plugin for AntsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(TilemapPlugin)
-            .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
-            .add_plugin(RapierRenderPlugin)
+        // rigid bodies are stepped by `step_world_system` once per tick, `TIME_STEP` at a time
+        let rapier_configuration = RapierConfiguration {
+            scale: 5.0,
+            gravity: Vector::new(0.0, 0.0),
+            timestep_mode: TimestepMode::FixedTimestep,
+            ..Default::default()
+        };
+        // Every system that reads or writes state another one touches is explicitly ordered, so
+        // that a seed always plays out the same way.
+        let mut simulation_systems = SystemSet::new()
+            .label("simulation")
+            .with_system(simulation_tick_system.label("tick"))
+            .with_system(sim_rng_system.label("sim_rng"))
+            .with_system(spatial_index_system::<FoodSource>.label("spatial_index"))
+            .with_system(spatial_index_system::<Home>.label("spatial_index"))
+            .with_system(spatial_index_system::<Ant>.label("spatial_index"))
+            .with_system(vision_system.label("vision").after("spatial_index"))
+            .with_system(ant_state_system.label("ant_state").after("vision"))
+            .with_system(
+                ant_steering_system
+                    .label("steering")
+                    .after("tick")
+                    .after("sim_rng")
+                    .after("ant_state"),
+            )
+            .with_system(
+                kinematic_locomotion_system
+                    .label("ant_movement")
+                    .after("steering"),
+            )
+            .with_system(
+                rigid_body_locomotion_system
+                    .label("rigid_body_movement")
+                    .after("steering"),
+            )
+            .with_system(
+                obstacle_collision_system
+                    .label("obstacle_collision")
+                    .after("ant_movement"),
+            )
+            .with_system(
+                arena_boundary_system
+                    .label("arena_boundary")
+                    .after("obstacle_collision")
+                    .after("rigid_body_movement"),
+            )
+            .with_system(
+                food_collision_system
+                    .label("food_collision")
+                    .after("spatial_index")
+                    .after("arena_boundary"),
+            )
+            .with_system(
+                food_source_system
+                    .label("food_sources")
+                    .after("sim_rng")
+                    .after("food_collision"),
+            )
+            .with_system(
+                trail_spawn_system
+                    .label("trail_spawn")
+                    .after("food_collision"),
+            )
+            .with_system(
+                pheromone_dynamics_system
+                    .label("pheromone_field")
+                    .after("trail_spawn"),
+            )
+            .with_system(
+                ant_lifecycle_system
+                    .label("lifecycle")
+                    .after("rigid_body_movement")
+                    .after("trail_spawn"),
+            )
+            .with_system(
+                nest_reproduction_system
+                    .label("reproduction")
+                    .after("lifecycle"),
+            )
+            .with_system(
+                step_world_system::<NoUserData>
+                    .label("physics_step")
+                    .after("food_collision")
+                    .after("lifecycle"),
+            )
+            .with_system(trip_system.label("trips").after("food_collision"))
+            .with_system(population_system.label("population").after("reproduction"))
+            .with_system(
+                colony_stats_system
+                    .after("trips")
+                    .after("population")
+                    .after("pheromone_field"),
+            );
+        match self.tick_mode {
+            TickMode::FixedTimestep => {
+                simulation_systems =
+                    simulation_systems.with_run_criteria(fixed_timestep_run_criteria);
+            }
+            TickMode::EveryUpdate => {
+                simulation_systems =
+                    simulation_systems.with_run_criteria(every_update_run_criteria);
+            }
+        }
+        app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+            // the plugin's own step would run once per frame, however many ticks it had
+            .stage(PhysicsStages::StepWorld, |stage: &mut SystemStage| {
+                stage.set_run_criteria(never_run_criteria)
+            })
             .init_resource::<Config>()
             .init_resource::<MapGenerator>()
-            .init_resource::<EditorInput>()
-            .insert_resource(RapierConfiguration {
-                scale: 5.0,
-                gravity: Vector::new(0.0, 0.0),
+            .init_resource::<NextAntId>()
+            .init_resource::<NextFoodId>()
+            .init_resource::<DefaultLocomotion>()
+            .init_resource::<SimulationTick>()
+            .init_resource::<ColonyStats>()
+            .init_resource::<PheromoneDynamics>()
+            .init_resource::<SimulationControl>()
+            .add_event::<FoodDelivered>()
+            .add_event::<AntBorn>()
+            .add_event::<AntDied>()
+            .add_event::<AntStateChanged>()
+            .add_event::<ConfigChanged>()
+            .insert_resource(ObstacleGrid::new(
+                (self.arena.size[0] / OBSTACLE_TILE_SIZE) as u32,
+                (self.arena.size[1] / OBSTACLE_TILE_SIZE) as u32,
+                OBSTACLE_TILE_SIZE,
+            ))
+            .insert_resource(PheromoneField::new(
+                (self.arena.size[0] / PHEROMONE_CELL_SIZE) as u32,
+                (self.arena.size[1] / PHEROMONE_CELL_SIZE) as u32,
+                PHEROMONE_CELL_SIZE,
+                MAX_COLONIES * TrailType::ALL.len(),
+            ))
+            .insert_resource(SpatialIndex::<FoodSource>::new(SPATIAL_INDEX_CELL_SIZE))
+            .insert_resource(SpatialIndex::<Home>::new(SPATIAL_INDEX_CELL_SIZE))
+            .insert_resource(SpatialIndex::<Ant>::new(SPATIAL_INDEX_CELL_SIZE))
+            .insert_resource(rapier_configuration)
+            .insert_resource(IntegrationParameters {
+                dt: TIME_STEP,
                 ..Default::default()
             })
+            .insert_resource(self.arena.clone())
+            .insert_resource(ScenarioFile(self.scenario.clone()))
+            .insert_resource(SnapshotFile(self.snapshot.clone()))
+            .insert_resource(StartupSeed(self.seed))
+            .insert_resource(self.config_overrides.clone())
+            .insert_resource(ConfigDumpFile(self.dump_config.clone()))
+            .insert_resource(StatsFile(self.stats.clone()))
             .add_startup_system(setup.label("setup"))
-            .add_startup_system(map_generator_system.after("setup"))
-            .add_system(mouse_input_system)
-            .add_system(map_generator_system)
-            .add_system(set_texture_filters_to_nearest)
-            .add_system_set(
-                SystemSet::new()
-                    .with_run_criteria(FixedTimestep::step(TIME_STEP as f64))
-                    .with_system(obstacle_collision_system)
-                    .with_system(food_collision_system)
-                    .with_system(ant_movement_system)
-                    .with_system(ant_movement_system2)
-                    .with_system(trail_spawn_system)
-                    .with_system(trail_decay_system),
-            );
+            .add_startup_stage_after(
+                StartupStage::Startup,
+                AntsStartupStage::LoadScenario,
+                SystemStage::single_threaded(),
+            )
+            .add_startup_system_to_stage(
+                AntsStartupStage::LoadScenario,
+                load_startup_scenario_system
+                    .exclusive_system()
+                    .label("load_scenario"),
+            )
+            .add_startup_system_to_stage(
+                AntsStartupStage::LoadScenario,
+                load_startup_snapshot_system
+                    .exclusive_system()
+                    .label("load_snapshot")
+                    .after("load_scenario"),
+            )
+            .add_startup_system_to_stage(
+                AntsStartupStage::LoadScenario,
+                apply_startup_config_system
+                    .exclusive_system()
+                    .label("apply_startup_config")
+                    .after("load_snapshot"),
+            )
+            .add_startup_system_to_stage(
+                AntsStartupStage::LoadScenario,
+                dump_config_system
+                    .exclusive_system()
+                    .after("apply_startup_config"),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                config_change_system.label("config_changes"),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                map_generator_system
+                    .label("map_generator")
+                    .after("config_changes"),
+            )
+            .add_system(obstacle_collider_system)
+            .add_system(arena_collider_system)
+            .add_system_set(simulation_systems)
+            .add_system(stats_export_system);
+        add_sim_commands(app);
     }
 }
 
 #[derive(Component)]
-struct Ant {
-    carrying_food: bool,
-    target_speed: f32,
-    motor_force: f32,
-    grip_force: f32,
-    turning_torque: f32,
-    random_turning_torque: f32,
+pub struct Ant {
+    pub carrying_food: bool,
+    /// Food store units the food the ant carries is worth to its home.
+    pub carried_food: usize,
+    /// Remaining energy as a fraction of a full stomach. The ant dies when it runs out.
+    pub energy: f32,
+    /// Distance in world units within which the ant senses food and heads straight for it.
+    pub food_perception: f32,
+    /// Distance in world units within which the ant senses a home of its colony.
+    pub nest_perception: f32,
 }
 
-impl Default for Ant {
-    fn default() -> Ant {
+impl Ant {
+    /// A well fed ant with the perception of `profile`.
+    pub fn new(profile: &CasteProfile) -> Ant {
         Ant {
             carrying_food: false,
-            target_speed: 8.0,
-            motor_force: 4.0,
-            grip_force: 5.0,
-            turning_torque: 2.0,
-            random_turning_torque: 5.0,
+            carried_food: 0,
+            energy: 1.0,
+            food_perception: profile.food_perception,
+            nest_perception: profile.nest_perception,
         }
     }
 }
 
-#[derive(Component)]
-enum Collider {
-    Solid,
+/// Identity and parameters an ant is spawned with.
+#[derive(Bundle)]
+pub struct AntBundle {
+    pub id: AntId,
+    pub colony: ColonyId,
+    pub caste: Caste,
+    pub ant: Ant,
+}
+
+impl AntBundle {
+    /// A newborn ant of `caste`, with the caste's perception.
+    pub fn new(id: AntId, colony: ColonyId, caste: Caste, config: &Config) -> AntBundle {
+        AntBundle {
+            id,
+            colony,
+            caste,
+            ant: Ant::new(&caste.profile(config)),
+        }
+    }
+}
+
+/// Identifies an ant independently of its `Entity`, which changes when a world is restored.
+#[derive(
+    Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize,
+)]
+pub struct AntId(pub u64);
+
+/// The ants of `id_query` in `AntId` order. Systems whose outcome depends on the order ants are
+/// visited in go through this, as entities and archetypes are laid out differently in a world
+/// restored from a snapshot.
+fn ants_in_id_order(id_query: &Query<(Entity, &AntId)>) -> Vec<Entity> {
+    let mut ants: Vec<(AntId, Entity)> =
+        id_query.iter().map(|(entity, &id)| (id, entity)).collect();
+    ants.sort_unstable_by_key(|&(id, _)| id);
+    ants.into_iter().map(|(_, entity)| entity).collect()
 }
 
-#[derive(Component, Copy, Clone)]
-enum Icon {
-    SpawnObstacle,
-    SpawnFood,
-    SpawnFoodCluster,
-    SpawnHome,
+#[derive(Default, Serialize, Deserialize)]
+pub struct NextAntId(pub u64);
+
+impl NextAntId {
+    pub fn next(&mut self) -> AntId {
+        let id = AntId(self.0);
+        self.0 += 1;
+        id
+    }
 }
 
+/// The colony an ant or home belongs to. Ants only follow their own colony's trails and only
+/// deliver food to their own colony's homes.
+#[derive(
+    Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize,
+)]
+pub struct ColonyId(pub u32);
+
+/// Number of simulation steps run so far, counting the one in progress.
+#[derive(Default)]
+pub struct SimulationTick(pub usize);
+
+/// A static rapier collider covering a rectangle of `ObstacleGrid` tiles.
 #[derive(Component)]
-struct Obstacle {}
+pub struct ObstacleCollider;
 
+/// The static rapier collider of a solid arena boundary.
 #[derive(Component)]
-struct Food {}
+pub struct ArenaCollider;
 
-enum TrailType {
+#[derive(Clone, Copy, PartialEq)]
+pub enum TrailType {
     Gathering,
     GotFood,
 }
 
-#[derive(Component)]
-struct Trail {
-    trail_type: TrailType,
-    strength: f32,
+impl TrailType {
+    pub const ALL: [TrailType; 2] = [TrailType::Gathering, TrailType::GotFood];
+
+    /// Index of `colony`'s channel for this trail type in the `PheromoneField`.
+    pub fn channel(&self, colony: ColonyId) -> usize {
+        colony.0 as usize * TrailType::ALL.len() + *self as usize
+    }
 }
 
-#[derive(Component)]
-struct Home {}
+impl Ant {
+    /// Hungry ants head home to eat, following the same trail as ants carrying food.
+    pub fn is_hungry(&self, config: &Config) -> bool {
+        self.energy < config.get(&ANT_HUNGER)
+    }
+}
 
-#[derive(Default)]
-struct MapGenerator {
-    octaves: usize,
-    frequency: f64,
-    lacunarity: f64,
-    persistence: f64,
-    threshold: f64,
+#[derive(Component, Default)]
+pub struct Home {
+    /// Food delivered and not yet eaten or turned into new ants.
+    pub food_store: usize,
 }
 
-#[derive(Default)]
-struct EditorInput {
-    selected_icon: Option<Icon>,
+/// Sent when `ant` of `caste` drops the food it carries, worth `amount` food store units, at
+/// `home` of `colony`.
+pub struct FoodDelivered {
+    pub ant: Entity,
+    pub home: Entity,
+    pub colony: ColonyId,
+    pub caste: Caste,
+    pub amount: usize,
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut config: ResMut<Config>) {
-    config.entries.insert("ant.speed", ConfigValue::Float(40.0));
-    config.entries.insert(
-        "ant.wandering",
-        ConfigValue::Float(0.02 * std::f32::consts::PI),
-    );
-    config.entries.insert("map.octaves", ConfigValue::Int(4));
-    config
-        .entries
-        .insert("map.frequency", ConfigValue::Float(0.005));
-    config
-        .entries
-        .insert("map.lacunarity", ConfigValue::Float(1.0));
-    config
-        .entries
-        .insert("map.persistence", ConfigValue::Float(0.2));
-    config
-        .entries
-        .insert("map.threshold", ConfigValue::Float(0.4));
-    config
-        .entries
-        .insert("trail.spawn_period", ConfigValue::Float(0.25));
-    config
-        .entries
-        .insert("trail.initial_strength", ConfigValue::Float(1.0));
-    config
-        .entries
-        .insert("trail.decay_rate", ConfigValue::Float(0.999));
-    config.entries.insert(
-        "sensor_angle",
-        ConfigValue::Float(std::f32::consts::PI / 4.0),
-    );
-    config
-        .entries
-        .insert("sensor_distance", ConfigValue::Float(20.0));
-    config
-        .entries
-        .insert("sensor_radius", ConfigValue::Float(7.66));
-    config
-        .entries
-        .insert("sensor_turning_coefficient", ConfigValue::Float(1.0));
-    let mut camera = OrthographicCameraBundle::new_2d();
-    camera.orthographic_projection.scale = 1.0;
-    commands.spawn_bundle(camera);
-
-    // spawn ants
-    for _ in 0..1 {
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("ant.png"),
-                transform: Transform {
-                    scale: Vec3::new(ANT_SIZE, ANT_SIZE, 0.0),
-                    translation: Vec3::new(0.0, -50.0, 0.0),
-                    rotation: Quat::from_rotation_z(random::<f32>() * 2.0 * std::f32::consts::PI),
-                    ..Default::default()
-                },
-                sprite: Sprite {
-                    custom_size: Some(Vec2::new(1.0, 1.0)),
-                    ..Default::default()
-                },
-                ..Default::default()
-            })
-            .insert(Ant {
-                ..Default::default()
-            });
+/// Sent when a home of `colony` raises the new ant `ant` of `caste`.
+pub struct AntBorn {
+    pub ant: Entity,
+    pub colony: ColonyId,
+    pub caste: Caste,
+}
+
+/// Sent when `ant` of `colony` and `caste` starves.
+pub struct AntDied {
+    pub ant: Entity,
+    pub colony: ColonyId,
+    pub caste: Caste,
+}
+
+/// Noise parameters the current obstacles were generated with.
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapGenerator {
+    pub octaves: usize,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub persistence: f64,
+    pub threshold: f64,
+    /// Absent from worlds saved before maps were seeded, which used seed 0.
+    #[serde(default)]
+    pub seed: i32,
+}
+
+impl MapGenerator {
+    /// The parameters set by the `map.*` config entries.
+    pub fn from_config(config: &Config) -> MapGenerator {
+        MapGenerator {
+            octaves: config.get(&MAP_OCTAVES),
+            frequency: config.get(&MAP_FREQUENCY) as f64,
+            lacunarity: config.get(&MAP_LACUNARITY) as f64,
+            persistence: config.get(&MAP_PERSISTENCE) as f64,
+            threshold: config.get(&MAP_THRESHOLD) as f64,
+            seed: config.get(&MAP_SEED),
+        }
     }
 
-    /* Create a parallel rapier ant */
-    let rigid_body = RigidBodyBundle {
-        position: Vec2::new(0.0, 1.0).into(),
-        damping: RigidBodyDamping {
-            linear_damping: 2.0,
-            angular_damping: 5.0,
+    /// Replaces all obstacles with freshly generated ones.
+    pub fn generate(&self, obstacle_grid: &mut ObstacleGrid, arena: &Arena) {
+        obstacle_grid.clear();
+        for tile_pos in generate_map_tiles(self, obstacle_grid, arena) {
+            obstacle_grid.set_obstacle(tile_pos, true);
         }
-        .into(),
-        ..Default::default()
-    };
-    let collider = ColliderBundle {
-        // TODO: debug render a capsule?
-        shape: ColliderShape::capsule(Point2::new(0.25, 0.0), Point2::new(-0.25, 0.0), 0.25).into(),
-        // shape: ColliderShape::cuboid(0.5, 0.25).into(),
-        material: ColliderMaterial {
-            restitution: 0.7,
-            friction: 0.0,
-            ..Default::default()
+    }
+}
+
+/// Lets the simulation be paused, single stepped and sped up while it runs.
+pub struct SimulationControl {
+    pub paused: bool,
+    /// Ticks still to run regardless of `paused`, one per check of the run criteria. Steps past
+    /// `MAX_TICKS_PER_FRAME` in a frame are carried over to the next.
+    pub pending_steps: usize,
+    /// Simulated seconds per wall-clock second. Only applies with `TickMode::FixedTimestep`.
+    pub speed: f32,
+    /// Simulated seconds not yet run as ticks.
+    accumulator: f64,
+    /// Whether the run criteria is being checked again after letting a tick run this frame.
+    looping: bool,
+    /// Ticks run in the current frame.
+    pub ticks_this_frame: usize,
+}
+
+impl Default for SimulationControl {
+    fn default() -> SimulationControl {
+        SimulationControl {
+            paused: false,
+            pending_steps: 0,
+            speed: 1.0,
+            accumulator: 0.0,
+            looping: false,
+            ticks_this_frame: 0,
         }
-        .into(),
-        ..Default::default()
-    };
-    commands
-        .spawn_bundle(rigid_body)
-        .insert_bundle(collider)
-        .insert_bundle(SpriteBundle {
-            texture: asset_server.load("ant.png"),
-            transform: Transform {
-                scale: Vec3::new(ANT_SIZE, ANT_SIZE, 0.0),
-                ..Default::default()
-            },
-            sprite: Sprite {
-                custom_size: Some(Vec2::new(1.0, 1.0)),
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(ColliderPositionSync::Discrete)
-        .insert(ColliderDebugRender::with_id(2))
-        .insert(Ant {
-            ..Default::default()
-        })
-        .id();
+    }
+}
 
-    // bottom wall
-    commands
-        .spawn_bundle(ColliderBundle {
-            shape: ColliderShape::cuboid(180.0, 0.1).into(),
-            material: ColliderMaterial {
-                friction: 0.0,
-                ..Default::default()
-            }
-            .into(),
-            position: (Vec2::new(0.0, -60.0)).into(),
-            ..Default::default()
-        })
-        .insert(ColliderPositionSync::Discrete)
-        .insert(ColliderDebugRender::with_id(2));
-    // top wall
-    commands
-        .spawn_bundle(ColliderBundle {
-            shape: ColliderShape::cuboid(180.0, 0.1).into(),
-            material: ColliderMaterial {
-                friction: 0.0,
-                ..Default::default()
-            }
-            .into(),
-            position: (Vec2::new(0.0, 60.0)).into(),
-            ..Default::default()
-        })
-        .insert(ColliderPositionSync::Discrete)
-        .insert(ColliderDebugRender::with_id(2));
-    // left wall
-    commands
-        .spawn_bundle(ColliderBundle {
-            shape: ColliderShape::cuboid(0.1, 120.0).into(),
-            material: ColliderMaterial {
-                friction: 0.0,
-                ..Default::default()
-            }
-            .into(),
-            position: (Vec2::new(-90.0, 0.0)).into(),
-            ..Default::default()
-        })
-        .insert(ColliderPositionSync::Discrete)
-        .insert(ColliderDebugRender::with_id(2));
-    // right wall
-    commands
-        .spawn_bundle(ColliderBundle {
-            shape: ColliderShape::cuboid(0.1, 120.0).into(),
-            material: ColliderMaterial {
-                friction: 0.0,
-                ..Default::default()
+impl SimulationControl {
+    /// Runs one queued step, if any.
+    fn take_step(&mut self) -> bool {
+        if self.pending_steps == 0 {
+            return false;
+        }
+        self.pending_steps -= 1;
+        true
+    }
+}
+
+/// Scenario to load at startup instead of spawning the default ants, homes and food.
+pub struct ScenarioFile(pub Option<PathBuf>);
+
+/// Snapshot to restore at startup, after any scenario, to resume a checkpointed run.
+pub struct SnapshotFile(pub Option<PathBuf>);
+
+/// Seed given on the command line, applied over the seed of whatever world was loaded.
+pub struct StartupSeed(pub Option<i32>);
+
+/// Config given on the command line, applied over the defaults and again over whatever world was
+/// loaded. Validated by `resolve_config_overrides` once every plugin has registered its models,
+/// so keys of added models can be set and a bad value is reported before the app runs.
+#[derive(Clone, Default)]
+pub struct ConfigOverrides {
+    file: Option<PathBuf>,
+    sets: Vec<(String, String)>,
+    values: Option<Vec<(&'static str, ConfigValue)>>,
+}
+
+impl ConfigOverrides {
+    /// Overrides from the TOML `file`, then any `ANTS_SIM_*` environment variables, then `sets`
+    /// of keys and value texts, parsed like the console's `config_set`.
+    pub fn new(file: Option<PathBuf>, sets: Vec<(String, String)>) -> ConfigOverrides {
+        ConfigOverrides {
+            file,
+            sets,
+            values: None,
+        }
+    }
+
+    /// Applies the layers over the registered defaults of the simulation and the models of
+    /// `pheromone_dynamics`, and keeps the resulting value of every key they set.
+    pub fn resolve(
+        &mut self,
+        pheromone_dynamics: &PheromoneDynamics,
+    ) -> Result<(), ConfigFileError> {
+        let mut config = Config::default();
+        register_config(&mut config, pheromone_dynamics);
+        let keys = config.apply_layers(self.file.as_deref(), &self.sets)?;
+        self.values = Some(
+            keys.into_iter()
+                .map(|key| (key, config.entry(key).unwrap().value().clone()))
+                .collect(),
+        );
+        Ok(())
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        let values = self
+            .values
+            .as_ref()
+            .expect("config overrides applied before they were resolved");
+        for (key, value) in values.iter() {
+            if let Err(e) = config.set_value(key, value.clone()) {
+                panic!("config override no longer applies: {}", e);
             }
-            .into(),
-            position: (Vec2::new(90.0, 0.0)).into(),
-            ..Default::default()
-        })
-        .insert(ColliderPositionSync::Discrete)
-        .insert(ColliderDebugRender::with_id(2));
+        }
+    }
+}
 
-    spawn_home(Vec3::new(0.0, -50.0, 0.0), &mut commands);
+/// Resolves the `ConfigOverrides` of `world` against its `PheromoneDynamics`. Call once every
+/// plugin is added; startup resolves them itself otherwise, failing on a bad value.
+pub fn resolve_config_overrides(world: &mut World) -> Result<(), ConfigFileError> {
+    world.resource_scope(|world, mut config_overrides: Mut<ConfigOverrides>| {
+        config_overrides.resolve(world.get_resource::<PheromoneDynamics>().unwrap())
+    })
+}
 
-    spawn_food_cluster(Vec3::new(-218.0, -84.0, 0.0), &mut commands);
-    spawn_food_cluster(Vec3::new(22.0, 157.0, 0.0), &mut commands);
-    spawn_food_cluster(Vec3::new(235.0, 1.0, 0.0), &mut commands);
+/// File to dump the effective config to at the end of startup.
+pub struct ConfigDumpFile(pub Option<PathBuf>);
 
-    // Add walls
-    let wall_color = Color::rgb(0.8, 0.8, 0.8);
-    let wall_thickness = 10.0;
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+enum AntsStartupStage {
+    LoadScenario,
+}
 
-    // left
-    commands
-        .spawn_bundle(SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new(-(BOUNDS_X + wall_thickness) / 2.0, 0.0, 0.0),
-                scale: Vec3::new(wall_thickness, BOUNDS_Y + wall_thickness * 2.0, 1.0),
-                ..Default::default()
-            },
-            sprite: Sprite {
-                color: wall_color,
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(Collider::Solid);
-    // right
-    commands
-        .spawn_bundle(SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new((BOUNDS_X + wall_thickness) / 2.0, 0.0, 0.0),
-                scale: Vec3::new(wall_thickness, BOUNDS_Y + wall_thickness * 2.0, 1.0),
-                ..Default::default()
-            },
-            sprite: Sprite {
-                color: wall_color,
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(Collider::Solid);
-    // bottom
-    commands
-        .spawn_bundle(SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new(0.0, -(BOUNDS_Y + wall_thickness) / 2.0, 0.0),
-                scale: Vec3::new(BOUNDS_X + wall_thickness * 2.0, wall_thickness, 1.0),
-                ..Default::default()
-            },
-            sprite: Sprite {
-                color: wall_color,
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(Collider::Solid);
-    // top
-    commands
-        .spawn_bundle(SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new(0.0, (BOUNDS_Y + wall_thickness) / 2.0, 0.0),
-                scale: Vec3::new(BOUNDS_X + wall_thickness * 2.0, wall_thickness, 1.0),
-                ..Default::default()
-            },
-            sprite: Sprite {
-                color: wall_color,
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(Collider::Solid);
+/// Registers every config entry of the simulation, including those of the models of
+/// `pheromone_dynamics`.
+pub fn register_config(config: &mut Config, pheromone_dynamics: &PheromoneDynamics) {
+    config.register(&SIM_SEED);
+    config.register(&ANT_SPEED);
+    config.register(&ANT_WANDERING);
+    config.register(&MAP_OCTAVES);
+    config.register(&MAP_FREQUENCY);
+    config.register(&MAP_LACUNARITY);
+    config.register(&MAP_PERSISTENCE);
+    config.register(&MAP_THRESHOLD);
+    config.register(&MAP_SEED);
+    config.register(&TRAIL_SPAWN_PERIOD);
+    config.register(&TRAIL_INITIAL_STRENGTH);
+    config.register(&SENSOR_ANGLE);
+    config.register(&SENSOR_DISTANCE);
+    config.register(&SENSOR_RADIUS);
+    config.register(&SENSOR_TURNING_COEFFICIENT);
+    config.register(&ANT_ENERGY_PER_SECOND);
+    config.register(&ANT_ENERGY_PER_DISTANCE);
+    config.register(&ANT_HUNGER);
+    config.register(&HOME_ENERGY_PER_FOOD);
+    config.register(&HOME_FOOD_PER_ANT);
+    config.register(&HOME_FOOD_RESERVE);
+    pheromone_dynamics.register_config(config);
+    vision::register_config(config);
+    behavior::register_config(config);
+    caste::register_config(config);
+    food::register_config(config);
+    stats::register_config(config);
+}
 
-    // icons
-    commands
-        .spawn_bundle(SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new(-BOUNDS_X / 2.0 - 50.0, BOUNDS_Y / 2.0 - 15.0, 0.0),
-                scale: Vec3::new(40.0, 40.0, 1.0),
-                ..Default::default()
-            },
-            sprite: Sprite {
-                color: OBSTACLE_COLOR,
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(Icon::SpawnObstacle);
-    commands
-        .spawn_bundle(SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new(-BOUNDS_X / 2.0 - 50.0, BOUNDS_Y / 2.0 - 60.0, 0.0),
-                scale: Vec3::new(40.0, 40.0, 1.0),
-                ..Default::default()
-            },
-            sprite: Sprite {
-                color: FOOD_COLOR,
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(Icon::SpawnFood);
-    commands
-        .spawn_bundle(SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new(-BOUNDS_X / 2.0 - 50.0, BOUNDS_Y / 2.0 - 105.0, 0.0),
-                scale: Vec3::new(40.0, 40.0, 1.0),
-                ..Default::default()
-            },
-            sprite: Sprite {
-                color: FOOD_COLOR,
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(Icon::SpawnFoodCluster);
-    commands
-        .spawn_bundle(SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new(-BOUNDS_X / 2.0 - 50.0, BOUNDS_Y / 2.0 - 150.0, 0.0),
-                scale: Vec3::new(40.0, 40.0, 1.0),
-                ..Default::default()
+fn setup(
+    mut commands: Commands,
+    mut config: ResMut<Config>,
+    mut next_ant_id: ResMut<NextAntId>,
+    mut next_food_id: ResMut<NextFoodId>,
+    scenario_file: Res<ScenarioFile>,
+    snapshot_file: Res<SnapshotFile>,
+    startup_seed: Res<StartupSeed>,
+    mut config_overrides: ResMut<ConfigOverrides>,
+    pheromone_dynamics: Res<PheromoneDynamics>,
+) {
+    register_config(&mut config, &pheromone_dynamics);
+    if config_overrides.values.is_none() {
+        if let Err(e) = config_overrides.resolve(&pheromone_dynamics) {
+            panic!("failed to apply config overrides: {}", e);
+        }
+    }
+    apply_config_overrides(&mut config, &config_overrides, startup_seed.0);
+    let mut sim_rng = SimRng::new(config.get(&SIM_SEED));
+
+    // a scenario or snapshot file provides its own ants, homes and food
+    if scenario_file.0.is_none() && snapshot_file.0.is_none() {
+        // spawn ants
+        for _ in 0..1 {
+            spawn_ant(
+                AntBundle::new(next_ant_id.next(), ColonyId(0), Caste::Forager, &config),
+                Vec3::new(0.0, -50.0, 0.0),
+                sim_rng.gen::<f32>() * 2.0 * std::f32::consts::PI,
+                &mut commands,
+            );
+        }
+
+        /* Create a parallel rapier ant */
+        spawn_rigid_body_ant(
+            AntBundle::new(next_ant_id.next(), ColonyId(0), Caste::Forager, &config),
+            Vec2::new(0.0, 1.0),
+            0.0,
+            &config,
+            &mut commands,
+        );
+
+        spawn_home(Vec3::new(0.0, -50.0, 0.0), ColonyId(0), &mut commands);
+
+        spawn_food_source(
+            Vec2::new(-218.0, -84.0),
+            next_food_id.next(),
+            FoodSource::new(FoodType::Seed, &config),
+            &mut commands,
+        );
+        // a fruit bush that grows back, and an insect swarm that moves on once eaten
+        spawn_food_source(
+            Vec2::new(22.0, 157.0),
+            next_food_id.next(),
+            FoodSource {
+                amount: 20,
+                capacity: 20,
+                regrowth: 0.5,
+                ..FoodSource::new(FoodType::Fruit, &config)
             },
-            sprite: Sprite {
-                color: HOME_COLOR,
-                ..Default::default()
+            &mut commands,
+        );
+        spawn_food_source(
+            Vec2::new(235.0, 1.0),
+            next_food_id.next(),
+            FoodSource {
+                amount: 10,
+                capacity: 10,
+                respawn: true,
+                ..FoodSource::new(FoodType::Insect, &config)
             },
-            ..Default::default()
-        })
-        .insert(Icon::SpawnHome);
-
-    // // spawn some test trails
-    // for _ in 0..1 {
-    //     let foo = Vec3::new(9.0, 6.0, 0.0).normalize() * 25.0 * random::<f32>();
-    //     let mut pos = Vec3::new(-450.0, -300.0, 0.0) + foo;
-    //     while pos.x <= 450.0 && pos.y <= 300.0 {
-    //         spawn_trail(
-    //             pos,
-    //             &mut commands,
-    //             TrailType::Gathering,
-    //             config.entries["trail.initial_strength"].f32(),
-    //         );
-    //         pos += Vec3::new(9.0, 6.0, 0.0).normalize() * 10.0;
-    //     }
-    // }
-    // for _ in 0..5 {
-    //     let step = Vec3::new(9.0, -6.0, 0.0).normalize() * 10.0;
-    //     let foo = step * random::<f32>();
-    //     let mut pos = Vec3::new(-450.0, 300.0, 0.0) + foo;
-    //     while pos.x <= 450.0 && pos.y >= -300.0 {
-    //         spawn_trail(
-    //             pos,
-    //             &mut commands,
-    //             TrailType::Gathering,
-    //             config.entries["trail.initial_strength"].f32(),
-    //         );
-    //         pos += step;
-    //     }
-    // }
-}
+            &mut commands,
+        );
+    }
 
-fn window_to_world(position: Vec2, window: &Window, camera: &Transform) -> Vec3 {
-    let norm = Vec3::new(
-        position.x - window.width() / 2.,
-        position.y - window.height() / 2.,
-        0.,
-    );
-    let mut pos = *camera * norm;
-    pos.z = 0.0;
-    return pos;
+    commands.insert_resource(sim_rng);
 }
 
-fn to_tile_center(world_pos: Vec3, grid_size: f32) -> Vec3 {
-    Vec3::new(
-        (world_pos.x / grid_size).floor() * grid_size + grid_size / 2.0,
-        (world_pos.y / grid_size).floor() * grid_size + grid_size / 2.0,
-        0.0,
-    )
+fn load_startup_scenario_system(world: &mut World) {
+    let path = match &world.get_resource::<ScenarioFile>().unwrap().0 {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    if let Err(e) = load_scenario(world, &path) {
+        panic!("failed to load scenario {}: {}", path.display(), e);
+    }
 }
 
-fn pos_in_transform(pos: &Vec3, transform: &Transform) -> bool {
-    pos.x > transform.translation.x - transform.scale.x / 2.0
-        && pos.x < transform.translation.x + transform.scale.x / 2.0
-        && pos.y > transform.translation.y - transform.scale.y / 2.0
-        && pos.y < transform.translation.y + transform.scale.y / 2.0
+fn load_startup_snapshot_system(world: &mut World) {
+    let path = match &world.get_resource::<SnapshotFile>().unwrap().0 {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    if let Err(e) = load_snapshot(world, &path) {
+        panic!("failed to load snapshot {}: {}", path.display(), e);
+    }
 }
 
-fn pos_in_bounds(pos: &Vec3) -> bool {
-    pos.x < BOUNDS_X / 2.0
-        && pos.x > -BOUNDS_X / 2.0
-        && pos.y < BOUNDS_Y / 2.0
-        && pos.y > -BOUNDS_Y / 2.0
+/// Applies the command line config, then the command line seed.
+fn apply_config_overrides(config: &mut Config, overrides: &ConfigOverrides, seed: Option<i32>) {
+    overrides.apply(config);
+    if let Some(seed) = seed {
+        config.set(&SIM_SEED, seed).unwrap();
+    }
 }
 
-fn mouse_input_system(
-    mut commands: Commands,
-    buttons: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
-    mut editor_input: ResMut<EditorInput>,
-    transform_query: Query<&Transform, With<Camera>>,
-    collider_query: Query<(Entity, &Collider, &Transform)>,
-    home_query: Query<(Entity, &Home, &Transform)>,
-    food_query: Query<(Entity, &Food, &Transform)>,
-    icon_query: Query<(&Icon, &Transform)>,
-    map_transform_query: Query<&Transform, With<Map>>,
-    mut map_query: MapQuery,
+/// Reapplies the command line config and seed after any loaded world replaced them.
+fn apply_startup_config_system(
+    startup_seed: Res<StartupSeed>,
+    config_overrides: Res<ConfigOverrides>,
+    mut config: ResMut<Config>,
+    mut sim_rng: ResMut<SimRng>,
 ) {
-    let window = windows.get_primary().unwrap();
-    if let Some(cursor_pos) = window.cursor_position() {
-        let world_cursor_pos = window_to_world(cursor_pos, window, transform_query.single());
-
-        if buttons.just_pressed(MouseButton::Left) {
-            for (icon, transform) in icon_query.iter() {
-                if pos_in_transform(&world_cursor_pos, &transform) {
-                    editor_input.selected_icon = Some(*icon);
-                }
-            }
-        }
-        if pos_in_bounds(&world_cursor_pos) {
-            match editor_input.selected_icon {
-                Some(Icon::SpawnObstacle) => {
-                    if buttons.pressed(MouseButton::Left) {
-                        if !collider_query.iter().any(|(_, _, transform)| {
-                            pos_in_transform(&world_cursor_pos, &transform)
-                        }) {
-                            let tile_pos = tile_pos_from_world_pos(
-                                &world_cursor_pos,
-                                &mut map_query,
-                                map_transform_query.single(),
-                                0,
-                                0,
-                            );
-                            spawn_obstacle(tile_pos, &mut commands, &mut map_query);
-                        }
-                    } else if buttons.pressed(MouseButton::Right) {
-                        // TODO: fix obstacle removal
-                        for (entity, _collider, transform) in collider_query.iter() {
-                            if pos_in_transform(&world_cursor_pos, &transform) {
-                                commands.entity(entity).despawn();
-                            }
-                        }
-                    }
-                }
-                Some(Icon::SpawnFood) => {
-                    if buttons.pressed(MouseButton::Left) {
-                        if !food_query.iter().any(|(_, _, transform)| {
-                            pos_in_transform(&world_cursor_pos, &transform)
-                        }) {
-                            spawn_food(world_cursor_pos.x, world_cursor_pos.y, &mut commands);
-                        }
-                    } else if buttons.pressed(MouseButton::Right) {
-                        for (entity, _food, transform) in food_query.iter() {
-                            if pos_in_transform(&world_cursor_pos, &transform) {
-                                commands.entity(entity).despawn();
-                            }
-                        }
-                    }
-                }
-                Some(Icon::SpawnFoodCluster) => {
-                    if buttons.just_pressed(MouseButton::Left) {
-                        if !food_query.iter().any(|(_, _, transform)| {
-                            pos_in_transform(&world_cursor_pos, &transform)
-                        }) {
-                            spawn_food_cluster(world_cursor_pos, &mut commands);
-                        }
-                    } else if buttons.pressed(MouseButton::Right) {
-                        for (entity, _food, transform) in food_query.iter() {
-                            if pos_in_transform(&world_cursor_pos, &transform) {
-                                commands.entity(entity).despawn();
-                            }
-                        }
-                    }
-                }
-                Some(Icon::SpawnHome) => {
-                    if buttons.pressed(MouseButton::Left) {
-                        let tile_center = to_tile_center(world_cursor_pos, OBSTACLE_TILE_SIZE);
-                        if !home_query.iter().any(|(_, _, transform)| {
-                            pos_in_transform(&world_cursor_pos, &transform)
-                        }) {
-                            spawn_home(tile_center, &mut commands);
-                        }
-                    } else if buttons.pressed(MouseButton::Right) {
-                        for (entity, _home, transform) in home_query.iter() {
-                            if pos_in_transform(&world_cursor_pos, &transform) {
-                                commands.entity(entity).despawn();
-                            }
-                        }
-                    }
-                }
-                None => (),
-            }
+    apply_config_overrides(&mut config, &config_overrides, startup_seed.0);
+    let seed = config.get(&SIM_SEED);
+    // a command line seed restarts the random streams even of a snapshot with the same seed
+    if startup_seed.0.is_some() || sim_rng.seed() != seed {
+        *sim_rng = SimRng::new(seed);
+    }
+}
+
+fn dump_config_system(config: Res<Config>, dump_file: Res<ConfigDumpFile>) {
+    if let Some(path) = &dump_file.0 {
+        if let Err(e) = config.save_file(path) {
+            println!("failed to save config {}: {}", path.display(), e);
         }
     }
 }
 
+/// Regenerates the obstacles when a `map.*` entry changes to parameters other than the ones the
+/// current obstacles were generated or loaded with.
 fn map_generator_system(
-    mut commands: Commands,
-    config: ResMut<Config>,
-    asset_server: Res<AssetServer>,
+    mut config_changes: EventReader<ConfigChanged>,
+    config: Res<Config>,
+    arena: Res<Arena>,
     mut map_generator: ResMut<MapGenerator>,
-    mut map_query: MapQuery,
+    mut obstacle_grid: ResMut<ObstacleGrid>,
 ) {
-    if map_generator.octaves == config.entries["map.octaves"].usize()
-        && map_generator.frequency == config.entries["map.frequency"].f64()
-        && map_generator.lacunarity == config.entries["map.lacunarity"].f64()
-        && map_generator.persistence == config.entries["map.persistence"].f64()
-        && map_generator.threshold == config.entries["map.threshold"].f64()
+    if !config_changes
+        .iter()
+        .any(|change| change.key.starts_with("map."))
     {
         return;
     }
-    map_generator.octaves = config.entries["map.octaves"].usize();
-    map_generator.frequency = config.entries["map.frequency"].f64();
-    map_generator.lacunarity = config.entries["map.lacunarity"].f64();
-    map_generator.persistence = config.entries["map.persistence"].f64();
-    map_generator.threshold = config.entries["map.threshold"].f64();
-
-    if map_query.get_layer(0, 0).is_none() {
-        let texture_handle = asset_server.load("tiles_10.png");
-
-        // Create map entity and component:
-        let map_entity = commands.spawn().id();
-        let mut map = Map::new(0u16, map_entity);
-
-        let layer_settings = LayerSettings::new(
-            MapSize((BOUNDS_X / 100.0) as u32, (BOUNDS_Y / 100.0) as u32),
-            ChunkSize(10, 10),
-            TileSize(10.0, 10.0),
-            TextureSize(60.0, 10.0),
-        );
-
-        // Creates a new layer builder with a layer entity.
-        let (mut layer_builder, _) =
-            LayerBuilder::<TileBundle>::new(&mut commands, layer_settings, 0u16, 0u16);
-
-        for tile_bundle in generate_map_tiles(map_generator) {
-            let _ = layer_builder.set_tile(tile_bundle.position, tile_bundle);
-        }
-
-        // Builds the layer.
-        // Note: Once this is called you can no longer edit the layer until a hard sync in bevy.
-        let layer_entity = map_query.build_layer(&mut commands, layer_builder, texture_handle);
-
-        // Required to keep track of layers for a map internally.
-        map.add_layer(&mut commands, 0u16, layer_entity);
+    let parameters = MapGenerator::from_config(&config);
+    if *map_generator == parameters {
+        return;
+    }
+    *map_generator = parameters;
+    map_generator.generate(&mut obstacle_grid, &arena);
+}
 
-        // Spawn Map
-        // Required in order to use map_query to retrieve layers/tiles.
+/// Rebuilds the rapier colliders of the obstacle tiles whenever the `ObstacleGrid` changes, so
+/// rigid body ants collide with the same obstacles kinematic ants bounce off.
+fn obstacle_collider_system(
+    mut commands: Commands,
+    obstacle_grid: Res<ObstacleGrid>,
+    rapier_configuration: Res<RapierConfiguration>,
+    collider_query: Query<Entity, With<ObstacleCollider>>,
+) {
+    if !obstacle_grid.is_changed() {
+        return;
+    }
+    for entity in collider_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    let scale = obstacle_grid.tile_size / rapier_configuration.scale;
+    for (start, size) in obstacle_grid.merged_rects() {
+        let half_extents = size.as_vec2() * scale / 2.0;
+        let center = obstacle_grid.origin / rapier_configuration.scale
+            + start.as_vec2() * scale
+            + half_extents;
         commands
-            .entity(map_entity)
-            .insert(map)
-            .insert(Transform::from_xyz(
-                -layer_settings.get_pixel_center().x,
-                -layer_settings.get_pixel_center().y,
-                0.0,
-            ))
-            .insert(GlobalTransform::default());
-    } else {
-        despawn_layer_tiles_and_notify_chunks(&mut commands, &mut map_query, 0, 0);
-        // Generate a new set of obstacles
-        for tile_bundle in generate_map_tiles(map_generator) {
-            let _result = map_query.set_tile(
-                &mut commands,
-                tile_bundle.position,
-                tile_bundle.tile,
-                0u16,
-                0u16,
-            );
-            map_query.notify_chunk_for_tile(tile_bundle.position, 0u16, 0u16);
-        }
+            .spawn_bundle(ColliderBundle {
+                shape: ColliderShape::cuboid(half_extents.x, half_extents.y).into(),
+                material: ColliderMaterial {
+                    friction: 0.0,
+                    ..Default::default()
+                }
+                .into(),
+                position: center.into(),
+                ..Default::default()
+            })
+            .insert(ColliderPositionSync::Discrete)
+            .insert(ObstacleCollider);
+    }
+}
+
+/// Rebuilds the rapier collider of the arena boundary whenever the `Arena` changes. Only solid
+/// boundaries have one.
+fn arena_collider_system(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    rapier_configuration: Res<RapierConfiguration>,
+    collider_query: Query<Entity, With<ArenaCollider>>,
+) {
+    if !arena.is_changed() {
+        return;
+    }
+    for entity in collider_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    if arena.boundary != Boundary::Solid {
+        return;
     }
+    let mut outline = arena.outline();
+    outline.push(outline[0]);
+    let vertices = outline
+        .iter()
+        .map(|vertex| {
+            let vertex = *vertex / rapier_configuration.scale;
+            Point2::new(vertex.x, vertex.y)
+        })
+        .collect();
+    commands
+        .spawn_bundle(ColliderBundle {
+            shape: ColliderShape::polyline(vertices, None).into(),
+            material: ColliderMaterial {
+                friction: 0.0,
+                ..Default::default()
+            }
+            .into(),
+            ..Default::default()
+        })
+        .insert(ColliderPositionSync::Discrete)
+        .insert(ArenaCollider);
 }
 
-fn generate_map_tiles(map_generator: ResMut<MapGenerator>) -> Vec<TileBundle> {
-    let mut tile_bundles = Vec::new();
+/// Returns the tiles inside the arena whose noise value passes the generator's threshold.
+fn generate_map_tiles(
+    map_generator: &MapGenerator,
+    obstacle_grid: &ObstacleGrid,
+    arena: &Arena,
+) -> Vec<UVec2> {
+    let mut tile_positions = Vec::new();
     let noise = HybridMulti::new()
         .set_octaves(map_generator.octaves)
         .set_frequency(map_generator.frequency)
         .set_lacunarity(map_generator.lacunarity)
-        .set_persistence(map_generator.persistence);
-    let num_tiles_x = (BOUNDS_X / OBSTACLE_TILE_SIZE) as i32;
-    let num_tiles_y = (BOUNDS_Y / OBSTACLE_TILE_SIZE) as i32;
-    for i in 0..num_tiles_x {
-        for j in 0..num_tiles_y {
-            let ox = OBSTACLE_TILE_SIZE * ((i - num_tiles_x / 2) as f32) + OBSTACLE_TILE_SIZE / 2.0;
-            let oy = OBSTACLE_TILE_SIZE * ((num_tiles_y / 2 - j) as f32) - OBSTACLE_TILE_SIZE / 2.0;
-            if noise.get([ox as f64, oy as f64]) < map_generator.threshold {
+        .set_persistence(map_generator.persistence)
+        .set_seed(map_generator.seed as u32);
+    for i in 0..obstacle_grid.width {
+        for j in 0..obstacle_grid.height {
+            let tile_pos = UVec2::new(i, j);
+            let center = obstacle_grid.world_pos_from_tile_pos(tile_pos);
+            if !arena.contains(center.truncate())
+                || noise.get([center.x as f64, center.y as f64]) < map_generator.threshold
+            {
                 continue;
             }
-            tile_bundles.push(TileBundle {
-                position: TilePos(i as u32, (num_tiles_y - j) as u32),
-                tile: Tile {
-                    texture_index: 0,
-                    ..Default::default()
-                },
-                ..Default::default()
-            });
+            tile_positions.push(tile_pos);
         }
     }
-    tile_bundles
+    tile_positions
 }
 
-fn spawn_obstacle(tile_pos: TilePos, commands: &mut Commands, map_query: &mut MapQuery) {
-    let _result = map_query.set_tile(
-        commands,
-        tile_pos,
-        Tile {
-            texture_index: 0,
-            ..Default::default()
-        },
-        0u16,
-        0u16,
-    );
-    map_query.notify_chunk_for_tile(tile_pos, 0u16, 0u16);
-}
-
-fn spawn_food(x: f32, y: f32, commands: &mut Commands) {
+pub fn spawn_ant(ant: AntBundle, pos: Vec3, rotation: f32, commands: &mut Commands) -> Entity {
     commands
-        .spawn_bundle(SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new(x, y, 0.0),
-                scale: Vec3::new(FOOD_SIZE, FOOD_SIZE, 1.0),
+        .spawn_bundle((
+            Transform {
+                scale: Vec3::new(ANT_SIZE, ANT_SIZE, 0.0),
+                translation: pos,
+                rotation: Quat::from_rotation_z(rotation),
                 ..Default::default()
             },
-            sprite: Sprite {
-                color: FOOD_COLOR,
+            GlobalTransform::default(),
+        ))
+        .insert_bundle(ant)
+        .insert(Locomotion::Kinematic)
+        .insert(DesiredHeading(rotation))
+        .insert(Sight::default())
+        .insert(AntState::default())
+        .insert(StateTicks::default())
+        .id()
+}
+
+/// Spawns an ant driven by a rapier rigid body, with the motor of its caste. `pos` is in physics
+/// units, i.e. world units divided by `RapierConfiguration::scale`.
+pub fn spawn_rigid_body_ant(
+    ant: AntBundle,
+    pos: Vec2,
+    rotation: f32,
+    config: &Config,
+    commands: &mut Commands,
+) -> Entity {
+    let motor = RigidBodyMotor::new(&ant.caste.profile(config));
+    let rigid_body = RigidBodyBundle {
+        position: (pos, rotation).into(),
+        damping: RigidBodyDamping {
+            linear_damping: 2.0,
+            angular_damping: 5.0,
+        }
+        .into(),
+        ..Default::default()
+    };
+    let collider = ColliderBundle {
+        // TODO: debug render a capsule?
+        shape: ColliderShape::capsule(Point2::new(0.25, 0.0), Point2::new(-0.25, 0.0), 0.25).into(),
+        // shape: ColliderShape::cuboid(0.5, 0.25).into(),
+        material: ColliderMaterial {
+            restitution: 0.7,
+            friction: 0.0,
+            ..Default::default()
+        }
+        .into(),
+        ..Default::default()
+    };
+    commands
+        .spawn_bundle(rigid_body)
+        .insert_bundle(collider)
+        .insert_bundle((
+            Transform {
+                scale: Vec3::new(ANT_SIZE, ANT_SIZE, 0.0),
                 ..Default::default()
             },
-            ..Default::default()
-        })
-        .insert(Food {});
+            GlobalTransform::default(),
+        ))
+        .insert(ColliderPositionSync::Discrete)
+        .insert_bundle(ant)
+        .insert(Locomotion::RigidBody)
+        .insert(motor)
+        .insert(DesiredHeading(rotation))
+        .insert(Sight::default())
+        .insert(AntState::default())
+        .insert(StateTicks::default())
+        .id()
 }
 
-fn spawn_food_cluster(pos: Vec3, commands: &mut Commands) {
-    for _ in 0..40 {
-        let r = 20.0;
-        let food_pos = pos
-            + Vec3::new(
-                random::<f32>() * 2.0 * r - r,
-                random::<f32>() * 2.0 * r - r,
-                0.0,
-            );
-        commands
-            .spawn_bundle(SpriteBundle {
-                transform: Transform {
-                    translation: food_pos,
-                    scale: Vec3::new(FOOD_SIZE, FOOD_SIZE, 1.0),
-                    ..Default::default()
-                },
-                sprite: Sprite {
-                    color: FOOD_COLOR,
-                    ..Default::default()
-                },
-                ..Default::default()
-            })
-            .insert(Food {});
+/// Spawns an ant moved by `locomotion` at `pos` in world units.
+pub fn spawn_ant_with_locomotion(
+    locomotion: Locomotion,
+    ant: AntBundle,
+    pos: Vec2,
+    rotation: f32,
+    rapier_scale: f32,
+    config: &Config,
+    commands: &mut Commands,
+) -> Entity {
+    match locomotion {
+        Locomotion::Kinematic => spawn_ant(ant, pos.extend(0.0), rotation, commands),
+        Locomotion::RigidBody => {
+            spawn_rigid_body_ant(ant, pos / rapier_scale, rotation, config, commands)
+        }
     }
 }
 
-fn spawn_trail(pos: Vec3, commands: &mut Commands, trail_type: TrailType, initial_strength: f32) {
+pub fn spawn_food_source(
+    pos: Vec2,
+    id: FoodId,
+    source: FoodSource,
+    commands: &mut Commands,
+) -> Entity {
     commands
-        .spawn_bundle(SpriteBundle {
-            transform: Transform {
-                translation: pos,
-                scale: Vec3::new(TRAIL_SIZE, TRAIL_SIZE, 1.0),
-                ..Default::default()
-            },
-            sprite: Sprite {
-                color: match trail_type {
-                    TrailType::GotFood => TRAIL_GOT_FOOD_COLOR,
-                    TrailType::Gathering => TRAIL_GATHERING_COLOR,
-                },
+        .spawn_bundle((
+            Transform {
+                translation: pos.extend(0.0),
+                scale: Vec3::new(FOOD_SIZE, FOOD_SIZE, 1.0),
                 ..Default::default()
             },
-            ..Default::default()
-        })
-        .insert(Trail {
-            trail_type: trail_type,
-            strength: initial_strength,
-        });
+            GlobalTransform::default(),
+        ))
+        .insert_bundle((id, source))
+        .id()
 }
 
-fn spawn_home(pos: Vec3, commands: &mut Commands) {
+pub fn spawn_home(pos: Vec3, colony: ColonyId, commands: &mut Commands) -> Entity {
     commands
-        .spawn_bundle(SpriteBundle {
-            transform: Transform {
+        .spawn_bundle((
+            Transform {
                 translation: pos,
                 scale: Vec3::new(HOME_SIZE, HOME_SIZE, 1.0),
                 ..Default::default()
             },
-            sprite: Sprite {
-                color: HOME_COLOR,
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(Home {});
+            GlobalTransform::default(),
+        ))
+        .insert(Home::default())
+        .insert(colony)
+        .id()
 }
 
+/// Bounces kinematic ants off obstacles. Rigid body ants collide through rapier.
 fn obstacle_collision_system(
-    mut ant_query: Query<(&Ant, &mut Transform), Without<Collider>>,
-    collider_query: Query<(&Collider, &Transform), Without<Ant>>,
-    mut map_query: MapQuery,
-    map_transform_query: Query<&Transform, (With<Map>, Without<Ant>)>,
+    mut ant_query: Query<(&Locomotion, &mut Transform), With<Ant>>,
+    obstacle_grid: Res<ObstacleGrid>,
 ) {
-    for (_ant, mut ant_transform) in ant_query.iter_mut() {
+    for (&locomotion, mut ant_transform) in ant_query.iter_mut() {
+        if locomotion != Locomotion::Kinematic {
+            continue;
+        }
         let ant_size = ant_transform.scale.truncate();
 
-        // check collision with walls
-        // TODO: converge walls and tiles
-        for (_collider, transform) in collider_query.iter() {
-            let a = ant_transform.translation.x - transform.translation.x;
-            let b = ant_transform.translation.y - transform.translation.y;
-            let c = transform.scale.x.max(transform.scale.y);
-            if a * a + b * b < c * c {
-                let collision = collide(
-                    ant_transform.translation,
-                    ant_size,
-                    transform.translation,
-                    transform.scale.truncate(),
-                );
-                if let Some(collision) = collision {
-                    // reflect the ball when it collides
-                    let mut reflect_x = false;
-                    let mut reflect_y = false;
-                    let direction = ant_transform.rotation * Vec3::X;
-
-                    // only reflect if the ant's velocity is going in the opposite direction of the
-                    // collision
-                    match collision {
-                        Collision::Left => reflect_x = direction.x > 0.0,
-                        Collision::Right => reflect_x = direction.x < 0.0,
-                        Collision::Top => reflect_y = direction.y < 0.0,
-                        Collision::Bottom => reflect_y = direction.y > 0.0,
-                    }
-
-                    // reflect velocity on the x-axis if we hit something on the x-axis
-                    if reflect_x {
-                        let clamped_direction = Vec3::new(-direction.x * 0.1, direction.y, 0.0);
-                        let angle = vec3_angle(clamped_direction);
-                        ant_transform.rotation = Quat::from_rotation_z(angle);
-                    }
-
-                    // reflect velocity on the y-axis if we hit something on the y-axis
-                    if reflect_y {
-                        let clamped_direction = Vec3::new(direction.x, -direction.y * 0.1, 0.0);
-                        let angle = vec3_angle(clamped_direction);
-                        ant_transform.rotation = Quat::from_rotation_z(angle);
-                    }
-                }
-            }
-        }
-        let collisions = collide_tiles_with_rect(
-            ant_transform.translation,
-            ant_size,
-            &mut map_query,
-            map_transform_query.single(),
-            0,
-            0,
-        );
+        let collisions = obstacle_grid.collide_rect(ant_transform.translation, ant_size);
         for collision in collisions {
             // reflect the ball when it collides
             let mut reflect_x = false;
@@ -892,105 +1233,374 @@ fn obstacle_collision_system(
     }
 }
 
-fn food_collision_system(
+/// Makes an ant face the opposite way, turning its rigid body too if it has one.
+fn turn_around(transform: &mut Transform, rb_pos: Option<&mut RigidBodyPositionComponent>) {
+    transform.rotation *= Quat::from_rotation_z(std::f32::consts::PI);
+    if let Some(rb_pos) = rb_pos {
+        let half_turn = UnitComplex::new(std::f32::consts::PI);
+        rb_pos.position.rotation *= half_turn;
+        rb_pos.next_position.rotation *= half_turn;
+    }
+}
+
+/// Applies the arena boundary to ants that left the arena this tick: kinematic ants bounce off a
+/// solid boundary (rigid body ants hit its collider instead), wrapping boundaries move ants to
+/// the opposite side and absorbing ones remove them.
+fn arena_boundary_system(
     mut commands: Commands,
-    mut ant_query: Query<(Entity, Option<&Children>, &mut Ant, &mut Transform), Without<Food>>,
-    mut available_food_query: Query<
-        (Entity, &Food, &mut Transform),
-        (Without<Parent>, Without<Ant>),
-    >,
-    home_query: Query<(Entity, &Home, &Transform), (Without<Ant>, Without<Food>)>,
+    arena: Res<Arena>,
+    rapier_configuration: Res<RapierConfiguration>,
+    mut ant_query: Query<(
+        Entity,
+        &ColonyId,
+        &Caste,
+        &Locomotion,
+        &mut AntState,
+        &mut Transform,
+        Option<&mut RigidBodyPositionComponent>,
+    )>,
+    mut deaths: EventWriter<AntDied>,
+    mut state_changes: EventWriter<AntStateChanged>,
 ) {
-    let mut taken_food: HashSet<u32> = HashSet::new();
-    for (ant_entity, maybe_children, mut ant, mut ant_transform) in ant_query.iter_mut() {
-        match maybe_children {
-            Some(children) if children.len() > 0 => {
-                // returning: check collision with home
-                for (_home_entity, _home, transform) in home_query.iter() {
-                    let a = ant_transform.translation.x - transform.translation.x;
-                    let b = ant_transform.translation.y - transform.translation.y;
-                    if a * a + b * b < HOME_SIZE * HOME_SIZE {
-                        for &child in children.iter() {
-                            commands.entity(child).despawn_recursive();
-                        }
-                        ant.carrying_food = false;
-                        ant_transform.rotation *= Quat::from_rotation_z(std::f32::consts::PI);
-                    }
+    for (entity, &colony, &caste, &locomotion, mut state, mut transform, rb_pos) in
+        ant_query.iter_mut()
+    {
+        let pos = match &rb_pos {
+            Some(rb_pos) => {
+                let translation = rb_pos.position.translation;
+                Vec2::new(translation.x, translation.y) * rapier_configuration.scale
+            }
+            None => transform.translation.truncate(),
+        };
+        if arena.contains(pos) {
+            continue;
+        }
+        match arena.boundary {
+            Boundary::Solid => {
+                if locomotion != Locomotion::Kinematic {
+                    continue;
+                }
+                let (point, normal) = arena.nearest_boundary(pos);
+                transform.translation = arena.clamp_inside(point).extend(transform.translation.z);
+                let direction = (transform.rotation * Vec3::X).truncate();
+                if direction.dot(normal) < 0.0 {
+                    let reflected = direction - 2.0 * direction.dot(normal) * normal;
+                    transform.rotation = Quat::from_rotation_z(vec3_angle(reflected.extend(0.0)));
+                }
+            }
+            Boundary::Wrap => {
+                let wrapped = arena.wrap(pos);
+                transform.translation = wrapped.extend(transform.translation.z);
+                if let Some(mut rb_pos) = rb_pos {
+                    let translation = Translation2::new(
+                        wrapped.x / rapier_configuration.scale,
+                        wrapped.y / rapier_configuration.scale,
+                    );
+                    rb_pos.position.translation = translation;
+                    rb_pos.next_position.translation = translation;
                 }
             }
-            _ => {
-                // gathering: check collision with food
-                for (food_entity, _food, mut transform) in available_food_query.iter_mut() {
-                    if taken_food.contains(&food_entity.id()) {
+            Boundary::Absorb => {
+                commands.entity(entity).despawn_recursive();
+                state_changes.send(AntStateChanged {
+                    ant: entity,
+                    colony,
+                    from: *state,
+                    to: AntState::Dead,
+                });
+                *state = AntState::Dead;
+                deaths.send(AntDied {
+                    ant: entity,
+                    colony,
+                    caste,
+                });
+            }
+        }
+    }
+}
+
+/// Lets ants without food take some from the sources they touch, and ants carrying food drop
+/// it into the store of a home of their colony they touch.
+fn food_collision_system(
+    config: Res<Config>,
+    mut ant_query: Query<(
+        Entity,
+        &ColonyId,
+        &Caste,
+        &AntState,
+        &mut Ant,
+        &mut Transform,
+        Option<&mut RigidBodyPositionComponent>,
+    )>,
+    mut source_query: Query<&mut FoodSource>,
+    mut home_query: Query<(&ColonyId, &mut Home)>,
+    food_index: Res<SpatialIndex<FoodSource>>,
+    home_index: Res<SpatialIndex<Home>>,
+    id_query: Query<(Entity, &AntId)>,
+    mut deliveries: EventWriter<FoodDelivered>,
+) {
+    for entity in ants_in_id_order(&id_query) {
+        let (ant_entity, &colony, &caste, &state, mut ant, mut ant_transform, mut rb_pos) =
+            match ant_query.get_mut(entity) {
+                Ok(ant) => ant,
+                Err(_) => continue,
+            };
+        // already removed by the arena boundary this tick
+        if state == AntState::Dead {
+            continue;
+        }
+        let ant_pos = ant_transform.translation.truncate();
+        if ant.carrying_food {
+            // returning: check collision with a home of the ant's own colony
+            let home = home_index
+                .query_radius(ant_pos, HOME_SIZE)
+                .into_iter()
+                .find(|&(home_entity, _)| {
+                    home_query
+                        .get(home_entity)
+                        .map_or(false, |(&home_colony, _)| home_colony == colony)
+                });
+            if let Some((home_entity, _)) = home {
+                let (_, mut home) = home_query.get_mut(home_entity).unwrap();
+                home.food_store += ant.carried_food;
+                deliveries.send(FoodDelivered {
+                    ant: ant_entity,
+                    home: home_entity,
+                    colony,
+                    caste,
+                    amount: ant.carried_food,
+                });
+                ant.carrying_food = false;
+                ant.carried_food = 0;
+                turn_around(&mut ant_transform, rb_pos.as_deref_mut());
+            }
+        } else {
+            // gathering: check collision with food
+            for (food_entity, _) in food_index.query_radius(ant_pos, FOOD_SIZE) {
+                if let Ok(mut source) = source_query.get_mut(food_entity) {
+                    let taken = source.take_food();
+                    if taken == 0 {
                         continue;
                     }
-                    let a = ant_transform.translation.x - transform.translation.x;
-                    let b = ant_transform.translation.y - transform.translation.y;
-                    if a * a + b * b < FOOD_SIZE * FOOD_SIZE {
-                        transform.scale = Vec3::new(1.0, 1.0, 1.0);
-                        transform.translation = Vec3::new(1.0, 0.0, 0.0);
-                        commands.entity(ant_entity).push_children(&[food_entity]);
-                        taken_food.insert(food_entity.id());
-                        ant.carrying_food = true;
-                        ant_transform.rotation *= Quat::from_rotation_z(std::f32::consts::PI);
-                        break;
-                    }
+                    ant.carrying_food = true;
+                    ant.carried_food = taken * source.food_type.nutrition(&config);
+                    turn_around(&mut ant_transform, rb_pos.as_deref_mut());
+                    break;
                 }
             }
         }
     }
 }
 
-fn trail_spawn_system(
+/// Drains every ant's energy with time and distance walked, feeds ants at their own colony's
+/// homes from the food store and despawns the ones that starve.
+fn ant_lifecycle_system(
     mut commands: Commands,
-    mut current_frame: Local<usize>,
     config: Res<Config>,
-    query: Query<(Entity, &Ant, &Transform)>,
+    rapier_configuration: Res<RapierConfiguration>,
+    home_index: Res<SpatialIndex<Home>>,
+    mut ant_query: Query<(
+        Entity,
+        &ColonyId,
+        &Caste,
+        &mut Ant,
+        &mut AntState,
+        &Transform,
+        Option<&RigidBodyVelocityComponent>,
+    )>,
+    mut home_query: Query<(&ColonyId, &mut Home)>,
+    id_query: Query<(Entity, &AntId)>,
+    mut deaths: EventWriter<AntDied>,
+    mut state_changes: EventWriter<AntStateChanged>,
 ) {
-    let trail_spawn_period = config.entries["trail.spawn_period"].f32();
-    let spawn_period_frames = (trail_spawn_period / TIME_STEP) as usize;
-    let current_spawn_frame = *current_frame % spawn_period_frames;
-    for (entity, ant, transform) in query.iter() {
-        let mut rng = ChaCha8Rng::seed_from_u64(entity.id() as u64);
-        let ant_spawn_frame_offset = rng.gen::<usize>() % spawn_period_frames;
-        if ant_spawn_frame_offset == current_spawn_frame {
-            spawn_trail(
-                transform.translation,
+    let energy_per_second = config.get(&ANT_ENERGY_PER_SECOND);
+    let energy_per_distance = config.get(&ANT_ENERGY_PER_DISTANCE);
+    let energy_per_food = config.get(&HOME_ENERGY_PER_FOOD);
+    for entity in ants_in_id_order(&id_query) {
+        let (entity, &colony, &caste, mut ant, mut state, transform, rb_vel) =
+            match ant_query.get_mut(entity) {
+                Ok(ant) => ant,
+                Err(_) => continue,
+            };
+        // already removed by the arena boundary this tick
+        if *state == AntState::Dead {
+            continue;
+        }
+        let speed = match rb_vel {
+            Some(rb_vel) => rb_vel.linvel.norm() * rapier_configuration.scale,
+            None => {
+                config.get(&ANT_SPEED) * state.params(&config).speed * caste.profile(&config).speed
+            }
+        };
+        ant.energy -= (energy_per_second + energy_per_distance * speed) * TIME_STEP;
+
+        let ant_pos = transform.translation.truncate();
+        for (home_entity, _) in home_index.query_radius(ant_pos, HOME_SIZE) {
+            if let Ok((&home_colony, mut home)) = home_query.get_mut(home_entity) {
+                if home_colony == colony {
+                    eat_from_store(&mut ant.energy, &mut home.food_store, energy_per_food);
+                }
+            }
+        }
+
+        if ant.energy <= 0.0 {
+            commands.entity(entity).despawn_recursive();
+            state_changes.send(AntStateChanged {
+                ant: entity,
+                colony,
+                from: *state,
+                to: AntState::Dead,
+            });
+            *state = AntState::Dead;
+            deaths.send(AntDied {
+                ant: entity,
+                colony,
+                caste,
+            });
+        }
+    }
+}
+
+/// Eats one unit of `food_store` at a time, as long as none of it goes to waste. Food worth no
+/// energy is left in the store.
+fn eat_from_store(energy: &mut f32, food_store: &mut usize, energy_per_food: f32) {
+    if energy_per_food <= 0.0 {
+        return;
+    }
+    while *food_store > 0 && *energy + energy_per_food <= 1.0 {
+        *food_store -= 1;
+        *energy += energy_per_food;
+    }
+}
+
+/// Turns each home's food beyond `home.food_reserve` into new ants, one per
+/// `home.food_per_ant`, of castes drawn by their `caste.<name>.ratio`.
+fn nest_reproduction_system(
+    mut commands: Commands,
+    tick: Res<SimulationTick>,
+    config: Res<Config>,
+    sim_rng: Res<SimRng>,
+    default_locomotion: Res<DefaultLocomotion>,
+    rapier_configuration: Res<RapierConfiguration>,
+    mut next_ant_id: ResMut<NextAntId>,
+    mut home_query: Query<(&ColonyId, &mut Home, &Transform)>,
+    mut births: EventWriter<AntBorn>,
+) {
+    let food_per_ant = config.get(&HOME_FOOD_PER_ANT).max(1);
+    let food_reserve = config.get(&HOME_FOOD_RESERVE);
+    for (&colony, mut home, transform) in home_query.iter_mut() {
+        while home.food_store >= food_reserve + food_per_ant {
+            home.food_store -= food_per_ant;
+            let id = next_ant_id.next();
+            let mut rng = sim_rng.ant_rng(id.0, AntStream::Birth, tick.0);
+            let rotation = rng.gen::<f32>() * 2.0 * std::f32::consts::PI;
+            let caste = Caste::choose(&config, rng.gen::<f32>());
+            let ant = spawn_ant_with_locomotion(
+                default_locomotion.0,
+                AntBundle::new(id, colony, caste, &config),
+                transform.translation.truncate(),
+                rotation,
+                rapier_configuration.scale,
+                &config,
                 &mut commands,
-                if ant.carrying_food {
-                    TrailType::GotFood
-                } else {
-                    TrailType::Gathering
-                },
-                config.entries["trail.initial_strength"].f32(),
             );
+            births.send(AntBorn { ant, colony, caste });
         }
     }
-    *current_frame += 1;
 }
 
-fn trail_decay_system(
-    mut commands: Commands,
+/// Runs a tick for every `TIME_STEP` of wall-clock time scaled by the `SimulationControl` speed,
+/// plus any pending steps, at most `MAX_TICKS_PER_FRAME` a frame in all.
+fn fixed_timestep_run_criteria(
+    time: Res<Time>,
+    mut control: ResMut<SimulationControl>,
+) -> ShouldRun {
+    if !control.looping {
+        control.ticks_this_frame = 0;
+        if !control.paused {
+            let backlog = MAX_TICKS_PER_FRAME as f64 * TIME_STEP as f64;
+            control.accumulator = (control.accumulator
+                + time.delta_seconds_f64() * control.speed as f64)
+                .min(backlog);
+        }
+    }
+    let run = if control.ticks_this_frame >= MAX_TICKS_PER_FRAME {
+        false
+    } else if control.take_step() {
+        true
+    } else if !control.paused && control.accumulator >= TIME_STEP as f64 {
+        control.accumulator -= TIME_STEP as f64;
+        true
+    } else {
+        false
+    };
+    control.looping = run;
+    if run {
+        control.ticks_this_frame += 1;
+        ShouldRun::YesAndCheckAgain
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Runs one tick per update unless paused, or a pending step while paused.
+fn every_update_run_criteria(mut control: ResMut<SimulationControl>) -> ShouldRun {
+    let run = control.take_step() || !control.paused;
+    control.ticks_this_frame = run as usize;
+    if run {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+fn never_run_criteria() -> ShouldRun {
+    ShouldRun::No
+}
+
+fn simulation_tick_system(mut tick: ResMut<SimulationTick>) {
+    tick.0 += 1;
+}
+
+/// Reseeds the simulation when `sim.seed` is edited.
+fn sim_rng_system(config: Res<Config>, mut sim_rng: ResMut<SimRng>) {
+    let seed = config.get(&SIM_SEED);
+    if sim_rng.seed() != seed {
+        *sim_rng = SimRng::new(seed);
+    }
+}
+
+fn trail_spawn_system(
+    tick: Res<SimulationTick>,
+    sim_rng: Res<SimRng>,
     config: Res<Config>,
-    mut query: Query<(Entity, &mut Trail, &mut Sprite)>,
+    mut pheromone_field: ResMut<PheromoneField>,
+    query: Query<(&AntId, &ColonyId, &AntState, &Transform)>,
 ) {
-    let initial_strength = config.entries["trail.initial_strength"].f32();
-    let decay_rate = config.entries["trail.decay_rate"].f32();
-    for (entity, mut trail, mut sprite) in query.iter_mut() {
-        let mut color = match trail.trail_type {
-            TrailType::GotFood => TRAIL_GOT_FOOD_COLOR,
-            TrailType::Gathering => TRAIL_GATHERING_COLOR,
-        };
-        trail.strength = trail.strength * decay_rate;
-        color.set_a(trail.strength / initial_strength);
-        sprite.color = color;
-        if trail.strength < 0.01 {
-            commands.entity(entity).despawn();
+    let trail_spawn_period = config.get(&TRAIL_SPAWN_PERIOD);
+    let spawn_period_frames = (trail_spawn_period / TIME_STEP) as usize;
+    let current_spawn_frame = tick.0 % spawn_period_frames;
+    // deposits into the same cell add up differently depending on their order
+    let mut ants: Vec<_> = query.iter().collect();
+    ants.sort_unstable_by_key(|&(&ant_id, ..)| ant_id);
+    for (ant_id, &colony, state, transform) in ants {
+        let mut rng = sim_rng.ant_rng(ant_id.0, AntStream::TrailSpawn, 0);
+        let ant_spawn_frame_offset = rng.gen::<usize>() % spawn_period_frames;
+        if ant_spawn_frame_offset == current_spawn_frame {
+            let trail_type = match state.params(&config).drops {
+                Some(trail_type) => trail_type,
+                None => continue,
+            };
+            pheromone_field.deposit(
+                trail_type.channel(colony),
+                transform.translation,
+                config.get(&TRAIL_INITIAL_STRENGTH),
+            );
         }
     }
 }
 
-fn vec3_angle(v: Vec3) -> f32 {
+pub fn vec3_angle(v: Vec3) -> f32 {
     let angle = v.angle_between(Vec3::X);
     if v.y < 0.0 {
         -angle
@@ -999,114 +1609,154 @@ fn vec3_angle(v: Vec3) -> f32 {
     }
 }
 
-fn ant_movement_system2(
-    keys: Res<Input<KeyCode>>,
-    mut rigid_bodies: Query<(
-        &Ant,
-        &mut RigidBodyForcesComponent,
-        &mut RigidBodyVelocityComponent,
-        &RigidBodyMassPropsComponent,
-        &mut RigidBodyPositionComponent,
-    )>,
-) {
-    for (ant, mut rb_forces, rb_vel, _rb_mprops, rb_pos) in rigid_bodies.iter_mut() {
-        // Motor forces
-        let object_x_axis = rb_pos.position.rotation * Vector2::x_axis();
-        let object_x_velocity = rb_vel.linvel.dot(&object_x_axis) * object_x_axis.into_inner();
-        if !keys.pressed(KeyCode::Down) {
-            rb_forces.force += rb_pos.position.rotation
-                * Vector2::x_axis().into_inner()
-                * (ant.target_speed - object_x_velocity.norm())
-                * ant.motor_force;
-        }
-
-        // Grip forces
-        let object_y_axis = rb_pos.position.rotation * Vector2::y_axis();
-        let object_y_velocity = rb_vel.linvel.dot(&object_y_axis) * object_y_axis.into_inner();
-        rb_forces.force -= object_y_velocity * ant.grip_force;
+/// The three pheromone sensors ahead of an ant, `sensor_distance` away at `sensor_angle` to
+/// either side and straight ahead.
+pub struct TrailSensors {
+    /// In the ant's local coordinates, which are scaled by its size.
+    positions: [Vec3; 3],
+    radius: f32,
+}
 
-        // Turning input
-        if keys.pressed(KeyCode::Left) {
-            rb_forces.torque += ant.turning_torque;
+impl TrailSensors {
+    pub fn new(config: &Config) -> TrailSensors {
+        let sensor_angle = config.get(&SENSOR_ANGLE);
+        let sensor_base_pos = Vec3::new(1.0 / ANT_SIZE, 0.0, 0.0) * config.get(&SENSOR_DISTANCE);
+        TrailSensors {
+            positions: [
+                Quat::from_rotation_z(sensor_angle) * sensor_base_pos,
+                sensor_base_pos,
+                Quat::from_rotation_z(-sensor_angle) * sensor_base_pos,
+            ],
+            radius: config.get(&SENSOR_RADIUS),
         }
-        if keys.pressed(KeyCode::Right) {
-            rb_forces.torque -= ant.turning_torque;
+    }
+
+    /// Strength of pheromone `channel` at each sensor of an ant at `transform`. Pheromone behind
+    /// a wall can't be smelled.
+    pub fn sense(
+        &self,
+        transform: &Transform,
+        channel: usize,
+        pheromone_field: &PheromoneField,
+        obstacle_grid: &ObstacleGrid,
+        arena: &Arena,
+    ) -> [f32; 3] {
+        let ant_pos = transform.translation.truncate();
+        let mut magnitudes = [0.0, 0.0, 0.0];
+        for (i, sensor_position) in self.positions.iter().enumerate() {
+            let sensor_world_pos = transform.mul_vec3(*sensor_position);
+            if line_of_sight(obstacle_grid, arena, ant_pos, sensor_world_pos.truncate()) {
+                magnitudes[i] = pheromone_field.sample(channel, sensor_world_pos, self.radius);
+            }
         }
+        magnitudes
+    }
 
-        // Random wandering
-        rb_forces.torque += ant.random_turning_torque * (random::<f32>() * 2.0 - 1.0);
+    /// Angle, relative to the ant's facing, of the direction the sensed `magnitudes` pull it in.
+    /// Zero when nothing was sensed.
+    pub fn turning_angle(&self, magnitudes: [f32; 3]) -> f32 {
+        let turning_direction = self.positions[0] * magnitudes[0]
+            + self.positions[1] * magnitudes[1]
+            + self.positions[2] * magnitudes[2];
+        if turning_direction.length() > 0.0 {
+            vec3_angle(turning_direction)
+        } else {
+            0.0
+        }
     }
 }
 
-fn ant_movement_system(
-    mut ant_query: Query<(&Ant, &mut Transform), Without<Trail>>,
-    trail_query: Query<(&Trail, &Transform), Without<Ant>>,
+/// Points every ant's `DesiredHeading` as its `AntState` calls for: straight at the food or home
+/// it is after when it senses one, away from a threat when fleeing, and otherwise along the
+/// state's pheromone trail with some random wandering, turning away from walls in sight either
+/// way. Its `Locomotion` then moves it.
+fn ant_steering_system(
+    mut ant_query: Query<(
+        &AntId,
+        &ColonyId,
+        &Caste,
+        &AntState,
+        &Sight,
+        &Transform,
+        &mut DesiredHeading,
+    )>,
+    tick: Res<SimulationTick>,
+    sim_rng: Res<SimRng>,
+    pheromone_field: Res<PheromoneField>,
+    obstacle_grid: Res<ObstacleGrid>,
+    arena: Res<Arena>,
     config: Res<Config>,
 ) {
-    let sensor_angle = config.entries["sensor_angle"].f32();
-    let sensor_distance = config.entries["sensor_distance"].f32();
-    let sensor_radius = config.entries["sensor_radius"].f32();
-    let sensor_turning_coefficient = config.entries["sensor_turning_coefficient"].f32();
-    let mut sensor_magnitudes = [0.0, 0.0, 0.0];
-    let sensor_base_pos = Vec3::new(1.0 / ANT_SIZE, 0.0, 0.0) * sensor_distance;
-    let sensor_positions = [
-        Quat::from_rotation_z(sensor_angle) * sensor_base_pos,
-        sensor_base_pos,
-        Quat::from_rotation_z(-sensor_angle) * sensor_base_pos,
-    ];
-    for (ant, mut ant_transform) in ant_query.iter_mut() {
-        let velocity = ant_transform.rotation * Vec3::X * config.entries["ant.speed"].f32();
-        ant_transform.translation += velocity * TIME_STEP;
-
-        let angle = vec3_angle(velocity);
-        let wandering_angle_delta =
-            config.entries["ant.wandering"].f32() * (random::<f32>() * 2.0 - 1.0);
-
-        let t_sensor_positions = [
-            ant_transform.mul_vec3(sensor_positions[0]),
-            ant_transform.mul_vec3(sensor_positions[1]),
-            ant_transform.mul_vec3(sensor_positions[2]),
-        ];
-        for (trail, trail_transform) in trail_query.iter() {
-            if ant.carrying_food && matches!(trail.trail_type, TrailType::Gathering)
-                || !ant.carrying_food && matches!(trail.trail_type, TrailType::GotFood)
-            {
-                for (i, s_pos) in t_sensor_positions.iter().enumerate() {
-                    if (trail_transform.translation - *s_pos).length() < sensor_radius {
-                        sensor_magnitudes[i] += trail.strength;
-                    }
-                }
+    let sensor_turning_coefficient = config.get(&SENSOR_TURNING_COEFFICIENT);
+    let vision_distance = config.get(&VISION_DISTANCE);
+    let wall_avoidance = config.get(&VISION_WALL_AVOIDANCE);
+    let ray_angles = Sight::ray_angles(&config);
+    let trail_sensors = TrailSensors::new(&config);
+    for (ant_id, &colony, caste, state, sight, ant_transform, mut heading) in ant_query.iter_mut() {
+        let params = state.params(&config);
+        let ant_pos = ant_transform.translation.truncate();
+        let angle = vec3_angle(ant_transform.rotation * Vec3::X);
+        let mut rng = sim_rng.ant_rng(ant_id.0, AntStream::Wandering, tick.0);
+        let wandering_angle_delta = config.get(&ANT_WANDERING)
+            * params.wander
+            * caste.profile(&config).wandering
+            * (rng.gen::<f32>() * 2.0 - 1.0);
+
+        let avoidance = sight.wall_avoidance(&ray_angles, vision_distance);
+        let avoidance_angle_delta = if avoidance != Vec2::ZERO {
+            (Vec2::X + avoidance).y.atan2((Vec2::X + avoidance).x) * wall_avoidance
+        } else {
+            0.0
+        };
+
+        let target_direction = match state {
+            AntState::Exploring | AntState::FollowingTrail => sight.food.map(|food| food - ant_pos),
+            AntState::CarryingFood | AntState::ReturningHome => {
+                sight.home.map(|home| home - ant_pos)
             }
+            AntState::Fleeing => sight.threat.map(|threat| ant_pos - threat),
+            AntState::Resting | AntState::Dead => None,
+        };
+        if let Some(direction) = target_direction {
+            heading.0 = direction.y.atan2(direction.x) + avoidance_angle_delta;
+            continue;
         }
-        let turning_direction = sensor_positions[0] * sensor_magnitudes[0]
-            + sensor_positions[1] * sensor_magnitudes[1]
-            + sensor_positions[2] * sensor_magnitudes[2];
 
-        let turning_angle_delta = if turning_direction.length() > 0.0 {
-            vec3_angle(turning_direction) * sensor_turning_coefficient
-        } else {
-            0.0
+        let turning_angle_delta = match params.follows {
+            Some(followed_trail) => {
+                let magnitudes = trail_sensors.sense(
+                    ant_transform,
+                    followed_trail.channel(colony),
+                    &pheromone_field,
+                    &obstacle_grid,
+                    &arena,
+                );
+                trail_sensors.turning_angle(magnitudes) * sensor_turning_coefficient
+            }
+            None => 0.0,
         };
-        ant_transform.rotation =
-            Quat::from_rotation_z(angle + turning_angle_delta + wandering_angle_delta);
+        heading.0 = angle + turning_angle_delta + wandering_angle_delta + avoidance_angle_delta;
     }
 }
 
-pub fn set_texture_filters_to_nearest(
-    mut texture_events: EventReader<AssetEvent<Image>>,
-    mut textures: ResMut<Assets<Image>>,
-) {
-    // quick and dirty, run this for all textures anytime a texture is created.
-    for event in texture_events.iter() {
-        match event {
-            AssetEvent::Created { handle } => {
-                if let Some(mut texture) = textures.get_mut(handle) {
-                    texture.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
-                        | TextureUsages::COPY_SRC
-                        | TextureUsages::COPY_DST;
-                }
-            }
-            _ => (),
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ants_eat_until_full() {
+        let (mut energy, mut food_store) = (0.125, 10);
+        eat_from_store(&mut energy, &mut food_store, 0.25);
+        assert_eq!((energy, food_store), (0.875, 7));
+        let (mut energy, mut food_store) = (0.125, 2);
+        eat_from_store(&mut energy, &mut food_store, 0.25);
+        assert_eq!((energy, food_store), (0.625, 0));
+    }
+
+    #[test]
+    fn food_worth_no_energy_is_left_in_the_store() {
+        let (mut energy, mut food_store) = (0.5, 10);
+        eat_from_store(&mut energy, &mut food_store, 0.0);
+        assert_eq!((energy, food_store), (0.5, 10));
     }
 }
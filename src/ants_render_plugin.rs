@@ -0,0 +1,793 @@
+use crate::ants_plugin::{
+    spawn_food_source, spawn_home, Ant, ColonyId, Home, ScenarioFile, SnapshotFile, TrailType,
+    MAX_COLONIES, TRAIL_INITIAL_STRENGTH,
+};
+use crate::arena::{Arena, Boundary};
+use crate::behavior::{AntState, AntStateChanged};
+use crate::config::Config;
+use crate::food::{FoodSource, FoodType, NextFoodId};
+use crate::helpers::obstacle_grid::ObstacleGrid;
+use crate::helpers::pheromone_field::PheromoneField;
+use crate::scenario::{load_scenario, save_scenario};
+use crate::snapshot::{load_snapshot, save_snapshot};
+use crate::vision::Sight;
+use bevy::{
+    prelude::*,
+    render::render_resource::{
+        Extent3d, FilterMode, TextureDimension, TextureFormat, TextureUsages,
+    },
+};
+use bevy_ecs_tilemap::prelude::*;
+use bevy_rapier2d::prelude::*;
+use std::path::PathBuf;
+
+/// Presentation of the `AntsPlugin` simulation: camera, sprites, the obstacle tilemap and the
+/// mouse-driven editor.
+pub struct AntsRenderPlugin;
+
+const OBSTACLE_COLOR: Color = Color::rgb(0.65, 0.16, 0.16);
+const FOOD_COLOR: Color = Color::rgb(0.0, 0.65, 0.0);
+/// Tint of each `FoodType`, in `FoodType::ALL` order.
+const FOOD_TYPE_COLORS: [Color; 3] = [
+    FOOD_COLOR,
+    Color::rgb(0.9, 0.35, 0.55),
+    Color::rgb(0.55, 0.4, 0.2),
+];
+/// Opacity of a food source with no food left, which rises to 1 as it fills up.
+const EMPTY_FOOD_ALPHA: f32 = 0.2;
+const HOME_COLOR: Color = Color::rgb(1.0, 1.0, 0.62);
+/// Home and ant tint of each colony.
+const COLONY_COLORS: [Color; MAX_COLONIES] = [
+    HOME_COLOR,
+    Color::rgb(0.62, 1.0, 0.95),
+    Color::rgb(1.0, 0.7, 1.0),
+    Color::rgb(1.0, 0.78, 0.5),
+];
+/// Trail colors of each colony, in `TrailType::ALL` order.
+const COLONY_TRAIL_COLORS: [[Color; 2]; MAX_COLONIES] = [
+    [Color::rgb(0.28, 0.51, 0.87), Color::rgb(0.88, 0.18, 0.24)],
+    [Color::rgb(0.16, 0.75, 0.62), Color::rgb(0.96, 0.58, 0.12)],
+    [Color::rgb(0.58, 0.32, 0.86), Color::rgb(0.95, 0.86, 0.2)],
+    [Color::rgb(0.45, 0.45, 0.5), Color::rgb(0.95, 0.95, 0.95)],
+];
+/// Ant tint of each `AntState` while the state overlay is on, in `AntState::ALL` order.
+const STATE_COLORS: [Color; 7] = [
+    Color::rgb(0.6, 0.8, 1.0),
+    Color::rgb(0.3, 0.5, 1.0),
+    Color::rgb(0.2, 1.0, 0.2),
+    Color::rgb(1.0, 1.0, 0.3),
+    Color::rgb(0.7, 0.7, 0.7),
+    Color::rgb(1.0, 0.2, 0.2),
+    Color::rgb(0.2, 0.2, 0.2),
+];
+const WALL_COLOR: Color = Color::rgb(0.8, 0.8, 0.8);
+const OPEN_BOUNDARY_COLOR: Color = Color::rgba(0.8, 0.8, 0.8, 0.3);
+/// Width and height, in tiles, of the chunks of the obstacle tilemap.
+const OBSTACLE_CHUNK_SIZE: u32 = 10;
+const WALL_THICKNESS: f32 = 2.0;
+const PERCEPTION_LINE_THICKNESS: f32 = 0.5;
+const DEFAULT_SCENARIO_PATH: &str = "scenario.ron";
+const DEFAULT_SNAPSHOT_PATH: &str = "snapshot.ron";
+
+impl Plugin for AntsRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(TilemapPlugin)
+            .add_plugin(RapierRenderPlugin)
+            .init_resource::<EditorInput>()
+            .init_resource::<PerceptionOverlay>()
+            .init_resource::<StateOverlay>()
+            .add_startup_system(setup)
+            .add_system(ant_sprite_system)
+            .add_system(food_sprite_system)
+            .add_system(food_amount_sprite_system)
+            .add_system(carried_food_sprite_system)
+            .add_system(pheromone_texture_system)
+            .add_system(home_sprite_system)
+            .add_system(arena_sprite_system)
+            .add_system(collider_debug_render_system)
+            .add_system(obstacle_tilemap_system)
+            .add_system(mouse_input_system)
+            .add_system(colony_hotkey_system)
+            .add_system(food_type_hotkey_system)
+            .add_system(perception_hotkey_system)
+            .add_system(perception_overlay_system)
+            .add_system(state_hotkey_system)
+            .add_system(state_overlay_system)
+            .add_system(scenario_hotkey_system.exclusive_system())
+            .add_system(snapshot_hotkey_system.exclusive_system())
+            .add_system(set_texture_filters_to_nearest);
+    }
+}
+
+#[derive(Component, Copy, Clone)]
+enum Icon {
+    SpawnObstacle,
+    SpawnFood,
+    SpawnFoodSource,
+    SpawnHome,
+}
+
+/// Whether lines from each ant to the food and home it perceives are drawn.
+#[derive(Default)]
+struct PerceptionOverlay(bool);
+
+/// Whether ants are tinted by their `AntState` instead of their colony.
+#[derive(Default)]
+struct StateOverlay(bool);
+
+/// Texture the PheromoneField is drawn into, one texel per cell.
+struct PheromoneTexture(Handle<Image>);
+
+struct EditorInput {
+    selected_icon: Option<Icon>,
+    /// Colony new homes are spawned for.
+    colony: ColonyId,
+    /// Type of the food new food sources hold.
+    food_type: FoodType,
+}
+
+impl Default for EditorInput {
+    fn default() -> Self {
+        EditorInput {
+            selected_icon: None,
+            colony: ColonyId(0),
+            food_type: FoodType::Seed,
+        }
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    obstacle_grid: Res<ObstacleGrid>,
+    pheromone_field: Res<PheromoneField>,
+    mut map_query: MapQuery,
+) {
+    let mut camera = OrthographicCameraBundle::new_2d();
+    camera.orthographic_projection.scale = 1.0;
+    commands.spawn_bundle(camera);
+
+    // Create an empty obstacle layer; obstacle_tilemap_system fills it from the ObstacleGrid.
+    let texture_handle = asset_server.load("tiles_10.png");
+    let map_entity = commands.spawn().id();
+    let mut map = Map::new(0u16, map_entity);
+    let layer_settings = LayerSettings::new(
+        // whole chunks, rounded up so they cover every tile of the grid
+        MapSize(
+            obstacle_grid.width.div_ceil(OBSTACLE_CHUNK_SIZE),
+            obstacle_grid.height.div_ceil(OBSTACLE_CHUNK_SIZE),
+        ),
+        ChunkSize(OBSTACLE_CHUNK_SIZE, OBSTACLE_CHUNK_SIZE),
+        TileSize(obstacle_grid.tile_size, obstacle_grid.tile_size),
+        TextureSize(60.0, 10.0),
+    );
+    let (layer_builder, _) =
+        LayerBuilder::<TileBundle>::new(&mut commands, layer_settings, 0u16, 0u16);
+    let layer_entity = map_query.build_layer(&mut commands, layer_builder, texture_handle);
+    map.add_layer(&mut commands, 0u16, layer_entity);
+    commands
+        .entity(map_entity)
+        .insert(map)
+        .insert(Transform::from_xyz(
+            obstacle_grid.origin.x,
+            obstacle_grid.origin.y,
+            0.0,
+        ))
+        .insert(GlobalTransform::default());
+
+    // pheromone overlay, drawn above the obstacle tiles
+    let mut pheromone_image = Image::new_fill(
+        Extent3d {
+            width: pheromone_field.width,
+            height: pheromone_field.height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    pheromone_image.sampler_descriptor.mag_filter = FilterMode::Nearest;
+    pheromone_image.sampler_descriptor.min_filter = FilterMode::Nearest;
+    let pheromone_texture = images.add(pheromone_image);
+    let field_size = Vec2::new(pheromone_field.width as f32, pheromone_field.height as f32)
+        * pheromone_field.cell_size;
+    commands.spawn_bundle(SpriteBundle {
+        texture: pheromone_texture.clone(),
+        transform: Transform::from_translation(
+            (pheromone_field.origin + field_size / 2.0).extend(1.0),
+        ),
+        sprite: Sprite {
+            custom_size: Some(field_size),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    commands.insert_resource(PheromoneTexture(pheromone_texture));
+
+    // icons
+    let icons = [
+        (Icon::SpawnObstacle, OBSTACLE_COLOR),
+        (Icon::SpawnFood, FOOD_COLOR),
+        (Icon::SpawnFoodSource, FOOD_COLOR),
+        (Icon::SpawnHome, HOME_COLOR),
+    ];
+    let icons_top_left = obstacle_grid.origin * Vec2::new(1.0, -1.0);
+    for (i, (icon, color)) in icons.iter().enumerate() {
+        commands
+            .spawn_bundle(SpriteBundle {
+                transform: Transform {
+                    translation: Vec3::new(
+                        icons_top_left.x - 50.0,
+                        icons_top_left.y - 15.0 - 45.0 * i as f32,
+                        0.0,
+                    ),
+                    scale: Vec3::new(40.0, 40.0, 1.0),
+                    ..Default::default()
+                },
+                sprite: Sprite {
+                    color: *color,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(*icon);
+    }
+}
+
+fn colored_sprite(color: Color) -> Sprite {
+    Sprite {
+        color,
+        ..Default::default()
+    }
+}
+
+fn food_color(source: &FoodSource) -> Color {
+    let fill = source.amount as f32 / source.capacity.max(1) as f32;
+    let mut color = FOOD_TYPE_COLORS[source.food_type as usize];
+    color.set_a(EMPTY_FOOD_ALPHA + (1.0 - EMPTY_FOOD_ALPHA) * fill.min(1.0));
+    color
+}
+
+fn colony_color(colony: ColonyId) -> Color {
+    COLONY_COLORS[colony.0 as usize]
+}
+
+fn ant_color(colony: ColonyId, state: AntState, state_overlay: &StateOverlay) -> Color {
+    if state_overlay.0 {
+        STATE_COLORS[state as usize]
+    } else if colony == ColonyId(0) {
+        // colony 0 keeps the untinted ant texture
+        Color::WHITE
+    } else {
+        colony_color(colony)
+    }
+}
+
+fn ant_sprite_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    state_overlay: Res<StateOverlay>,
+    query: Query<(Entity, &ColonyId, &AntState), Added<Ant>>,
+) {
+    for (entity, &colony, &state) in query.iter() {
+        commands
+            .entity(entity)
+            .insert(Sprite {
+                color: ant_color(colony, state, &state_overlay),
+                custom_size: Some(Vec2::new(1.0, 1.0)),
+                ..Default::default()
+            })
+            .insert(asset_server.load::<Image, _>("ant.png"))
+            .insert(Visibility::default());
+    }
+}
+
+fn food_sprite_system(
+    mut commands: Commands,
+    query: Query<(Entity, &FoodSource), Added<FoodSource>>,
+) {
+    for (entity, source) in query.iter() {
+        commands
+            .entity(entity)
+            .insert(colored_sprite(food_color(source)))
+            .insert(Handle::<Image>::default())
+            .insert(Visibility::default());
+    }
+}
+
+/// Fades food sources as they are emptied, and brings them back as they regrow or respawn.
+fn food_amount_sprite_system(mut query: Query<(&FoodSource, &mut Sprite), Changed<FoodSource>>) {
+    for (source, mut sprite) in query.iter_mut() {
+        sprite.color = food_color(source);
+    }
+}
+
+/// Speck of food drawn on an ant while it carries some.
+#[derive(Component)]
+struct CarriedFoodSprite;
+
+/// Adds a `CarriedFoodSprite` to ants that picked up food and removes it once they drop it.
+fn carried_food_sprite_system(
+    mut commands: Commands,
+    ant_query: Query<(Entity, &Ant, Option<&Children>), Changed<Ant>>,
+    sprite_query: Query<(), With<CarriedFoodSprite>>,
+) {
+    for (entity, ant, children) in ant_query.iter() {
+        let sprite = children
+            .into_iter()
+            .flat_map(|children| children.iter())
+            .find(|&&child| sprite_query.get(child).is_ok());
+        match (ant.carrying_food, sprite) {
+            (true, None) => {
+                let sprite = commands
+                    .spawn_bundle(SpriteBundle {
+                        sprite: colored_sprite(FOOD_COLOR),
+                        transform: Transform::from_xyz(1.0, 0.0, 0.1),
+                        ..Default::default()
+                    })
+                    .insert(CarriedFoodSprite)
+                    .id();
+                commands.entity(entity).push_children(&[sprite]);
+            }
+            (false, Some(&sprite)) => commands.entity(sprite).despawn(),
+            _ => {}
+        }
+    }
+}
+
+fn home_sprite_system(mut commands: Commands, query: Query<(Entity, &ColonyId), Added<Home>>) {
+    for (entity, &colony) in query.iter() {
+        commands
+            .entity(entity)
+            .insert(colored_sprite(colony_color(colony)))
+            .insert(Handle::<Image>::default())
+            .insert(Visibility::default());
+    }
+}
+
+/// One segment of the drawn arena outline.
+#[derive(Component)]
+struct ArenaSprite;
+
+/// Redraws the arena outline whenever the `Arena` changes, faded for boundaries ants can cross.
+fn arena_sprite_system(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    query: Query<Entity, With<ArenaSprite>>,
+) {
+    if !arena.is_changed() {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+    let color = match arena.boundary {
+        Boundary::Solid => WALL_COLOR,
+        Boundary::Wrap | Boundary::Absorb => OPEN_BOUNDARY_COLOR,
+    };
+    let outline = arena.outline();
+    for (i, &start) in outline.iter().enumerate() {
+        let end = outline[(i + 1) % outline.len()];
+        commands
+            .spawn_bundle(line_sprite(start, end, WALL_THICKNESS, color))
+            .insert(ArenaSprite);
+    }
+}
+
+/// A straight line from `start` to `end`, drawn above the pheromone texture.
+fn line_sprite(start: Vec2, end: Vec2, thickness: f32, color: Color) -> SpriteBundle {
+    let segment = end - start;
+    SpriteBundle {
+        sprite: colored_sprite(color),
+        transform: Transform {
+            translation: ((start + end) / 2.0).extend(2.0),
+            rotation: Quat::from_rotation_z(segment.y.atan2(segment.x)),
+            scale: Vec3::new(segment.length() + thickness, thickness, 1.0),
+        },
+        ..Default::default()
+    }
+}
+
+/// F4 toggles tinting ants by their `AntState`.
+fn state_hotkey_system(keys: Res<Input<KeyCode>>, mut state_overlay: ResMut<StateOverlay>) {
+    if keys.just_pressed(KeyCode::F4) {
+        state_overlay.0 = !state_overlay.0;
+    }
+}
+
+/// Retints every ant when the state overlay is toggled, and afterwards only the ants that
+/// changed state.
+fn state_overlay_system(
+    state_overlay: Res<StateOverlay>,
+    mut state_changes: EventReader<AntStateChanged>,
+    mut ant_query: Query<(&ColonyId, &AntState, &mut Sprite), With<Ant>>,
+) {
+    if state_overlay.is_changed() {
+        for (&colony, &state, mut sprite) in ant_query.iter_mut() {
+            sprite.color = ant_color(colony, state, &state_overlay);
+        }
+        return;
+    }
+    if !state_overlay.0 {
+        return;
+    }
+    for state_change in state_changes.iter() {
+        if let Ok((&colony, _, mut sprite)) = ant_query.get_mut(state_change.ant) {
+            sprite.color = ant_color(colony, state_change.to, &state_overlay);
+        }
+    }
+}
+
+/// One line of the perception overlay.
+#[derive(Component)]
+struct PerceptionSprite;
+
+/// F3 toggles the perception overlay.
+fn perception_hotkey_system(
+    keys: Res<Input<KeyCode>>,
+    mut perception_overlay: ResMut<PerceptionOverlay>,
+) {
+    if keys.just_pressed(KeyCode::F3) {
+        perception_overlay.0 = !perception_overlay.0;
+    }
+}
+
+/// Redraws, every frame the overlay is on, a line from each ant to the food and the home it
+/// currently perceives.
+fn perception_overlay_system(
+    mut commands: Commands,
+    perception_overlay: Res<PerceptionOverlay>,
+    sprite_query: Query<Entity, With<PerceptionSprite>>,
+    ant_query: Query<(&Transform, &Sight), With<Ant>>,
+) {
+    for entity in sprite_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    if !perception_overlay.0 {
+        return;
+    }
+    for (transform, sight) in ant_query.iter() {
+        let pos = transform.translation.truncate();
+        for (target, color) in [(sight.food, FOOD_COLOR), (sight.home, HOME_COLOR)] {
+            if let Some(target) = target {
+                commands
+                    .spawn_bundle(line_sprite(pos, target, PERCEPTION_LINE_THICKNESS, color))
+                    .insert(PerceptionSprite);
+            }
+        }
+    }
+}
+
+fn trail_color(trail_type: &TrailType, colony: ColonyId) -> Color {
+    COLONY_TRAIL_COLORS[colony.0 as usize][*trail_type as usize]
+}
+
+/// Redraws the pheromone texture, blending each colony's trail colors by their strength relative
+/// to `trail.initial_strength`.
+fn pheromone_texture_system(
+    config: Res<Config>,
+    pheromone_field: Res<PheromoneField>,
+    pheromone_texture: Res<PheromoneTexture>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !pheromone_field.is_changed() {
+        return;
+    }
+    let image = match images.get_mut(&pheromone_texture.0) {
+        Some(image) => image,
+        None => return,
+    };
+    let initial_strength = config.get(&TRAIL_INITIAL_STRENGTH);
+    let (width, height) = (pheromone_field.width, pheromone_field.height);
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y * width + x) as usize;
+            let mut rgb = Vec3::ZERO;
+            let mut total: f32 = 0.0;
+            let mut alpha: f32 = 0.0;
+            for colony in (0..MAX_COLONIES as u32).map(ColonyId) {
+                for trail_type in TrailType::ALL {
+                    let strength = pheromone_field.channel(trail_type.channel(colony))[index];
+                    let a = (strength / initial_strength).min(1.0);
+                    let color = trail_color(&trail_type, colony);
+                    rgb += Vec3::new(color.r(), color.g(), color.b()) * a;
+                    total += a;
+                    alpha = alpha.max(a);
+                }
+            }
+            if total > 0.0 {
+                rgb /= total;
+            }
+            // image rows run top to bottom, field rows bottom to top
+            let texel = (((height - 1 - y) * width + x) * 4) as usize;
+            image.data[texel] = (rgb.x * 255.0) as u8;
+            image.data[texel + 1] = (rgb.y * 255.0) as u8;
+            image.data[texel + 2] = (rgb.z * 255.0) as u8;
+            image.data[texel + 3] = (alpha * 255.0) as u8;
+        }
+    }
+}
+
+fn collider_debug_render_system(
+    mut commands: Commands,
+    query: Query<Entity, Added<ColliderShapeComponent>>,
+) {
+    for entity in query.iter() {
+        commands
+            .entity(entity)
+            .insert(ColliderDebugRender::with_id(2));
+    }
+}
+
+/// Mirrors the ObstacleGrid into tilemap layer 0 of map 0, touching only the tiles that changed
+/// since the last sync.
+fn obstacle_tilemap_system(
+    mut commands: Commands,
+    obstacle_grid: Res<ObstacleGrid>,
+    mut synced_tiles: Local<Vec<bool>>,
+    mut map_query: MapQuery,
+) {
+    if !obstacle_grid.is_changed() {
+        return;
+    }
+    if synced_tiles.len() != obstacle_grid.tiles().len() {
+        *synced_tiles = vec![false; obstacle_grid.tiles().len()];
+    }
+    for (index, (synced, &obstacle)) in synced_tiles
+        .iter_mut()
+        .zip(obstacle_grid.tiles())
+        .enumerate()
+    {
+        if *synced == obstacle {
+            continue;
+        }
+        let grid_pos = obstacle_grid.tile_pos_from_index(index);
+        let tile_pos = TilePos(grid_pos.x, grid_pos.y);
+        if obstacle {
+            let _result = map_query.set_tile(
+                &mut commands,
+                tile_pos,
+                Tile {
+                    texture_index: 0,
+                    ..Default::default()
+                },
+                0u16,
+                0u16,
+            );
+        } else {
+            let _result = map_query.despawn_tile(&mut commands, tile_pos, 0u16, 0u16);
+        }
+        map_query.notify_chunk_for_tile(tile_pos, 0u16, 0u16);
+        *synced = obstacle;
+    }
+}
+
+fn window_to_world(position: Vec2, window: &Window, camera: &Transform) -> Vec3 {
+    let norm = Vec3::new(
+        position.x - window.width() / 2.,
+        position.y - window.height() / 2.,
+        0.,
+    );
+    let mut pos = *camera * norm;
+    pos.z = 0.0;
+    return pos;
+}
+
+fn to_tile_center(world_pos: Vec3, grid_size: f32) -> Vec3 {
+    Vec3::new(
+        (world_pos.x / grid_size).floor() * grid_size + grid_size / 2.0,
+        (world_pos.y / grid_size).floor() * grid_size + grid_size / 2.0,
+        0.0,
+    )
+}
+
+fn pos_in_transform(pos: &Vec3, transform: &Transform) -> bool {
+    pos.x > transform.translation.x - transform.scale.x / 2.0
+        && pos.x < transform.translation.x + transform.scale.x / 2.0
+        && pos.y > transform.translation.y - transform.scale.y / 2.0
+        && pos.y < transform.translation.y + transform.scale.y / 2.0
+}
+
+fn mouse_input_system(
+    mut commands: Commands,
+    buttons: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    mut editor_input: ResMut<EditorInput>,
+    mut obstacle_grid: ResMut<ObstacleGrid>,
+    mut next_food_id: ResMut<NextFoodId>,
+    config: Res<Config>,
+    arena: Res<Arena>,
+    transform_query: Query<&Transform, With<Camera>>,
+    home_query: Query<(Entity, &Home, &Transform)>,
+    food_query: Query<(Entity, &FoodSource, &Transform)>,
+    icon_query: Query<(&Icon, &Transform)>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    if let Some(cursor_pos) = window.cursor_position() {
+        let world_cursor_pos = window_to_world(cursor_pos, window, transform_query.single());
+
+        if buttons.just_pressed(MouseButton::Left) {
+            for (icon, transform) in icon_query.iter() {
+                if pos_in_transform(&world_cursor_pos, &transform) {
+                    editor_input.selected_icon = Some(*icon);
+                }
+            }
+        }
+        if arena.contains(world_cursor_pos.truncate()) {
+            match editor_input.selected_icon {
+                Some(Icon::SpawnObstacle) => {
+                    if let Some(tile_pos) = obstacle_grid.tile_pos_from_world_pos(&world_cursor_pos)
+                    {
+                        if buttons.pressed(MouseButton::Left) {
+                            if !obstacle_grid.is_obstacle(tile_pos) {
+                                obstacle_grid.set_obstacle(tile_pos, true);
+                            }
+                        } else if buttons.pressed(MouseButton::Right)
+                            && obstacle_grid.is_obstacle(tile_pos)
+                        {
+                            obstacle_grid.set_obstacle(tile_pos, false);
+                        }
+                    }
+                }
+                Some(Icon::SpawnFood) => {
+                    if buttons.pressed(MouseButton::Left) {
+                        if !food_query.iter().any(|(_, _, transform)| {
+                            pos_in_transform(&world_cursor_pos, &transform)
+                        }) {
+                            let single = FoodSource {
+                                amount: 1,
+                                capacity: 1,
+                                take: 1,
+                                ..FoodSource::new(editor_input.food_type, &config)
+                            };
+                            spawn_food_source(
+                                world_cursor_pos.truncate(),
+                                next_food_id.next(),
+                                single,
+                                &mut commands,
+                            );
+                        }
+                    } else if buttons.pressed(MouseButton::Right) {
+                        for (entity, _food, transform) in food_query.iter() {
+                            if pos_in_transform(&world_cursor_pos, &transform) {
+                                commands.entity(entity).despawn();
+                            }
+                        }
+                    }
+                }
+                Some(Icon::SpawnFoodSource) => {
+                    if buttons.just_pressed(MouseButton::Left) {
+                        if !food_query.iter().any(|(_, _, transform)| {
+                            pos_in_transform(&world_cursor_pos, &transform)
+                        }) {
+                            spawn_food_source(
+                                world_cursor_pos.truncate(),
+                                next_food_id.next(),
+                                FoodSource::new(editor_input.food_type, &config),
+                                &mut commands,
+                            );
+                        }
+                    } else if buttons.pressed(MouseButton::Right) {
+                        for (entity, _food, transform) in food_query.iter() {
+                            if pos_in_transform(&world_cursor_pos, &transform) {
+                                commands.entity(entity).despawn();
+                            }
+                        }
+                    }
+                }
+                Some(Icon::SpawnHome) => {
+                    if buttons.pressed(MouseButton::Left) {
+                        let tile_center = to_tile_center(world_cursor_pos, obstacle_grid.tile_size);
+                        if !home_query.iter().any(|(_, _, transform)| {
+                            pos_in_transform(&world_cursor_pos, &transform)
+                        }) {
+                            spawn_home(tile_center, editor_input.colony, &mut commands);
+                        }
+                    } else if buttons.pressed(MouseButton::Right) {
+                        for (entity, _home, transform) in home_query.iter() {
+                            if pos_in_transform(&world_cursor_pos, &transform) {
+                                commands.entity(entity).despawn();
+                            }
+                        }
+                    }
+                }
+                None => (),
+            }
+        }
+    }
+}
+
+/// Number keys 1 to `MAX_COLONIES` choose the colony the editor spawns homes for.
+fn colony_hotkey_system(keys: Res<Input<KeyCode>>, mut editor_input: ResMut<EditorInput>) {
+    let colony_keys = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4];
+    for (colony, key) in colony_keys.iter().enumerate().take(MAX_COLONIES) {
+        if keys.just_pressed(*key) {
+            editor_input.colony = ColonyId(colony as u32);
+        }
+    }
+}
+
+/// Z, X and C pick seeds, fruit or insects for the food sources placed next.
+fn food_type_hotkey_system(keys: Res<Input<KeyCode>>, mut editor_input: ResMut<EditorInput>) {
+    let food_type_keys = [KeyCode::Z, KeyCode::X, KeyCode::C];
+    for (food_type, key) in FoodType::ALL.iter().zip(food_type_keys.iter()) {
+        if keys.just_pressed(*key) {
+            editor_input.food_type = *food_type;
+        }
+    }
+}
+
+/// F5 saves the world to the scenario file given on the command line (or `scenario.ron`), F9
+/// loads it back.
+fn scenario_hotkey_system(world: &mut World) {
+    let keys = world.get_resource::<Input<KeyCode>>().unwrap();
+    let save = keys.just_pressed(KeyCode::F5);
+    let load = keys.just_pressed(KeyCode::F9);
+    if !save && !load {
+        return;
+    }
+    let path = world
+        .get_resource::<ScenarioFile>()
+        .unwrap()
+        .0
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SCENARIO_PATH));
+    let (action, result) = if save {
+        ("save", save_scenario(world, &path))
+    } else {
+        ("load", load_scenario(world, &path))
+    };
+    match result {
+        Ok(()) => println!("{}d scenario {}", action, path.display()),
+        Err(e) => println!("failed to {} scenario {}: {}", action, path.display(), e),
+    }
+}
+
+/// F6 checkpoints the running world to the snapshot file given on the command line (or
+/// `snapshot.ron`), F10 restores it.
+fn snapshot_hotkey_system(world: &mut World) {
+    let keys = world.get_resource::<Input<KeyCode>>().unwrap();
+    let save = keys.just_pressed(KeyCode::F6);
+    let load = keys.just_pressed(KeyCode::F10);
+    if !save && !load {
+        return;
+    }
+    let path = world
+        .get_resource::<SnapshotFile>()
+        .unwrap()
+        .0
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SNAPSHOT_PATH));
+    let (action, result) = if save {
+        ("save", save_snapshot(world, &path))
+    } else {
+        ("restore", load_snapshot(world, &path))
+    };
+    match result {
+        Ok(()) => println!("{}d snapshot {}", action, path.display()),
+        Err(e) => println!("failed to {} snapshot {}: {}", action, path.display(), e),
+    }
+}
+
+pub fn set_texture_filters_to_nearest(
+    mut texture_events: EventReader<AssetEvent<Image>>,
+    mut textures: ResMut<Assets<Image>>,
+) {
+    // quick and dirty, run this for all textures anytime a texture is created.
+    for event in texture_events.iter() {
+        match event {
+            AssetEvent::Created { handle } => {
+                if let Some(mut texture) = textures.get_mut(handle) {
+                    texture.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+                        | TextureUsages::COPY_SRC
+                        | TextureUsages::COPY_DST;
+                }
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use crate::ants_plugin::{OBSTACLE_TILE_SIZE, PHEROMONE_CELL_SIZE};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of segments the outline of a circular arena is approximated with.
+const CIRCLE_SEGMENTS: usize = 64;
+/// How far inside the boundary ants are put back after crossing it.
+const BOUNDARY_MARGIN: f32 = 0.5;
+/// Largest arena width or height, in world units, keeping the obstacle and pheromone grids
+/// covering it to a sensible size.
+pub const MAX_SIZE: f32 = 5000.0;
+
+/// The region the simulation takes place in, centered on the world origin.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Arena {
+    /// Width and height of the bounding rectangle, in world units. Obstacle and pheromone grids
+    /// cover this rectangle.
+    pub size: [f32; 2],
+    pub shape: ArenaShape,
+    pub boundary: Boundary,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ArenaShape {
+    Rectangle,
+    /// The ellipse inscribed in the bounding rectangle.
+    Circle,
+    /// Vertices in world units, in order around the outline. Must lie within the bounding
+    /// rectangle.
+    Polygon(Vec<[f32; 2]>),
+}
+
+/// What happens to an ant that reaches the arena boundary.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Boundary {
+    /// Ants bounce or slide along it.
+    Solid,
+    /// Ants reappear on the opposite side, as on a torus.
+    Wrap,
+    /// Ants leaving the arena are removed from the simulation.
+    Absorb,
+}
+
+impl Default for Arena {
+    fn default() -> Arena {
+        Arena {
+            size: [900.0, 600.0],
+            shape: ArenaShape::Rectangle,
+            boundary: Boundary::Solid,
+        }
+    }
+}
+
+impl Arena {
+    pub fn half_size(&self) -> Vec2 {
+        Vec2::from(self.size) / 2.0
+    }
+
+    pub fn contains(&self, pos: Vec2) -> bool {
+        let half_size = self.half_size();
+        match &self.shape {
+            ArenaShape::Rectangle => pos.x.abs() < half_size.x && pos.y.abs() < half_size.y,
+            ArenaShape::Circle => (pos / half_size).length_squared() < 1.0,
+            ArenaShape::Polygon(vertices) => {
+                // even-odd rule
+                let mut inside = false;
+                for (a, b) in polygon_edges(vertices) {
+                    if (a.y > pos.y) != (b.y > pos.y)
+                        && pos.x < a.x + (pos.y - a.y) / (b.y - a.y) * (b.x - a.x)
+                    {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    /// Vertices of the closed boundary outline, without repeating the first one.
+    pub fn outline(&self) -> Vec<Vec2> {
+        let half_size = self.half_size();
+        match &self.shape {
+            ArenaShape::Rectangle => vec![
+                Vec2::new(-half_size.x, -half_size.y),
+                Vec2::new(half_size.x, -half_size.y),
+                Vec2::new(half_size.x, half_size.y),
+                Vec2::new(-half_size.x, half_size.y),
+            ],
+            ArenaShape::Circle => (0..CIRCLE_SEGMENTS)
+                .map(|i| {
+                    let angle = i as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * std::f32::consts::PI;
+                    Vec2::new(angle.cos(), angle.sin()) * half_size
+                })
+                .collect(),
+            ArenaShape::Polygon(vertices) => vertices.iter().map(|&v| Vec2::from(v)).collect(),
+        }
+    }
+
+    /// Returns the point of the boundary closest to `pos` and the unit normal there pointing
+    /// from `pos` towards the inside, for a `pos` outside the arena.
+    pub fn nearest_boundary(&self, pos: Vec2) -> (Vec2, Vec2) {
+        let outline = self.outline();
+        let mut nearest = (Vec2::ZERO, Vec2::ZERO);
+        let mut nearest_distance = f32::INFINITY;
+        for (a, b) in polygon_edges(&outline) {
+            let edge = b - a;
+            let t = ((pos - a).dot(edge) / edge.length_squared()).clamp(0.0, 1.0);
+            let point = a + edge * t;
+            let distance = point.distance_squared(pos);
+            if distance < nearest_distance {
+                nearest_distance = distance;
+                nearest = (point, (point - pos).normalize_or_zero());
+            }
+        }
+        nearest
+    }
+
+    /// Moves `pos` just inside the boundary if it lies outside.
+    pub fn clamp_inside(&self, pos: Vec2) -> Vec2 {
+        if self.contains(pos) {
+            return pos;
+        }
+        let (point, normal) = self.nearest_boundary(pos);
+        point + normal * BOUNDARY_MARGIN
+    }
+
+    /// Where an ant at `pos`, outside the arena, reappears with `Boundary::Wrap`. Rectangles wrap
+    /// like a torus; other shapes send the ant to the point mirrored through the center.
+    pub fn wrap(&self, pos: Vec2) -> Vec2 {
+        match &self.shape {
+            ArenaShape::Rectangle => {
+                let size = Vec2::from(self.size);
+                let half_size = self.half_size();
+                Vec2::new(
+                    (pos.x + half_size.x).rem_euclid(size.x) - half_size.x,
+                    (pos.y + half_size.y).rem_euclid(size.y) - half_size.y,
+                )
+            }
+            _ => self.clamp_inside(-pos),
+        }
+    }
+}
+
+fn polygon_edges<T: Copy + Into<Vec2>>(vertices: &[T]) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+    vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .map(|(&a, &b)| (a.into(), b.into()))
+}
+
+#[derive(Debug)]
+pub struct ParseArenaError(String);
+
+impl fmt::Display for ParseArenaError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ParseArenaError {}
+
+impl FromStr for ArenaShape {
+    type Err = ParseArenaError;
+
+    /// Parses `rectangle` or `circle`. Polygons can only be given in a scenario file.
+    fn from_str(s: &str) -> Result<ArenaShape, ParseArenaError> {
+        match s {
+            "rectangle" => Ok(ArenaShape::Rectangle),
+            "circle" => Ok(ArenaShape::Circle),
+            _ => Err(ParseArenaError(format!(
+                "unknown arena shape '{}' (expected rectangle or circle)",
+                s
+            ))),
+        }
+    }
+}
+
+impl FromStr for Boundary {
+    type Err = ParseArenaError;
+
+    fn from_str(s: &str) -> Result<Boundary, ParseArenaError> {
+        match s {
+            "solid" => Ok(Boundary::Solid),
+            "wrap" => Ok(Boundary::Wrap),
+            "absorb" => Ok(Boundary::Absorb),
+            _ => Err(ParseArenaError(format!(
+                "unknown arena boundary '{}' (expected solid, wrap or absorb)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Parses an arena size written as `WIDTHxHEIGHT`. Each side must hold at least one obstacle tile
+/// and pheromone cell, and be at most `MAX_SIZE`.
+pub fn parse_size(s: &str) -> Result<[f32; 2], ParseArenaError> {
+    let error = || {
+        ParseArenaError(format!(
+            "invalid arena size '{}' (expected WIDTHxHEIGHT)",
+            s
+        ))
+    };
+    let (width, height) = s.split_once('x').ok_or_else(error)?;
+    let size = [
+        width.parse::<f32>().map_err(|_| error())?,
+        height.parse::<f32>().map_err(|_| error())?,
+    ];
+    let min_size = OBSTACLE_TILE_SIZE.max(PHEROMONE_CELL_SIZE);
+    // also rejects NaN
+    if !size.iter().all(|side| (min_size..=MAX_SIZE).contains(side)) {
+        return Err(ParseArenaError(format!(
+            "arena size '{}' is out of range (each side must be between {} and {})",
+            s, min_size, MAX_SIZE
+        )));
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_accepts_sizes_in_range() {
+        assert_eq!(parse_size("900x600").unwrap(), [900.0, 600.0]);
+        assert_eq!(parse_size("10x5000").unwrap(), [10.0, 5000.0]);
+    }
+
+    #[test]
+    fn parse_size_rejects_sizes_out_of_range() {
+        for size in [
+            "NaNx100",
+            "100xNaN",
+            "infx100",
+            "-infx100",
+            "1e12x1e12",
+            "0x100",
+            "-100x100",
+            "5x100",
+            "100x5001",
+        ] {
+            assert!(parse_size(size).is_err(), "{}", size);
+        }
+    }
+
+    #[test]
+    fn parse_size_rejects_malformed_sizes() {
+        for size in ["", "100", "100x", "x100", "100,100", "ax100"] {
+            assert!(parse_size(size).is_err(), "{}", size);
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use crate::ants_plugin::{Ant, ColonyId, Home, TrailSensors, TrailType, HOME_SIZE, TIME_STEP};
+use crate::arena::Arena;
+use crate::caste::Caste;
+use crate::config::{Config, ConfigKey};
+use crate::helpers::obstacle_grid::ObstacleGrid;
+use crate::helpers::pheromone_field::PheromoneField;
+use crate::helpers::spatial_index::SpatialIndex;
+use crate::vision::Sight;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub const ANT_TRAIL_THRESHOLD: ConfigKey<f32> = ConfigKey {
+    key: "ant.trail_threshold",
+    default: 0.1,
+    min: Some(0.0),
+    max: None,
+    units: "",
+    description: "Trail strength an exploring ant must sense to start following it",
+};
+
+pub const ANT_REST_TIME: ConfigKey<f32> = ConfigKey {
+    key: "ant.rest_time",
+    default: 2.0,
+    min: Some(0.0),
+    max: None,
+    units: "s",
+    description: "How long a hungry ant rests at home",
+};
+
+pub const ANT_FLEE_TIME: ConfigKey<f32> = ConfigKey {
+    key: "ant.flee_time",
+    default: 1.0,
+    min: Some(0.0),
+    max: None,
+    units: "s",
+    description: "Shortest time an ant flees from a threat",
+};
+
+pub fn register_config(config: &mut Config) {
+    config.register(&ANT_TRAIL_THRESHOLD);
+    config.register(&ANT_REST_TIME);
+    config.register(&ANT_FLEE_TIME);
+    for state in AntState::ALL {
+        if let Some((speed, wander)) = state.config_keys() {
+            config.register(&speed);
+            config.register(&wander);
+        }
+    }
+}
+
+fn multiplier_key(key: &'static str, default: f32, description: &'static str) -> ConfigKey<f32> {
+    ConfigKey {
+        key,
+        default,
+        min: Some(0.0),
+        max: None,
+        units: "",
+        description,
+    }
+}
+
+/// What an ant is doing. `ant_state_system` moves every ant between states before steering, and
+/// the state then decides where the ant heads, how fast it walks and which trail it lays.
+#[derive(
+    Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize,
+)]
+pub enum AntState {
+    /// Wandering in search of food or a food trail.
+    Exploring,
+    /// Following a trail to food, or heading for food it senses.
+    FollowingTrail,
+    /// Bringing food home along the trail laid while gathering.
+    CarryingFood,
+    /// Heading home hungry, to eat from the food store.
+    ReturningHome,
+    /// Standing still at home after coming back hungry, for `ant.rest_time` seconds.
+    Resting,
+    /// Running away from an ant of another colony, for at least `ant.flee_time` seconds. Soldiers
+    /// stand their ground.
+    Fleeing,
+    /// Starved or absorbed by the arena boundary; the ant is despawned right away.
+    Dead,
+}
+
+impl Default for AntState {
+    fn default() -> AntState {
+        AntState::Exploring
+    }
+}
+
+/// How an ant in some `AntState` moves and marks its way.
+pub struct StateParams {
+    /// Multiple of `ant.speed` the ant walks at.
+    pub speed: f32,
+    /// Multiple of `ant.wandering` the ant randomly turns by.
+    pub wander: f32,
+    /// Trail the ant steers along when it senses no target.
+    pub follows: Option<TrailType>,
+    /// Trail the ant lays as it walks.
+    pub drops: Option<TrailType>,
+}
+
+impl AntState {
+    pub const ALL: [AntState; 7] = [
+        AntState::Exploring,
+        AntState::FollowingTrail,
+        AntState::CarryingFood,
+        AntState::ReturningHome,
+        AntState::Resting,
+        AntState::Fleeing,
+        AntState::Dead,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AntState::Exploring => "exploring",
+            AntState::FollowingTrail => "following_trail",
+            AntState::CarryingFood => "carrying_food",
+            AntState::ReturningHome => "returning_home",
+            AntState::Resting => "resting",
+            AntState::Fleeing => "fleeing",
+            AntState::Dead => "dead",
+        }
+    }
+
+    /// Config keys of the state's speed and wander multipliers. Dead ants have none.
+    pub fn config_keys(&self) -> Option<(ConfigKey<f32>, ConfigKey<f32>)> {
+        let (speed_key, wander_key, speed, wander) = match self {
+            AntState::Exploring => ("state.exploring.speed", "state.exploring.wander", 1.0, 1.0),
+            AntState::FollowingTrail => (
+                "state.following_trail.speed",
+                "state.following_trail.wander",
+                1.0,
+                0.5,
+            ),
+            AntState::CarryingFood => (
+                "state.carrying_food.speed",
+                "state.carrying_food.wander",
+                1.0,
+                1.0,
+            ),
+            AntState::ReturningHome => (
+                "state.returning_home.speed",
+                "state.returning_home.wander",
+                1.0,
+                1.0,
+            ),
+            AntState::Resting => ("state.resting.speed", "state.resting.wander", 0.0, 0.0),
+            AntState::Fleeing => ("state.fleeing.speed", "state.fleeing.wander", 1.5, 0.5),
+            AntState::Dead => return None,
+        };
+        Some((
+            multiplier_key(speed_key, speed, "Speed multiplier of ants in this state"),
+            multiplier_key(
+                wander_key,
+                wander,
+                "Wander multiplier of ants in this state",
+            ),
+        ))
+    }
+
+    pub fn params(&self, config: &Config) -> StateParams {
+        let (speed, wander) = match self.config_keys() {
+            Some((speed_key, wander_key)) => (config.get(&speed_key), config.get(&wander_key)),
+            None => (0.0, 0.0),
+        };
+        // searching ants lay the trail back home, ants with food the trail back to it
+        let (follows, drops) = match self {
+            AntState::Exploring | AntState::FollowingTrail => {
+                (Some(TrailType::GotFood), Some(TrailType::Gathering))
+            }
+            AntState::CarryingFood => (Some(TrailType::Gathering), Some(TrailType::GotFood)),
+            AntState::ReturningHome => (Some(TrailType::Gathering), None),
+            AntState::Resting | AntState::Fleeing | AntState::Dead => (None, None),
+        };
+        StateParams {
+            speed,
+            wander,
+            follows,
+            drops,
+        }
+    }
+}
+
+/// Simulation steps an ant has spent in its current `AntState`.
+#[derive(Component, Clone, Copy, Default)]
+pub struct StateTicks(pub usize);
+
+/// Sent whenever `ant` of `colony` changes `AntState`, including to `Dead` right before it is
+/// despawned.
+pub struct AntStateChanged {
+    pub ant: Entity,
+    pub colony: ColonyId,
+    pub from: AntState,
+    pub to: AntState,
+}
+
+/// What `next_state` decides on.
+struct Situation<'a> {
+    ticks: usize,
+    carrying_food: bool,
+    hungry: bool,
+    at_home: bool,
+    senses_trail: bool,
+    flees: bool,
+    sight: &'a Sight,
+}
+
+/// Transition table of the state machine.
+fn next_state(
+    current: AntState,
+    situation: &Situation,
+    rest_ticks: usize,
+    flee_ticks: usize,
+) -> AntState {
+    match current {
+        AntState::Dead => return AntState::Dead,
+        _ if situation.flees && situation.sight.threat.is_some() => return AntState::Fleeing,
+        AntState::Fleeing if situation.ticks < flee_ticks => return AntState::Fleeing,
+        AntState::Resting if situation.ticks < rest_ticks => return AntState::Resting,
+        _ => {}
+    }
+    if situation.carrying_food {
+        AntState::CarryingFood
+    } else if situation.at_home && (current == AntState::ReturningHome || situation.hungry) {
+        AntState::Resting
+    } else if situation.hungry {
+        AntState::ReturningHome
+    } else if situation.sight.food.is_some() || situation.senses_trail {
+        AntState::FollowingTrail
+    } else {
+        AntState::Exploring
+    }
+}
+
+/// Moves every living ant to the state its food, hunger and senses call for, and reports each
+/// change as an `AntStateChanged`.
+pub fn ant_state_system(
+    config: Res<Config>,
+    pheromone_field: Res<PheromoneField>,
+    obstacle_grid: Res<ObstacleGrid>,
+    arena: Res<Arena>,
+    home_index: Res<SpatialIndex<Home>>,
+    home_query: Query<&ColonyId, With<Home>>,
+    mut ant_query: Query<(
+        Entity,
+        &ColonyId,
+        &Caste,
+        &Ant,
+        &Sight,
+        &Transform,
+        &mut AntState,
+        &mut StateTicks,
+    )>,
+    mut state_changes: EventWriter<AntStateChanged>,
+) {
+    let rest_ticks = (config.get(&ANT_REST_TIME) / TIME_STEP) as usize;
+    let flee_ticks = (config.get(&ANT_FLEE_TIME) / TIME_STEP) as usize;
+    let trail_threshold = config.get(&ANT_TRAIL_THRESHOLD);
+    let trail_sensors = TrailSensors::new(&config);
+    for (entity, &colony, caste, ant, sight, transform, mut state, mut ticks) in
+        ant_query.iter_mut()
+    {
+        let ant_pos = transform.translation.truncate();
+        let at_home = home_index
+            .query_radius(ant_pos, HOME_SIZE)
+            .into_iter()
+            .any(|(home, _)| home_query.get(home).map_or(false, |&c| c == colony));
+        let sensed: f32 = trail_sensors
+            .sense(
+                transform,
+                TrailType::GotFood.channel(colony),
+                &pheromone_field,
+                &obstacle_grid,
+                &arena,
+            )
+            .iter()
+            .sum();
+        let situation = Situation {
+            ticks: ticks.0,
+            carrying_food: ant.carrying_food,
+            hungry: ant.is_hungry(&config),
+            at_home,
+            senses_trail: sensed > trail_threshold,
+            flees: caste.flees(),
+            sight,
+        };
+        let next = next_state(*state, &situation, rest_ticks, flee_ticks);
+        if next == *state {
+            ticks.0 += 1;
+        } else {
+            state_changes.send(AntStateChanged {
+                ant: entity,
+                colony,
+                from: *state,
+                to: next,
+            });
+            *state = next;
+            ticks.0 = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REST_TICKS: usize = 10;
+    const FLEE_TICKS: usize = 5;
+
+    fn situation(sight: &Sight) -> Situation<'_> {
+        Situation {
+            ticks: 100,
+            carrying_food: false,
+            hungry: false,
+            at_home: false,
+            senses_trail: false,
+            flees: true,
+            sight,
+        }
+    }
+
+    fn next(current: AntState, situation: &Situation) -> AntState {
+        next_state(current, situation, REST_TICKS, FLEE_TICKS)
+    }
+
+    #[test]
+    fn dead_ants_stay_dead() {
+        let sight = Sight {
+            threat: Some(Vec2::ZERO),
+            ..Default::default()
+        };
+        let situation = Situation {
+            carrying_food: true,
+            ..situation(&sight)
+        };
+        assert_eq!(next(AntState::Dead, &situation), AntState::Dead);
+    }
+
+    #[test]
+    fn threats_override_everything_else() {
+        let sight = Sight {
+            threat: Some(Vec2::ZERO),
+            food: Some(Vec2::ZERO),
+            ..Default::default()
+        };
+        let situation = Situation {
+            ticks: 0,
+            carrying_food: true,
+            hungry: true,
+            at_home: true,
+            ..situation(&sight)
+        };
+        for state in AntState::ALL {
+            if state != AntState::Dead {
+                assert_eq!(next(state, &situation), AntState::Fleeing, "{:?}", state);
+            }
+        }
+    }
+
+    #[test]
+    fn castes_that_hold_their_ground_ignore_threats() {
+        let sight = Sight {
+            threat: Some(Vec2::ZERO),
+            ..Default::default()
+        };
+        let situation = Situation {
+            flees: false,
+            ..situation(&sight)
+        };
+        assert_eq!(next(AntState::Exploring, &situation), AntState::Exploring);
+        assert_eq!(next(AntState::Fleeing, &situation), AntState::Exploring);
+    }
+
+    #[test]
+    fn fleeing_and_resting_last_their_configured_time() {
+        let sight = Sight::default();
+        let early = Situation {
+            ticks: FLEE_TICKS - 1,
+            ..situation(&sight)
+        };
+        assert_eq!(next(AntState::Fleeing, &early), AntState::Fleeing);
+        let done = Situation {
+            ticks: FLEE_TICKS,
+            ..situation(&sight)
+        };
+        assert_eq!(next(AntState::Fleeing, &done), AntState::Exploring);
+
+        let early = Situation {
+            ticks: REST_TICKS - 1,
+            hungry: true,
+            at_home: true,
+            ..situation(&sight)
+        };
+        assert_eq!(next(AntState::Resting, &early), AntState::Resting);
+        let done = Situation {
+            ticks: REST_TICKS,
+            ..situation(&sight)
+        };
+        assert_eq!(next(AntState::Resting, &done), AntState::Exploring);
+    }
+
+    #[test]
+    fn carrying_food_comes_before_hunger() {
+        let sight = Sight::default();
+        let situation = Situation {
+            carrying_food: true,
+            hungry: true,
+            at_home: true,
+            ..situation(&sight)
+        };
+        assert_eq!(
+            next(AntState::ReturningHome, &situation),
+            AntState::CarryingFood
+        );
+    }
+
+    #[test]
+    fn hungry_ants_head_home_and_rest_there() {
+        let sight = Sight::default();
+        let away = Situation {
+            hungry: true,
+            ..situation(&sight)
+        };
+        assert_eq!(next(AntState::Exploring, &away), AntState::ReturningHome);
+        let home = Situation {
+            hungry: true,
+            at_home: true,
+            ..situation(&sight)
+        };
+        assert_eq!(next(AntState::Exploring, &home), AntState::Resting);
+        // an ant that came home hungry rests even once it is no longer hungry
+        let fed = Situation {
+            at_home: true,
+            ..situation(&sight)
+        };
+        assert_eq!(next(AntState::ReturningHome, &fed), AntState::Resting);
+        assert_eq!(next(AntState::Exploring, &fed), AntState::Exploring);
+    }
+
+    #[test]
+    fn food_or_trail_is_followed_otherwise_ants_explore() {
+        let nothing = Sight::default();
+        assert_eq!(
+            next(AntState::FollowingTrail, &situation(&nothing)),
+            AntState::Exploring
+        );
+        let food = Sight {
+            food: Some(Vec2::ZERO),
+            ..Default::default()
+        };
+        assert_eq!(
+            next(AntState::Exploring, &situation(&food)),
+            AntState::FollowingTrail
+        );
+        let trail = Situation {
+            senses_trail: true,
+            ..situation(&nothing)
+        };
+        assert_eq!(next(AntState::Exploring, &trail), AntState::FollowingTrail);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::config::{Config, ConfigKey};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Units and description of every profile entry, in `CasteProfile` field order.
+const PROFILE_ENTRIES: [(&str, &str); 5] = [
+    ("", "Multiple of ant.speed ants of this caste walk at"),
+    ("", "Multiple of ant.wandering ants of this caste turn by"),
+    (
+        "world units",
+        "Food perception radius of ants of this caste",
+    ),
+    (
+        "world units",
+        "Nest perception radius of ants of this caste",
+    ),
+    ("", "Share of nest births, relative to the other castes"),
+];
+
+/// The role an ant is born into, which picks its parameter profile.
+#[derive(
+    Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize,
+)]
+pub enum Caste {
+    /// Fast, wide ranging searchers with a keen sense for food.
+    Scout,
+    /// The bulk of the colony, carrying food home.
+    Forager,
+    /// Slow and steady; the only caste that doesn't flee other colonies.
+    Soldier,
+    /// Stay close to home, which they sense from far away.
+    Nurse,
+}
+
+/// Parameters of a caste, read from the `caste.<name>.*` config entries.
+pub struct CasteProfile {
+    /// Multiple of `ant.speed` the caste walks at.
+    pub speed: f32,
+    /// Multiple of `ant.wandering` the caste randomly turns by.
+    pub wandering: f32,
+    /// `Ant::food_perception` of ants born into the caste.
+    pub food_perception: f32,
+    /// `Ant::nest_perception` of ants born into the caste.
+    pub nest_perception: f32,
+    /// Share of the nest's births, relative to the other castes' ratios.
+    pub ratio: f32,
+}
+
+pub fn register_config(config: &mut Config) {
+    for caste in Caste::ALL {
+        for key in caste.config_keys() {
+            config.register(&key);
+        }
+    }
+}
+
+impl Caste {
+    pub const ALL: [Caste; 4] = [Caste::Scout, Caste::Forager, Caste::Soldier, Caste::Nurse];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Caste::Scout => "scout",
+            Caste::Forager => "forager",
+            Caste::Soldier => "soldier",
+            Caste::Nurse => "nurse",
+        }
+    }
+
+    /// Config keys of the profile, in `CasteProfile` field order.
+    pub fn config_keys(&self) -> [ConfigKey<f32>; 5] {
+        let mut entries = PROFILE_ENTRIES.iter();
+        self.key_defaults().map(|(key, default)| {
+            let (units, description) = entries.next().unwrap();
+            ConfigKey {
+                key,
+                default,
+                min: Some(0.0),
+                max: None,
+                units,
+                description,
+            }
+        })
+    }
+
+    fn key_defaults(&self) -> [(&'static str, f32); 5] {
+        match self {
+            Caste::Scout => [
+                ("caste.scout.speed", 1.3),
+                ("caste.scout.wandering", 1.5),
+                ("caste.scout.food_perception", 50.0),
+                ("caste.scout.nest_perception", 60.0),
+                ("caste.scout.ratio", 0.2),
+            ],
+            Caste::Forager => [
+                ("caste.forager.speed", 1.0),
+                ("caste.forager.wandering", 1.0),
+                ("caste.forager.food_perception", 30.0),
+                ("caste.forager.nest_perception", 60.0),
+                ("caste.forager.ratio", 0.6),
+            ],
+            Caste::Soldier => [
+                ("caste.soldier.speed", 0.8),
+                ("caste.soldier.wandering", 0.5),
+                ("caste.soldier.food_perception", 20.0),
+                ("caste.soldier.nest_perception", 80.0),
+                ("caste.soldier.ratio", 0.1),
+            ],
+            Caste::Nurse => [
+                ("caste.nurse.speed", 0.6),
+                ("caste.nurse.wandering", 0.5),
+                ("caste.nurse.food_perception", 15.0),
+                ("caste.nurse.nest_perception", 100.0),
+                ("caste.nurse.ratio", 0.1),
+            ],
+        }
+    }
+
+    pub fn profile(&self, config: &Config) -> CasteProfile {
+        let [speed, wandering, food_perception, nest_perception, ratio] =
+            self.config_keys().map(|key| config.get(&key));
+        CasteProfile {
+            speed,
+            wandering,
+            food_perception,
+            nest_perception,
+            ratio,
+        }
+    }
+
+    /// Whether ants of the caste run from ants of other colonies.
+    pub fn flees(&self) -> bool {
+        *self != Caste::Soldier
+    }
+
+    /// Picks the caste of a newborn ant by the `caste.<name>.ratio` entries, given a uniform
+    /// `roll` in `[0, 1)`. Falls back to foragers when every ratio is zero.
+    pub fn choose(config: &Config, roll: f32) -> Caste {
+        let ratios = Caste::ALL.map(|caste| caste.profile(config).ratio.max(0.0));
+        let total: f32 = ratios.iter().sum();
+        if total <= 0.0 {
+            return Caste::Forager;
+        }
+        let mut threshold = roll * total;
+        for (caste, ratio) in Caste::ALL.iter().zip(ratios.iter()) {
+            if threshold < *ratio {
+                return *caste;
+            }
+            threshold -= ratio;
+        }
+        // rounding can leave a sliver past the last caste with a share
+        *Caste::ALL
+            .iter()
+            .zip(ratios.iter())
+            .rev()
+            .find(|(_, &ratio)| ratio > 0.0)
+            .unwrap()
+            .0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(ratios: [f32; 4]) -> Config {
+        let mut config = Config::default();
+        register_config(&mut config);
+        for (caste, ratio) in Caste::ALL.iter().zip(ratios) {
+            let [.., ratio_key] = caste.config_keys();
+            config.set(&ratio_key, ratio).unwrap();
+        }
+        config
+    }
+
+    #[test]
+    fn choose_splits_rolls_by_ratio() {
+        let config = config([1.0, 2.0, 0.0, 1.0]);
+        for (roll, caste) in [
+            (0.0, Caste::Scout),
+            (0.24, Caste::Scout),
+            (0.25, Caste::Forager),
+            (0.74, Caste::Forager),
+            (0.75, Caste::Nurse),
+            (0.99, Caste::Nurse),
+        ] {
+            assert_eq!(Caste::choose(&config, roll), caste, "{}", roll);
+        }
+    }
+
+    #[test]
+    fn choose_skips_castes_without_a_share() {
+        let config = config([1.0, 0.0, 0.0, 1.0]);
+        for i in 0..100 {
+            let caste = Caste::choose(&config, i as f32 / 100.0);
+            assert!(matches!(caste, Caste::Scout | Caste::Nurse), "{:?}", caste);
+        }
+    }
+
+    #[test]
+    fn choose_gives_rolls_rounded_up_to_the_last_caste_with_a_share() {
+        let config = config([1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(Caste::choose(&config, 1.0), Caste::Forager);
+    }
+
+    #[test]
+    fn choose_falls_back_to_foragers() {
+        let config = config([0.0; 4]);
+        assert_eq!(Caste::choose(&config, 0.5), Caste::Forager);
+    }
+}
@@ -0,0 +1,861 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Prefix of the environment variables overriding config entries: `ant.speed` is overridden by
+/// `ANTS_SIM_ANT_SPEED`.
+pub const ENV_PREFIX: &str = "ANTS_SIM_";
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ConfigValue {
+    Int(i32),
+    Float(f32),
+    String(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConfigType {
+    Int,
+    Float,
+    String,
+}
+
+impl ConfigValue {
+    pub fn config_type(&self) -> ConfigType {
+        match self {
+            ConfigValue::Int(_) => ConfigType::Int,
+            ConfigValue::Float(_) => ConfigType::Float,
+            ConfigValue::String(_) => ConfigType::String,
+        }
+    }
+
+    /// The value as a number, for range checks. Strings have none.
+    fn number(&self) -> Option<f64> {
+        match self {
+            ConfigValue::Int(i) => Some(*i as f64),
+            ConfigValue::Float(f) => Some(*f as f64),
+            ConfigValue::String(_) => None,
+        }
+    }
+
+    /// Parses `text` as a value of `config_type`.
+    pub fn parse(config_type: ConfigType, text: &str) -> Option<ConfigValue> {
+        match config_type {
+            ConfigType::Int => text.parse().ok().map(ConfigValue::Int),
+            ConfigType::Float => text.parse().ok().map(ConfigValue::Float),
+            ConfigType::String => Some(ConfigValue::String(text.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigValue::Int(i) => write!(fmt, "{}", i),
+            ConfigValue::Float(f) => write!(fmt, "{}", f),
+            ConfigValue::String(s) => write!(fmt, "{}", s),
+        }
+    }
+}
+
+impl fmt::Display for ConfigType {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigType::Int => write!(fmt, "int"),
+            ConfigType::Float => write!(fmt, "float"),
+            ConfigType::String => write!(fmt, "string"),
+        }
+    }
+}
+
+/// A Rust type config entries can be read as. Integer-like types are stored as `Int`.
+pub trait ConfigPrimitive: Copy {
+    const TYPE: ConfigType;
+    /// Range every value of the type must lie in, besides the key's own.
+    const BOUNDS: (Option<f64>, Option<f64>) = (None, None);
+
+    fn from_value(value: &ConfigValue) -> Option<Self>;
+    fn to_value(self) -> ConfigValue;
+    fn to_f64(self) -> f64;
+}
+
+impl ConfigPrimitive for f32 {
+    const TYPE: ConfigType = ConfigType::Float;
+
+    fn from_value(value: &ConfigValue) -> Option<f32> {
+        match value {
+            ConfigValue::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    fn to_value(self) -> ConfigValue {
+        ConfigValue::Float(self)
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl ConfigPrimitive for i32 {
+    const TYPE: ConfigType = ConfigType::Int;
+
+    fn from_value(value: &ConfigValue) -> Option<i32> {
+        match value {
+            ConfigValue::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn to_value(self) -> ConfigValue {
+        ConfigValue::Int(self)
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl ConfigPrimitive for usize {
+    const TYPE: ConfigType = ConfigType::Int;
+    const BOUNDS: (Option<f64>, Option<f64>) = (Some(0.0), None);
+
+    fn from_value(value: &ConfigValue) -> Option<usize> {
+        match value {
+            ConfigValue::Int(i) if *i >= 0 => Some(*i as usize),
+            _ => None,
+        }
+    }
+
+    fn to_value(self) -> ConfigValue {
+        ConfigValue::Int(self as i32)
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl ConfigPrimitive for bool {
+    const TYPE: ConfigType = ConfigType::Int;
+    const BOUNDS: (Option<f64>, Option<f64>) = (Some(0.0), Some(1.0));
+
+    fn from_value(value: &ConfigValue) -> Option<bool> {
+        match value {
+            ConfigValue::Int(0) => Some(false),
+            ConfigValue::Int(1) => Some(true),
+            _ => None,
+        }
+    }
+
+    fn to_value(self) -> ConfigValue {
+        ConfigValue::Int(self as i32)
+    }
+
+    fn to_f64(self) -> f64 {
+        self as i32 as f64
+    }
+}
+
+/// Declaration of a config entry: its key, type, default and allowed range, plus units and a
+/// description for the console. Declared once, as a constant next to the code that reads it, and
+/// passed both to `Config::register` and to the typed `Config::get`.
+pub struct ConfigKey<T> {
+    pub key: &'static str,
+    pub default: T,
+    pub min: Option<T>,
+    pub max: Option<T>,
+    /// Empty for plain numbers.
+    pub units: &'static str,
+    pub description: &'static str,
+}
+
+impl<T: ConfigPrimitive> ConfigKey<T> {
+    /// The declaration without its Rust type, with the range of `T` folded into its own.
+    pub fn spec(&self) -> ConfigSpec {
+        let (type_min, type_max) = T::BOUNDS;
+        let min = self.min.map(T::to_f64);
+        let max = self.max.map(T::to_f64);
+        ConfigSpec {
+            key: self.key,
+            config_type: T::TYPE,
+            default: self.default.to_value(),
+            min: match (min, type_min) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            },
+            max: match (max, type_max) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            units: self.units,
+            description: self.description,
+        }
+    }
+}
+
+/// A `ConfigKey` without its Rust type, as stored in the `Config`.
+#[derive(Clone, Debug)]
+pub struct ConfigSpec {
+    pub key: &'static str,
+    pub config_type: ConfigType,
+    pub default: ConfigValue,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub units: &'static str,
+    pub description: &'static str,
+}
+
+impl ConfigSpec {
+    /// Checks that `value` has the entry's type and lies in its range.
+    pub fn validate(&self, value: &ConfigValue) -> Result<(), ConfigError> {
+        if value.config_type() != self.config_type {
+            return Err(ConfigError::TypeMismatch {
+                key: self.key.to_string(),
+                expected: self.config_type,
+                found: value.config_type(),
+            });
+        }
+        if let Some(number) = value.number() {
+            let below = self.min.is_some_and(|min| number < min);
+            let above = self.max.is_some_and(|max| number > max);
+            if below || above || number.is_nan() {
+                return Err(ConfigError::OutOfRange {
+                    key: self.key.to_string(),
+                    value: value.clone(),
+                    min: self.min,
+                    max: self.max,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct ConfigEntry {
+    pub spec: ConfigSpec,
+    value: ConfigValue,
+}
+
+impl ConfigEntry {
+    pub fn value(&self) -> &ConfigValue {
+        &self.value
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    UnknownKey(String),
+    TypeMismatch {
+        key: String,
+        expected: ConfigType,
+        found: ConfigType,
+    },
+    /// Text that doesn't parse as the entry's type.
+    Parse {
+        key: String,
+        expected: ConfigType,
+        text: String,
+    },
+    OutOfRange {
+        key: String,
+        value: ConfigValue,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::UnknownKey(key) => write!(fmt, "unknown config key '{}'", key),
+            ConfigError::TypeMismatch {
+                key,
+                expected,
+                found,
+            } => write!(
+                fmt,
+                "config key '{}' holds a {}, not a {}",
+                key, expected, found
+            ),
+            ConfigError::Parse {
+                key,
+                expected,
+                text,
+            } => write!(
+                fmt,
+                "'{}' is not a valid {} for config key '{}'",
+                text, expected, key
+            ),
+            ConfigError::OutOfRange {
+                key,
+                value,
+                min,
+                max,
+            } => {
+                write!(fmt, "{} is out of range for config key '{}': ", value, key)?;
+                match (min, max) {
+                    (Some(min), Some(max)) => write!(fmt, "must be between {} and {}", min, max),
+                    (Some(min), None) => write!(fmt, "must be at least {}", min),
+                    (None, Some(max)) => write!(fmt, "must be at most {}", max),
+                    (None, None) => write!(fmt, "must be a number"),
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug)]
+pub enum ConfigFileError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Config(ConfigError),
+}
+
+impl fmt::Display for ConfigFileError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigFileError::Io(e) => write!(fmt, "{}", e),
+            ConfigFileError::Toml(e) => write!(fmt, "{}", e),
+            ConfigFileError::Config(e) => write!(fmt, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigFileError {}
+
+impl From<io::Error> for ConfigFileError {
+    fn from(e: io::Error) -> ConfigFileError {
+        ConfigFileError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ConfigFileError {
+    fn from(e: toml::de::Error) -> ConfigFileError {
+        ConfigFileError::Toml(e)
+    }
+}
+
+impl From<ConfigError> for ConfigFileError {
+    fn from(e: ConfigError) -> ConfigFileError {
+        ConfigFileError::Config(e)
+    }
+}
+
+/// Name of the environment variable overriding `key`.
+pub fn env_var_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.replace('.', "_").to_uppercase())
+}
+
+/// Flattens nested TOML tables into dotted keys, so `[ant]` `speed = 40` and `ant.speed = 40`
+/// both end up as `ant.speed`.
+fn flatten_toml(prefix: &str, table: toml::value::Table, flat: &mut Vec<(String, toml::Value)>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            toml::Value::Table(table) => flatten_toml(&key, table, flat),
+            value => flat.push((key, value)),
+        }
+    }
+}
+
+/// Converts a TOML value to the type of `spec`. Integers are accepted for float entries and
+/// booleans for integer ones.
+fn value_from_toml(spec: &ConfigSpec, value: toml::Value) -> Result<ConfigValue, ConfigError> {
+    let converted = match (spec.config_type, &value) {
+        (ConfigType::Int, toml::Value::Integer(i)) => i32::try_from(*i).ok().map(ConfigValue::Int),
+        (ConfigType::Int, toml::Value::Boolean(b)) => Some(ConfigValue::Int(*b as i32)),
+        (ConfigType::Float, toml::Value::Integer(i)) => Some(ConfigValue::Float(*i as f32)),
+        (ConfigType::Float, toml::Value::Float(f)) => Some(ConfigValue::Float(*f as f32)),
+        (ConfigType::String, toml::Value::String(s)) => Some(ConfigValue::String(s.clone())),
+        _ => None,
+    };
+    converted.ok_or_else(|| ConfigError::Parse {
+        key: spec.key.to_string(),
+        expected: spec.config_type,
+        text: value.to_string(),
+    })
+}
+
+/// `value` as a TOML literal. Floats keep a decimal point so they read back as floats.
+fn toml_literal(value: &ConfigValue) -> String {
+    match value {
+        ConfigValue::Int(i) => i.to_string(),
+        ConfigValue::Float(f) => format!("{:?}", f),
+        ConfigValue::String(s) => toml::Value::String(s.clone()).to_string(),
+    }
+}
+
+/// Sent for every config entry whose value was registered or changed since the previous frame.
+pub struct ConfigChanged {
+    pub key: &'static str,
+}
+
+/// Registry of every tunable parameter of the simulation. Entries are declared by `ConfigKey`s
+/// and registered at startup; reading an unregistered key panics, and every write is checked
+/// against the entry's type and range.
+#[derive(Default)]
+pub struct Config {
+    entries: BTreeMap<&'static str, ConfigEntry>,
+    /// Keys registered or changed since `config_change_system` last ran.
+    changes: Vec<&'static str>,
+}
+
+impl Config {
+    /// Adds the entry declared by `key`, set to its default. Panics if the default lies outside
+    /// the declared range or the key is already registered with another declaration.
+    pub fn register<T: ConfigPrimitive>(&mut self, key: &ConfigKey<T>) {
+        self.register_spec(key.spec());
+    }
+
+    pub fn register_spec(&mut self, spec: ConfigSpec) {
+        if let Err(e) = spec.validate(&spec.default) {
+            panic!("invalid default for config key '{}': {}", spec.key, e);
+        }
+        if let Some(entry) = self.entries.get(spec.key) {
+            if entry.spec.config_type != spec.config_type || entry.spec.default != spec.default {
+                panic!("config key '{}' is registered twice", spec.key);
+            }
+            return;
+        }
+        self.changes.push(spec.key);
+        self.entries.insert(
+            spec.key,
+            ConfigEntry {
+                value: spec.default.clone(),
+                spec,
+            },
+        );
+    }
+
+    /// Reads the entry declared by `key`. Panics if it was never registered.
+    pub fn get<T: ConfigPrimitive>(&self, key: &ConfigKey<T>) -> T {
+        let entry = self
+            .entries
+            .get(key.key)
+            .unwrap_or_else(|| panic!("config key '{}' read before it was registered", key.key));
+        T::from_value(&entry.value).unwrap_or_else(|| {
+            panic!(
+                "config key '{}' holds {}, which can't be read as a {}",
+                key.key,
+                entry.value,
+                std::any::type_name::<T>()
+            )
+        })
+    }
+
+    /// Sets the entry declared by `key`, if `value` lies in its range.
+    pub fn set<T: ConfigPrimitive>(
+        &mut self,
+        key: &ConfigKey<T>,
+        value: T,
+    ) -> Result<(), ConfigError> {
+        self.set_value(key.key, value.to_value())
+    }
+
+    pub fn set_value(&mut self, key: &str, value: ConfigValue) -> Result<(), ConfigError> {
+        let entry = self
+            .entries
+            .get_mut(key)
+            .ok_or_else(|| ConfigError::UnknownKey(key.to_string()))?;
+        entry.spec.validate(&value)?;
+        if entry.value != value {
+            entry.value = value;
+            self.changes.push(entry.spec.key);
+        }
+        Ok(())
+    }
+
+    /// Parses `text` as the type of the entry at `key` and sets it.
+    pub fn set_str(&mut self, key: &str, text: &str) -> Result<(), ConfigError> {
+        let entry = self.entry(key)?;
+        let value =
+            ConfigValue::parse(entry.spec.config_type, text).ok_or_else(|| ConfigError::Parse {
+                key: key.to_string(),
+                expected: entry.spec.config_type,
+                text: text.to_string(),
+            })?;
+        self.set_value(key, value)
+    }
+
+    pub fn entry(&self, key: &str) -> Result<&ConfigEntry, ConfigError> {
+        self.entries
+            .get(key)
+            .ok_or_else(|| ConfigError::UnknownKey(key.to_string()))
+    }
+
+    /// Every entry, in key order.
+    pub fn entries(&self) -> impl Iterator<Item = &ConfigEntry> {
+        self.entries.values()
+    }
+
+    /// Sets the entries given in the TOML `text`, either as dotted keys or as tables, and returns
+    /// their keys. Nothing is changed unless every entry is known and valid.
+    pub fn load_toml(&mut self, text: &str) -> Result<Vec<&'static str>, ConfigFileError> {
+        let mut flat = Vec::new();
+        flatten_toml("", toml::from_str(text)?, &mut flat);
+        let mut values = Vec::new();
+        for (key, value) in flat {
+            let spec = &self.entry(&key)?.spec;
+            let value = value_from_toml(spec, value)?;
+            spec.validate(&value)?;
+            values.push((spec.key, value));
+        }
+        let mut keys = Vec::new();
+        for (key, value) in values {
+            self.set_value(key, value)?;
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+
+    pub fn load_file(&mut self, path: &Path) -> Result<Vec<&'static str>, ConfigFileError> {
+        let text = fs::read_to_string(path)?;
+        self.load_toml(&text)
+    }
+
+    /// Every entry as TOML dotted keys, each preceded by a comment with its description and
+    /// units, so the file documents itself.
+    pub fn to_toml(&self) -> String {
+        let mut text = String::new();
+        for entry in self.entries() {
+            text.push_str(&format!("# {}", entry.spec.description));
+            if !entry.spec.units.is_empty() {
+                text.push_str(&format!(" ({})", entry.spec.units));
+            }
+            text.push_str(&format!(
+                "\n{} = {}\n",
+                entry.spec.key,
+                toml_literal(entry.value())
+            ));
+        }
+        text
+    }
+
+    pub fn save_file(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_toml())
+    }
+
+    /// Sets every entry whose `ANTS_SIM_*` environment variable is set, parsing it like
+    /// `set_str`, and returns their keys.
+    pub fn apply_env(&mut self) -> Result<Vec<&'static str>, ConfigError> {
+        let overrides: Vec<(&'static str, String)> = self
+            .entries
+            .keys()
+            .filter_map(|&key| {
+                std::env::var(env_var_name(key))
+                    .ok()
+                    .map(|text| (key, text))
+            })
+            .collect();
+        for (key, text) in overrides.iter() {
+            self.set_str(key, text)?;
+        }
+        Ok(overrides.into_iter().map(|(key, _)| key).collect())
+    }
+
+    /// Applies the TOML `file`, then the `ANTS_SIM_*` environment variables, then `sets` of keys
+    /// and value texts, each layer taking precedence over the previous ones. Returns every key
+    /// set, once each, in order of first appearance.
+    pub fn apply_layers(
+        &mut self,
+        file: Option<&Path>,
+        sets: &[(String, String)],
+    ) -> Result<Vec<&'static str>, ConfigFileError> {
+        let mut keys = Vec::new();
+        if let Some(path) = file {
+            keys.extend(self.load_file(path)?);
+        }
+        keys.extend(self.apply_env()?);
+        for (key, text) in sets {
+            self.set_str(key, text)?;
+            keys.push(self.entry(key)?.spec.key);
+        }
+        let mut seen = Vec::new();
+        keys.retain(|key| {
+            let first = !seen.contains(key);
+            seen.push(*key);
+            first
+        });
+        Ok(keys)
+    }
+}
+
+/// Reports the config entries registered or changed since the previous frame as
+/// `ConfigChanged` events.
+pub fn config_change_system(mut config: ResMut<Config>, mut changes: EventWriter<ConfigChanged>) {
+    // checked through `Deref` first so an unchanged config isn't marked as changed
+    if config.changes.is_empty() {
+        return;
+    }
+    for key in config.changes.drain(..) {
+        changes.send(ConfigChanged { key });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::app::Events;
+
+    const SPEED: ConfigKey<f32> = ConfigKey {
+        key: "test.speed",
+        default: 1.0,
+        min: Some(0.0),
+        max: Some(10.0),
+        units: "units/s",
+        description: "Speed",
+    };
+
+    const COUNT: ConfigKey<usize> = ConfigKey {
+        key: "test.count",
+        default: 3,
+        min: Some(1),
+        max: None,
+        units: "",
+        description: "Count",
+    };
+
+    fn test_config() -> Config {
+        let mut config = Config::default();
+        config.register(&SPEED);
+        config.register(&COUNT);
+        config
+    }
+
+    #[test]
+    fn registered_entries_start_at_their_defaults() {
+        let config = test_config();
+        assert_eq!(config.get(&SPEED), 1.0);
+        assert_eq!(config.get(&COUNT), 3);
+    }
+
+    #[test]
+    fn set_checks_the_range() {
+        let mut config = test_config();
+        config.set(&SPEED, 10.0).unwrap();
+        assert_eq!(config.get(&SPEED), 10.0);
+        for value in [-0.5, 10.5, f32::NAN] {
+            match config.set(&SPEED, value) {
+                Err(ConfigError::OutOfRange { key, .. }) => assert_eq!(key, "test.speed"),
+                result => panic!("set {} gave {:?}", value, result),
+            }
+        }
+        assert!(matches!(
+            config.set(&COUNT, 0),
+            Err(ConfigError::OutOfRange { .. })
+        ));
+        assert_eq!(config.get(&SPEED), 10.0);
+        assert_eq!(config.get(&COUNT), 3);
+    }
+
+    #[test]
+    fn usize_entries_reject_negative_values() {
+        let mut config = test_config();
+        assert!(matches!(
+            config.set_value("test.count", ConfigValue::Int(-1)),
+            Err(ConfigError::OutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn set_value_checks_the_type() {
+        let mut config = test_config();
+        match config.set_value("test.count", ConfigValue::Float(2.0)) {
+            Err(ConfigError::TypeMismatch {
+                key,
+                expected,
+                found,
+            }) => {
+                assert_eq!(key, "test.count");
+                assert_eq!(expected, ConfigType::Int);
+                assert_eq!(found, ConfigType::Float);
+            }
+            result => panic!("set a float on an int entry: {:?}", result),
+        }
+        assert_eq!(config.get(&COUNT), 3);
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        let mut config = test_config();
+        assert!(matches!(
+            config.set_value("test.missing", ConfigValue::Int(1)),
+            Err(ConfigError::UnknownKey(key)) if key == "test.missing"
+        ));
+        assert!(matches!(
+            config.set_str("test.missing", "1"),
+            Err(ConfigError::UnknownKey(_))
+        ));
+        assert!(config.entry("test.missing").is_err());
+    }
+
+    #[test]
+    fn set_str_parses_the_entry_type() {
+        let mut config = test_config();
+        config.set_str("test.speed", "2.5").unwrap();
+        config.set_str("test.count", "7").unwrap();
+        assert_eq!(config.get(&SPEED), 2.5);
+        assert_eq!(config.get(&COUNT), 7);
+        assert!(matches!(
+            config.set_str("test.count", "2.5"),
+            Err(ConfigError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    #[should_panic]
+    fn defaults_must_lie_in_range() {
+        let mut config = Config::default();
+        config.register(&ConfigKey {
+            default: 20.0,
+            ..SPEED
+        });
+    }
+
+    #[test]
+    fn changes_are_sent_as_events() {
+        let mut world = World::new();
+        world.insert_resource(test_config());
+        world.insert_resource(Events::<ConfigChanged>::default());
+        let mut stage = SystemStage::single_threaded().with_system(config_change_system);
+        let mut reader = world
+            .get_resource::<Events<ConfigChanged>>()
+            .unwrap()
+            .get_reader();
+        let mut changed_keys = |world: &mut World| -> Vec<&'static str> {
+            stage.run(world);
+            let events = world.get_resource::<Events<ConfigChanged>>().unwrap();
+            let mut keys: Vec<_> = reader.iter(events).map(|change| change.key).collect();
+            keys.sort_unstable();
+            keys
+        };
+
+        assert_eq!(changed_keys(&mut world), ["test.count", "test.speed"]);
+        assert!(changed_keys(&mut world).is_empty());
+
+        let mut config = world.get_resource_mut::<Config>().unwrap();
+        config.set(&SPEED, 2.0).unwrap();
+        // setting the current value or an invalid one is no change
+        config.set(&COUNT, 3).unwrap();
+        assert!(config.set(&COUNT, 0).is_err());
+        assert_eq!(changed_keys(&mut world), ["test.speed"]);
+    }
+
+    /// A file in the temporary directory, unique to the test.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ants_sim_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn layers_apply_file_then_environment_then_sets() {
+        // keys of their own, so no other test sees the environment variables
+        const FILE_ONLY: ConfigKey<f32> = ConfigKey {
+            key: "layers.file_only",
+            ..SPEED
+        };
+        const LAYERED: ConfigKey<f32> = ConfigKey {
+            key: "layers.layered",
+            ..SPEED
+        };
+        let layered_config = || {
+            let mut config = Config::default();
+            config.register(&FILE_ONLY);
+            config.register(&LAYERED);
+            config
+        };
+        let path = temp_path("layers.toml");
+        fs::write(&path, "[layers]\nfile_only = 2.0\nlayered = 2.0\n").unwrap();
+
+        let mut config = layered_config();
+        assert_eq!(
+            config.apply_layers(Some(&path), &[]).unwrap(),
+            ["layers.file_only", "layers.layered"]
+        );
+        assert_eq!(config.get(&LAYERED), 2.0);
+
+        std::env::set_var(env_var_name(LAYERED.key), "3");
+        let mut config = layered_config();
+        config.apply_layers(Some(&path), &[]).unwrap();
+        assert_eq!(config.get(&LAYERED), 3.0);
+
+        let sets = [("layers.layered".to_string(), "4".to_string())];
+        let mut config = layered_config();
+        assert_eq!(
+            config.apply_layers(Some(&path), &sets).unwrap(),
+            ["layers.file_only", "layers.layered"]
+        );
+        assert_eq!(config.get(&LAYERED), 4.0);
+        assert_eq!(config.get(&FILE_ONLY), 2.0);
+
+        std::env::remove_var(env_var_name(LAYERED.key));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn out_of_range_values_name_their_key() {
+        let mut config = test_config();
+        let e = config
+            .load_toml("test.count = 5\ntest.speed = 20.0\n")
+            .unwrap_err();
+        assert!(matches!(
+            &e,
+            ConfigFileError::Config(ConfigError::OutOfRange { key, .. }) if key == "test.speed"
+        ));
+        assert!(e.to_string().contains("'test.speed'"));
+        // nothing is applied from a file with an invalid entry
+        assert_eq!(config.get(&COUNT), 3);
+
+        let sets = [("test.count".to_string(), "0".to_string())];
+        let e = config.apply_layers(None, &sets).unwrap_err();
+        assert!(e.to_string().contains("'test.count'"));
+    }
+
+    #[test]
+    fn saved_files_load_back_to_the_same_values() {
+        let mut config = test_config();
+        config.register_spec(ConfigSpec {
+            key: "test.name",
+            config_type: ConfigType::String,
+            default: ConfigValue::String("default".to_string()),
+            min: None,
+            max: None,
+            units: "",
+            description: "Name",
+        });
+        config.set(&SPEED, 0.1).unwrap();
+        config.set(&COUNT, 12).unwrap();
+        config
+            .set_value(
+                "test.name",
+                ConfigValue::String("a \"quoted\" name".to_string()),
+            )
+            .unwrap();
+        let path = temp_path("saved.toml");
+        config.save_file(&path).unwrap();
+
+        let mut loaded = test_config();
+        loaded.register_spec(config.entry("test.name").unwrap().spec.clone());
+        loaded.load_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        for entry in config.entries() {
+            assert_eq!(
+                loaded.entry(entry.spec.key).unwrap().value(),
+                entry.value(),
+                "{}",
+                entry.spec.key
+            );
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+use clap::ArgMatches;
+use std::str::FromStr;
+
+/// A command of the console, contributed by whichever plugin owns the state it works on. It runs
+/// inside the schedule with the whole `World` at hand.
+pub trait ConsoleCommand: Send + Sync {
+    /// The clap subcommand parsing the command's arguments. Its name is what gets typed.
+    fn command(&self) -> clap::App<'static>;
+
+    /// Runs the command, returning what to print, or an error message.
+    fn run(&mut self, matches: &ArgMatches, world: &mut World) -> Result<String, String>;
+}
+
+/// A `ConsoleCommand` made of a clap subcommand and a handler function.
+pub struct CommandFn {
+    command: clap::App<'static>,
+    handler: fn(&ArgMatches, &mut World) -> Result<String, String>,
+}
+
+impl CommandFn {
+    pub fn new(
+        command: clap::App<'static>,
+        handler: fn(&ArgMatches, &mut World) -> Result<String, String>,
+    ) -> CommandFn {
+        CommandFn { command, handler }
+    }
+}
+
+impl ConsoleCommand for CommandFn {
+    fn command(&self) -> clap::App<'static> {
+        self.command.clone()
+    }
+
+    fn run(&mut self, matches: &ArgMatches, world: &mut World) -> Result<String, String> {
+        (self.handler)(matches, world)
+    }
+}
+
+/// Every registered `ConsoleCommand`, in registration order.
+#[derive(Default)]
+pub struct ConsoleCommands {
+    commands: Vec<Box<dyn ConsoleCommand>>,
+}
+
+impl ConsoleCommands {
+    /// Registers `command`. Panics if a command of the same name is already registered.
+    pub fn add(&mut self, command: impl ConsoleCommand + 'static) -> &mut ConsoleCommands {
+        let name = command.command().get_name().to_string();
+        if self.find(&name).is_some() {
+            panic!("console command '{}' is registered twice", name);
+        }
+        self.commands.push(Box::new(command));
+        self
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.commands
+            .iter()
+            .position(|command| command.command().get_name() == name)
+    }
+
+    /// The clap app parsing console lines, with every command as a subcommand.
+    pub fn app(&self) -> clap::App<'static> {
+        clap::App::new("").subcommands(self.commands.iter().map(|command| command.command()))
+    }
+
+    /// Parses and runs one console line against `world`, returning what to print.
+    pub fn run(&mut self, line: &str, world: &mut World) -> String {
+        let args = std::iter::once("").chain(line.split_whitespace());
+        let matches = match self.app().try_get_matches_from(args) {
+            Ok(matches) => matches,
+            // also how `help` and `--help` print their text
+            Err(e) => return e.to_string().trim_end().to_string(),
+        };
+        let (name, s_matches) = match matches.subcommand() {
+            Some(subcommand) => subcommand,
+            None => return String::new(),
+        };
+        let index = self.find(name).unwrap();
+        match self.commands[index].run(s_matches, world) {
+            Ok(output) => output,
+            Err(e) => format!("error: {}", e),
+        }
+    }
+}
+
+/// Registration of console commands on an `App`, for plugins to contribute their own.
+pub trait AddConsoleCommand {
+    fn add_console_command(&mut self, command: impl ConsoleCommand + 'static) -> &mut Self;
+}
+
+impl AddConsoleCommand for App {
+    fn add_console_command(&mut self, command: impl ConsoleCommand + 'static) -> &mut App {
+        self.world
+            .get_resource_or_insert_with(ConsoleCommands::default)
+            .add(command);
+        self
+    }
+}
+
+/// Parses argument `name` of `matches`, if given.
+pub fn parse_arg<T: FromStr>(matches: &ArgMatches, name: &str) -> Result<Option<T>, String> {
+    matches
+        .value_of(name)
+        .map(|text| {
+            text.parse()
+                .map_err(|_| format!("invalid {} '{}'", name, text))
+        })
+        .transpose()
+}
+
+/// Parses a position in world units given as `X,Y`.
+pub fn parse_position(text: &str) -> Result<Vec2, String> {
+    let invalid = || format!("invalid position '{}', expected X,Y", text);
+    let (x, y) = text.split_once(',').ok_or_else(invalid)?;
+    match (x.trim().parse(), y.trim().parse()) {
+        (Ok(x), Ok(y)) => Ok(Vec2::new(x, y)),
+        _ => Err(invalid()),
+    }
+}
@@ -1,181 +1,280 @@
-use bevy::app::AppExit;
-use bevy::{
-    prelude::*,
-    tasks::AsyncComputeTaskPool,
-};
-use crossbeam::channel::{bounded, Receiver};
-use clap::{App, ArgMatches};
-use std::io::{self, Write};
-use std::collections::HashMap;
-use std::fmt;
-
-fn spawn_io_thread(mut commands: Commands, thread_pool: Res<AsyncComputeTaskPool>) {
-    println!("Bevy Console Debugger.  Type 'help' for list of commands.");
-    print!(">>> ");
-    io::stdout().flush().unwrap();
+use crate::config::Config;
+use crate::console_commands::{AddConsoleCommand, CommandFn, ConsoleCommands};
+use bevy::app::{AppExit, Events};
+use bevy::{prelude::*, tasks::AsyncComputeTaskPool};
+use clap::ArgMatches;
+use crossbeam::channel::{bounded, Receiver, Sender};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::path::PathBuf;
 
-    let (tx, rx) = bounded(1);
-    let task = thread_pool.spawn(async move {
-        let mut rl = rustyline::Editor::<()>::new();
-        loop {
-            let readline = rl.readline(">> ");
-            match readline {
-                Ok(line) => {
-                    rl.add_history_entry(line.as_str());
-                    let quit = line == "quit";
-                    tx.send(line)
-                     .expect("error sending user input to other thread");
-                    if quit {
-                        break;
-                    }
-                },
-                Err(_) => println!("No input"),
-            }
-        }
-    });
-    task.detach();
-    commands.insert_resource(rx);
-}
+const PROMPT: &str = ">> ";
+/// File the console history is kept in, in the home directory or, without one, the working
+/// directory.
+const HISTORY_FILE: &str = ".ants_sim_history";
+/// Commands whose first argument is a config key.
+const CONFIG_KEY_COMMANDS: [&str; 3] = ["config_ls", "config_get", "config_set"];
 
-fn parse_input(
-    line_channel: Res<Receiver<String>>, mut config: ResMut<Config>, exit: EventWriter<AppExit>
-) {
-    if let Ok(line) = line_channel.try_recv() {
-        let app_name = "";
-        println!("" );
-        let split = line.split_whitespace();
-        let mut args = vec![app_name];
-        args.append(&mut split.collect());
-
-        let matches_result = build_commands(app_name).try_get_matches_from(args);
-
-        if let Err(e) = matches_result {
-            println!("{}", e.to_string());
-            print!(">> ");
-            io::stdout().flush().unwrap();
-            return;
-        }
+fn history_path() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(HISTORY_FILE)
+}
 
-        let matches = matches_result.unwrap();
+/// Tab-completes command names, then subcommand names, or config keys as the first argument of
+/// the config commands.
+struct ConsoleHelper {
+    /// Every command, with the names of its subcommands.
+    commands: Vec<(String, Vec<String>)>,
+    config_keys: Vec<String>,
+}
 
-        let output = match_commands(&matches, &mut config, exit);
+impl Completer for ConsoleHelper {
+    type Candidate = String;
 
-        println!("{}", output);
-        print!(">> ");
-        io::stdout().flush().unwrap();
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.trim_end_matches(|c: char| !c.is_whitespace()).len();
+        let mut previous = line[..start].split_whitespace();
+        let candidates: Vec<&String> = match (previous.next(), previous.next()) {
+            (None, _) => self.commands.iter().map(|(name, _)| name).collect(),
+            (Some(command), None) if CONFIG_KEY_COMMANDS.contains(&command) => {
+                self.config_keys.iter().collect()
+            }
+            (Some(command), None) => self
+                .commands
+                .iter()
+                .filter(|(name, _)| name == command)
+                .flat_map(|(_, subcommands)| subcommands.iter())
+                .collect(),
+            _ => return Ok((start, Vec::new())),
+        };
+        let word = &line[start..];
+        Ok((
+            start,
+            candidates
+                .into_iter()
+                .filter(|candidate| candidate.starts_with(word))
+                .cloned()
+                .collect(),
+        ))
     }
 }
 
-pub enum ConfigValue {
-    Int(i32),
-    Float(f32),
-    String(String)
+impl Hinter for ConsoleHelper {
+    type Hint = String;
 }
 
-impl ConfigValue {
-    pub fn f32(&self) -> f32 {
-        match self {
-            ConfigValue::Float(f) => *f,
-            _ => 0.0
-        }
-    }
-    pub fn f64(&self) -> f64 {
-        match self {
-            ConfigValue::Float(f) => *f as f64,
-            _ => 0.0
-        }
-    }
-    pub fn usize(&self) -> usize {
-        match self {
-            ConfigValue::Int(i) => *i as usize,
-            _ => 0
-        }
-    }
+impl Highlighter for ConsoleHelper {}
+
+impl Validator for ConsoleHelper {}
+
+impl Helper for ConsoleHelper {}
+
+/// The simulation's ends of the channels to the console thread: the lines typed, and the output
+/// of each, which the thread waits for before prompting again.
+struct ConsoleChannels {
+    lines: Receiver<String>,
+    output: Sender<String>,
 }
 
-impl fmt::Display for ConfigValue {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-          ConfigValue::Int(i) => write!(fmt, "{}", i),
-          ConfigValue::Float(f) => write!(fmt, "{}", f),
-          ConfigValue::String(s) => write!(fmt, "{}", s),
+fn spawn_io_thread(
+    mut commands: Commands,
+    thread_pool: Res<AsyncComputeTaskPool>,
+    config: Res<Config>,
+    console_commands: Res<ConsoleCommands>,
+) {
+    println!("Bevy Console Debugger.  Type 'help' for list of commands.");
+
+    let mut command_names: Vec<(String, Vec<String>)> = console_commands
+        .app()
+        .get_subcommands()
+        .map(|command| {
+            let subcommands = command
+                .get_subcommands()
+                .map(|subcommand| subcommand.get_name().to_string())
+                .collect();
+            (command.get_name().to_string(), subcommands)
+        })
+        .collect();
+    command_names.push(("help".to_string(), Vec::new()));
+    let helper = ConsoleHelper {
+        commands: command_names,
+        config_keys: config
+            .entries()
+            .map(|entry| entry.spec.key.to_string())
+            .collect(),
+    };
+    let (line_tx, line_rx) = bounded(1);
+    let (output_tx, output_rx) = bounded::<String>(1);
+    let task = thread_pool.spawn(async move {
+        let mut rl = Editor::<ConsoleHelper>::new();
+        rl.set_helper(Some(helper));
+        let history = history_path();
+        // there is no history file before the first session
+        let _ = rl.load_history(&history);
+        loop {
+            let line = match rl.readline(PROMPT) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                // closing the input quits, just like typing quit
+                Err(ReadlineError::Eof) => "quit".to_string(),
+                Err(e) => {
+                    println!("console input failed: {}", e);
+                    break;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            rl.add_history_entry(line.as_str());
+            if let Err(e) = rl.save_history(&history) {
+                println!("failed to save history {}: {}", history.display(), e);
+            }
+            let quit = line.trim() == "quit";
+            if line_tx.send(line).is_err() || quit {
+                break;
+            }
+            match output_rx.recv() {
+                Ok(output) if !output.is_empty() => println!("{}", output),
+                Ok(_) => {}
+                Err(_) => break,
+            }
         }
-    }
+    });
+    task.detach();
+    commands.insert_resource(ConsoleChannels {
+        lines: line_rx,
+        output: output_tx,
+    });
+}
+
+fn parse_input(world: &mut World) {
+    let line = match world
+        .get_resource::<ConsoleChannels>()
+        .unwrap()
+        .lines
+        .try_recv()
+    {
+        Ok(line) => line,
+        Err(_) => return,
+    };
+    let output = world.resource_scope(|world, mut console_commands: Mut<ConsoleCommands>| {
+        console_commands.run(&line, world)
+    });
+    // the console thread is gone after quit
+    let _ = world
+        .get_resource::<ConsoleChannels>()
+        .unwrap()
+        .output
+        .send(output);
 }
 
-#[derive(Default)]
-pub struct Config {
-    pub entries: HashMap<&'static str, ConfigValue>,
+fn quit(_: &ArgMatches, world: &mut World) -> Result<String, String> {
+    world
+        .get_resource_mut::<Events<AppExit>>()
+        .unwrap()
+        .send(AppExit);
+    Ok(String::new())
 }
 
-pub fn build_commands<'a>(app_name: &'a str) -> App {
-    let app = clap::App::new(app_name)
-        .subcommand(clap::App::new("quit"))
-        .subcommand(clap::App::new("config_ls")
-            .about("list all config entries"))
-        .subcommand(clap::App::new("config_get")
-            .about("get convig value")
-            .arg(clap::arg!([key] "'string key of entry to get'")))
-        .subcommand(clap::App::new("config_set")
-            .about("set convig value")
-            .arg(clap::arg!([key] "'string key of entry to set'"))
-            .arg(clap::arg!([value] "'value of entry to set'")));
-    app
+fn config_get(matches: &ArgMatches, world: &mut World) -> Result<String, String> {
+    let key = matches.value_of("key").unwrap();
+    let config = world.get_resource::<Config>().unwrap();
+    let entry = config.entry(key).map_err(|e| e.to_string())?;
+    Ok(format!("{} = {}", key, entry.value()))
 }
 
-pub fn match_commands(matches: &ArgMatches, config: &mut Config, mut exit: EventWriter<AppExit>) -> String {
-        let mut output = String::new();
-    match matches.subcommand() {
-        Some(("quit", _)) => {
-            exit.send(AppExit);
-        }
-        Some(("config_ls", _)) => {
-            for (ref key, value) in &config.entries {
-                println!("{:20} {}", key, value);
-             }
-        }
-        Some(("config_get", s_matches)) => {
-            output.push_str("...config_get command!");
-            if let Some(key) = s_matches.value_of("key") {
-              output.push_str(" key: ");
-              output.push_str(key);
-              output.push_str(" value: ");
-              if let Some(value) = config.entries.get(key) {
-                  match value {
-                    ConfigValue::Int(i) => output.push_str(&i.to_string()[..]),
-                    ConfigValue::Float(f) => output.push_str(&f.to_string()[..]),
-                    ConfigValue::String(s) => output.push_str(&s[..]),
-                  }
-              }
-            }
-        }
-        Some(("config_set", s_matches)) => {
-            output.push_str("...config_set command!.");
-            if let Some(key) = s_matches.value_of("key") {
-              // let key: &'static str = key;
-              output.push_str(" key: ");
-              output.push_str(key);
-              if let Some(new_value) = s_matches.value_of("value") {
-                let entries = &mut config.entries;
-                  if let Some((ref mut old_key, old_value)) = entries.get_key_value(key) {
-                      let foo = match old_value {
-                        ConfigValue::Int(_) => {ConfigValue::Int(new_value.parse::<i32>().unwrap())},
-                        ConfigValue::Float(_) => {ConfigValue::Float(new_value.parse::<f32>().unwrap())},
-                        ConfigValue::String(_) => {ConfigValue::String(new_value.to_string())},
-                      };
-                      entries.insert(old_key, foo);
-                  }
-              }
-            }
-        }
-        _ => {}
+fn config_set(matches: &ArgMatches, world: &mut World) -> Result<String, String> {
+    let key = matches.value_of("key").unwrap();
+    let value = matches.value_of("value").unwrap();
+    let mut config = world.get_resource_mut::<Config>().unwrap();
+    config.set_str(key, value).map_err(|e| e.to_string())?;
+    Ok(format!("{} = {}", key, config.entry(key).unwrap().value()))
+}
+
+/// One line per entry whose key starts with `prefix`, in key order, with its value, units and
+/// description in aligned columns.
+fn config_ls(config: &Config, prefix: &str) -> Result<String, String> {
+    let rows: Vec<[String; 4]> = config
+        .entries()
+        .filter(|entry| entry.spec.key.starts_with(prefix))
+        .map(|entry| {
+            [
+                entry.spec.key.to_string(),
+                entry.value().to_string(),
+                entry.spec.units.to_string(),
+                entry.spec.description.to_string(),
+            ]
+        })
+        .collect();
+    if rows.is_empty() {
+        return Err(format!("no config keys start with '{}'", prefix));
     }
-    output
+    let widths = [0, 1, 2].map(|column| rows.iter().map(|row| row[column].len()).max().unwrap());
+    Ok(rows
+        .iter()
+        .map(|[key, value, units, description]| {
+            format!(
+                "{:key_width$}  {:value_width$}  {:units_width$}  {}",
+                key,
+                value,
+                units,
+                description,
+                key_width = widths[0],
+                value_width = widths[1],
+                units_width = widths[2],
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
 }
 
+/// Interactive console on stdin for inspecting, tuning and controlling a running simulation. Runs
+/// the commands of every plugin registered through `AddConsoleCommand`, along with its own quit
+/// and config commands. Only built with the `console` feature.
 pub struct ConsoleDebugPlugin;
 impl Plugin for ConsoleDebugPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.init_resource::<Config>().add_startup_system(spawn_io_thread).add_system(parse_input.system());
+        // the config keys to complete are all registered by the end of startup
+        app.init_resource::<Config>()
+            .add_console_command(CommandFn::new(
+                clap::App::new("quit").about("quit the simulation"),
+                quit,
+            ))
+            .add_console_command(CommandFn::new(
+                clap::App::new("config_ls")
+                    .about("list config entries in key order")
+                    .arg(clap::arg!([prefix] "only list keys starting with this prefix")),
+                |matches, world| {
+                    let config = world.get_resource::<Config>().unwrap();
+                    config_ls(config, matches.value_of("prefix").unwrap_or_default())
+                },
+            ))
+            .add_console_command(CommandFn::new(
+                clap::App::new("config_get")
+                    .about("get config value")
+                    .arg(clap::arg!(<key> "key of the entry to get")),
+                config_get,
+            ))
+            .add_console_command(CommandFn::new(
+                clap::App::new("config_set")
+                    .about("set config value")
+                    .arg(clap::arg!(<key> "key of the entry to set"))
+                    .arg(clap::arg!(<value> "value to set the entry to")),
+                config_set,
+            ))
+            .add_startup_system_to_stage(StartupStage::PostStartup, spawn_io_thread)
+            .add_system(parse_input.exclusive_system());
     }
-}
\ No newline at end of file
+}
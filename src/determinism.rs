@@ -0,0 +1,242 @@
+use crate::ants_plugin::{AntsPlugin, TickMode};
+use crate::snapshot::Snapshot;
+use bevy::core::DefaultTaskPoolOptions;
+use bevy::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+/// A headless run of the simulation to compare against another.
+#[derive(Clone)]
+pub struct Run {
+    pub scenario: Option<PathBuf>,
+    pub seed: Option<i32>,
+    /// Number of threads in bevy's task pools.
+    pub threads: usize,
+    /// `TickMode::FixedTimestep` runs only the steps queued while paused, as `Time` is real.
+    pub tick_mode: TickMode,
+}
+
+impl Run {
+    pub fn app(&self) -> App {
+        let mut app = App::new();
+        app.insert_resource(DefaultTaskPoolOptions::with_num_threads(self.threads))
+            .add_plugins(MinimalPlugins)
+            .add_plugin(AntsPlugin {
+                tick_mode: self.tick_mode,
+                scenario: self.scenario.clone(),
+                snapshot: None,
+                seed: self.seed,
+                ..Default::default()
+            });
+        app
+    }
+}
+
+/// One component or resource of the world state, as raw bits so floats compare exactly.
+pub struct StateEntry {
+    pub label: String,
+    pub bits: Vec<u64>,
+}
+
+impl StateEntry {
+    fn new(label: impl Into<String>, bits: impl IntoIterator<Item = u64>) -> StateEntry {
+        StateEntry {
+            label: label.into(),
+            bits: bits.into_iter().collect(),
+        }
+    }
+}
+
+fn f32_bits(values: &[f32]) -> impl Iterator<Item = u64> + '_ {
+    values.iter().map(|value| value.to_bits() as u64)
+}
+
+/// Flattens the full simulation state of `world`, as captured by `Snapshot`, into labelled
+/// entries in a stable order.
+pub fn state_entries(world: &mut World) -> Vec<StateEntry> {
+    let snapshot = Snapshot::capture(world);
+    let word_pos = snapshot.rng.word_pos();
+    let mut entries = vec![
+        StateEntry::new("SimulationTick", [snapshot.tick as u64]),
+        StateEntry::new("NextAntId", [snapshot.next_ant_id]),
+        StateEntry::new("NextFoodId", [snapshot.next_food_id]),
+        StateEntry::new(
+            "SimRng",
+            [
+                snapshot.rng.seed() as u64,
+                word_pos as u64,
+                (word_pos >> 64) as u64,
+            ],
+        ),
+    ];
+    for ant in snapshot.ants.iter() {
+        entries.push(StateEntry::new(
+            format!("ant {:?} Ant", ant.id),
+            [
+                ant.colony.0 as u64,
+                ant.caste as u64,
+                ant.carrying_food as u64,
+                ant.state as u64,
+                ant.state_ticks as u64,
+                ant.energy.to_bits() as u64,
+                ant.food_perception.to_bits() as u64,
+                ant.nest_perception.to_bits() as u64,
+                ant.carried_food as u64,
+            ],
+        ));
+        entries.push(StateEntry::new(
+            format!("ant {:?} Transform", ant.id),
+            f32_bits(&ant.translation).chain(f32_bits(&ant.rotation)),
+        ));
+        if let Some(rigid_body) = &ant.rigid_body {
+            entries.push(StateEntry::new(
+                format!("ant {:?} RigidBody", ant.id),
+                f32_bits(&rigid_body.position)
+                    .chain(f32_bits(&rigid_body.next_position))
+                    .chain(f32_bits(&rigid_body.linvel))
+                    .chain(f32_bits(&[rigid_body.angvel]))
+                    .chain(f32_bits(&rigid_body.force))
+                    .chain(f32_bits(&[rigid_body.torque])),
+            ));
+        }
+        if let Some(trip) = &ant.trip {
+            entries.push(StateEntry::new(
+                format!("ant {:?} Trip", ant.id),
+                [trip.started as u64]
+                    .into_iter()
+                    .chain(f32_bits(&[trip.distance]))
+                    .chain(f32_bits(&trip.last_position)),
+            ));
+        }
+    }
+    let scenario = &snapshot.scenario;
+    entries.push(StateEntry::new(
+        "FoodSource",
+        scenario.food.iter().flat_map(|food| {
+            let source = &food.source;
+            let mut bits = vec![food.id.map_or(u64::MAX, |id| id.0)];
+            bits.extend(f32_bits(&food.position));
+            bits.extend([
+                source.food_type as u64,
+                source.amount as u64,
+                source.capacity as u64,
+                source.take as u64,
+                source.respawn as u64,
+            ]);
+            bits.extend(f32_bits(&[source.regrowth, source.regrowth_progress]));
+            bits
+        }),
+    ));
+    entries.push(StateEntry::new(
+        "Home",
+        scenario.homes.iter().flat_map(|home| {
+            f32_bits(&home.position).chain([home.colony.0 as u64, home.food_store as u64])
+        }),
+    ));
+    entries.push(StateEntry::new(
+        "ObstacleGrid",
+        scenario
+            .obstacles
+            .iter()
+            .flat_map(|&[x, y]| [x as u64, y as u64]),
+    ));
+    for (colony, totals) in snapshot.colony_stats.iter() {
+        let mut bits = vec![totals.food_delivered as u64];
+        bits.extend(totals.food_delivered_by_caste.iter().map(|&n| n as u64));
+        bits.push(totals.births as u64);
+        bits.extend(totals.births_by_caste.iter().map(|&n| n as u64));
+        bits.push(totals.deaths as u64);
+        bits.extend(totals.deaths_by_caste.iter().map(|&n| n as u64));
+        bits.extend([
+            totals.trips_completed as u64,
+            totals.window_trips as u64,
+            totals.window_trip_ticks as u64,
+            totals.window_trip_distance.to_bits() as u64,
+        ]);
+        entries.push(StateEntry::new(
+            format!("ColonyStats colony {}", colony.0),
+            bits,
+        ));
+    }
+    for (channel, cells) in snapshot.pheromones.iter().enumerate() {
+        entries.push(StateEntry::new(
+            format!("PheromoneField channel {}", channel),
+            f32_bits(cells),
+        ));
+    }
+    entries
+}
+
+pub fn state_hash(entries: &[StateEntry]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for entry in entries {
+        entry.label.hash(&mut hasher);
+        entry.bits.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Describes the first entry where `a` and `b` differ, if any.
+pub fn first_difference(a: &[StateEntry], b: &[StateEntry]) -> Option<String> {
+    for (entry_a, entry_b) in a.iter().zip(b.iter()) {
+        if entry_a.label != entry_b.label {
+            return Some(format!("{} vs {}", entry_a.label, entry_b.label));
+        }
+        if let Some(index) = (0..entry_a.bits.len().min(entry_b.bits.len()))
+            .find(|&index| entry_a.bits[index] != entry_b.bits[index])
+        {
+            return Some(format!(
+                "{} value {}: {:#x} vs {:#x}",
+                entry_a.label, index, entry_a.bits[index], entry_b.bits[index]
+            ));
+        }
+        if entry_a.bits.len() != entry_b.bits.len() {
+            return Some(format!(
+                "{} has {} values vs {}",
+                entry_a.label,
+                entry_a.bits.len(),
+                entry_b.bits.len()
+            ));
+        }
+    }
+    if a.len() != b.len() {
+        return Some(format!("{} entries vs {}", a.len(), b.len()));
+    }
+    None
+}
+
+#[derive(Debug)]
+pub struct Divergence {
+    pub tick: usize,
+    pub difference: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "runs diverge at tick {}: {}",
+            self.tick, self.difference
+        )
+    }
+}
+
+/// Steps `a` and `b` side by side for `ticks` ticks, comparing the full world state after each.
+/// Returns the state hash of every tick if the runs agree throughout.
+pub fn compare_runs(a: &Run, b: &Run, ticks: usize) -> Result<Vec<u64>, Divergence> {
+    let (mut app_a, mut app_b) = (a.app(), b.app());
+    let mut hashes = Vec::with_capacity(ticks);
+    for tick in 1..=ticks {
+        app_a.update();
+        app_b.update();
+        let entries_a = state_entries(&mut app_a.world);
+        let entries_b = state_entries(&mut app_b.world);
+        if let Some(difference) = first_difference(&entries_a, &entries_b) {
+            return Err(Divergence { tick, difference });
+        }
+        hashes.push(state_hash(&entries_a));
+    }
+    Ok(hashes)
+}
@@ -0,0 +1,206 @@
+use crate::ants_plugin::{SimulationTick, TIME_STEP};
+use crate::arena::Arena;
+use crate::config::{Config, ConfigKey};
+use crate::helpers::obstacle_grid::ObstacleGrid;
+use crate::helpers::sim_rng::SimRng;
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Random spots tried when moving a depleted source before giving up until the next tick.
+const RESPAWN_ATTEMPTS: usize = 100;
+
+pub const FOOD_AMOUNT: ConfigKey<usize> = ConfigKey {
+    key: "food.amount",
+    default: 40,
+    min: Some(1),
+    max: None,
+    units: "units",
+    description: "Food a new source holds",
+};
+
+pub const FOOD_TAKE: ConfigKey<usize> = ConfigKey {
+    key: "food.take",
+    default: 1,
+    min: Some(1),
+    max: None,
+    units: "units",
+    description: "Food an ant takes from a source at once",
+};
+
+pub const FOOD_REGROWTH: ConfigKey<f32> = ConfigKey {
+    key: "food.regrowth",
+    default: 0.0,
+    min: Some(0.0),
+    max: None,
+    units: "units/s",
+    description: "Food a new source regrows while below its capacity",
+};
+
+/// Kind of food a source holds, which decides how much a home gains from each unit.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum FoodType {
+    Seed,
+    Fruit,
+    Insect,
+}
+
+impl FoodType {
+    pub const ALL: [FoodType; 3] = [FoodType::Seed, FoodType::Fruit, FoodType::Insect];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FoodType::Seed => "seed",
+            FoodType::Fruit => "fruit",
+            FoodType::Insect => "insect",
+        }
+    }
+
+    /// Config key of the food store units a home gains per unit delivered.
+    pub fn nutrition_key(&self) -> ConfigKey<usize> {
+        let (key, default) = match self {
+            FoodType::Seed => ("food.seed.nutrition", 1),
+            FoodType::Fruit => ("food.fruit.nutrition", 2),
+            FoodType::Insect => ("food.insect.nutrition", 4),
+        };
+        ConfigKey {
+            key,
+            default,
+            min: Some(0),
+            max: None,
+            units: "units",
+            description: "Food store units a home gains per unit of this type delivered",
+        }
+    }
+
+    pub fn nutrition(&self, config: &Config) -> usize {
+        config.get(&self.nutrition_key())
+    }
+}
+
+/// Identifies a food source independently of its `Entity`, which changes when a world is
+/// restored.
+#[derive(
+    Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize,
+)]
+pub struct FoodId(pub u64);
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct NextFoodId(pub u64);
+
+impl NextFoodId {
+    pub fn next(&mut self) -> FoodId {
+        let id = FoodId(self.0);
+        self.0 += 1;
+        id
+    }
+}
+
+/// A pile of food ants take from, a few units at a time.
+#[derive(Component, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct FoodSource {
+    pub food_type: FoodType,
+    /// Units left to take.
+    pub amount: usize,
+    /// Units the source holds when full, and regrows up to.
+    pub capacity: usize,
+    /// Units an ant takes at once.
+    pub take: usize,
+    /// Units regrown per second while below `capacity`. Zero for one-shot piles.
+    pub regrowth: f32,
+    /// Part of the next unit regrown so far.
+    pub regrowth_progress: f32,
+    /// Whether the source reappears, full, somewhere else in the arena once depleted.
+    pub respawn: bool,
+}
+
+impl FoodSource {
+    /// A full source of `food_type` as set up by the `food.amount`, `food.take` and
+    /// `food.regrowth` config entries.
+    pub fn new(food_type: FoodType, config: &Config) -> FoodSource {
+        let amount = config.get(&FOOD_AMOUNT);
+        FoodSource {
+            food_type,
+            amount,
+            capacity: amount,
+            take: config.get(&FOOD_TAKE),
+            regrowth: config.get(&FOOD_REGROWTH),
+            regrowth_progress: 0.0,
+            respawn: false,
+        }
+    }
+
+    /// Removes up to `take` units and returns how many were removed.
+    pub fn take_food(&mut self) -> usize {
+        let taken = self.take.min(self.amount);
+        self.amount -= taken;
+        taken
+    }
+
+    pub fn is_depleted(&self) -> bool {
+        self.amount == 0
+    }
+}
+
+pub fn register_config(config: &mut Config) {
+    config.register(&FOOD_AMOUNT);
+    config.register(&FOOD_TAKE);
+    config.register(&FOOD_REGROWTH);
+    for food_type in FoodType::ALL {
+        config.register(&food_type.nutrition_key());
+    }
+}
+
+/// Random spot inside the arena and off the obstacles, if one turns up.
+fn free_spot(arena: &Arena, obstacle_grid: &ObstacleGrid, rng: &mut impl Rng) -> Option<Vec2> {
+    let half_size = arena.half_size();
+    (0..RESPAWN_ATTEMPTS)
+        .map(|_| {
+            Vec2::new(
+                rng.gen_range(-half_size.x..half_size.x),
+                rng.gen_range(-half_size.y..half_size.y),
+            )
+        })
+        .find(|&pos| {
+            arena.contains(pos)
+                && obstacle_grid
+                    .tile_pos_from_world_pos(&pos.extend(0.0))
+                    .map_or(false, |tile| !obstacle_grid.is_obstacle(tile))
+        })
+}
+
+/// Regrows food sources and deals with the depleted ones: respawning sources move somewhere else
+/// in the arena and refill, regrowing ones wait to regrow and the rest are despawned. Each source
+/// draws its new spot from its own stream, so the order sources are visited in doesn't matter.
+pub fn food_source_system(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    obstacle_grid: Res<ObstacleGrid>,
+    sim_rng: Res<SimRng>,
+    tick: Res<SimulationTick>,
+    mut query: Query<(Entity, &FoodId, &mut FoodSource, &mut Transform)>,
+) {
+    for (entity, id, mut source, mut transform) in query.iter_mut() {
+        if source.amount < source.capacity {
+            source.regrowth_progress += source.regrowth * TIME_STEP;
+            let regrown = source.regrowth_progress as usize;
+            source.regrowth_progress -= regrown as f32;
+            source.amount = (source.amount + regrown).min(source.capacity);
+        } else if source.regrowth_progress != 0.0 {
+            source.regrowth_progress = 0.0;
+        }
+        if !source.is_depleted() {
+            continue;
+        }
+        if source.respawn {
+            let mut rng = sim_rng.food_rng(id.0, tick.0);
+            if let Some(pos) = free_spot(&arena, &obstacle_grid, &mut rng) {
+                transform.translation = pos.extend(transform.translation.z);
+                source.amount = source.capacity;
+                source.regrowth_progress = 0.0;
+            }
+        } else if source.regrowth <= 0.0 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -1 +1,5 @@
+pub mod obstacle_grid;
+pub mod pheromone_field;
+pub mod sim_rng;
+pub mod spatial_index;
 pub mod tilemap_utils;
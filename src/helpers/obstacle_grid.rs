@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+use bevy::sprite::collide_aabb::{collide, Collision};
+
+/// Dense grid of obstacle tiles. This is the simulation's source of truth for obstacles; the
+/// tilemap layer is only a rendering of it.
+pub struct ObstacleGrid {
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: f32,
+    /// World position of the bottom left corner of tile (0, 0).
+    pub origin: Vec2,
+    tiles: Vec<bool>,
+}
+
+impl ObstacleGrid {
+    /// Creates an empty grid of `width` x `height` tiles centered on the world origin.
+    pub fn new(width: u32, height: u32, tile_size: f32) -> ObstacleGrid {
+        ObstacleGrid {
+            width,
+            height,
+            tile_size,
+            origin: -Vec2::new(width as f32, height as f32) * tile_size / 2.0,
+            tiles: vec![false; (width * height) as usize],
+        }
+    }
+
+    pub fn tiles(&self) -> &[bool] {
+        &self.tiles
+    }
+
+    pub fn index(&self, tile_pos: UVec2) -> usize {
+        (tile_pos.y * self.width + tile_pos.x) as usize
+    }
+
+    pub fn tile_pos_from_index(&self, index: usize) -> UVec2 {
+        UVec2::new(index as u32 % self.width, index as u32 / self.width)
+    }
+
+    pub fn in_grid(&self, tile_pos: UVec2) -> bool {
+        tile_pos.x < self.width && tile_pos.y < self.height
+    }
+
+    pub fn is_obstacle(&self, tile_pos: UVec2) -> bool {
+        self.in_grid(tile_pos) && self.tiles[self.index(tile_pos)]
+    }
+
+    pub fn set_obstacle(&mut self, tile_pos: UVec2, obstacle: bool) {
+        if self.in_grid(tile_pos) {
+            let index = self.index(tile_pos);
+            self.tiles[index] = obstacle;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.tiles.iter_mut().for_each(|tile| *tile = false);
+    }
+
+    /// Returns the tile containing `world_pos`, or `None` if it lies outside the grid.
+    pub fn tile_pos_from_world_pos(&self, world_pos: &Vec3) -> Option<UVec2> {
+        let local = (world_pos.truncate() - self.origin) / self.tile_size;
+        if local.x < 0.0 || local.y < 0.0 {
+            return None;
+        }
+        let tile_pos = UVec2::new(local.x as u32, local.y as u32);
+        if self.in_grid(tile_pos) {
+            Some(tile_pos)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the world position of the center of the tile.
+    pub fn world_pos_from_tile_pos(&self, tile_pos: UVec2) -> Vec3 {
+        (self.origin + (tile_pos.as_vec2() + Vec2::splat(0.5)) * self.tile_size).extend(0.0)
+    }
+
+    /// Covers every obstacle tile with as few non-overlapping rectangles as a greedy row-major
+    /// sweep finds. Each rectangle is `(bottom left tile, size in tiles)`.
+    pub fn merged_rects(&self) -> Vec<(UVec2, UVec2)> {
+        let mut rects = Vec::new();
+        let mut covered = vec![false; self.tiles.len()];
+        let free = |covered: &[bool], tile_pos: UVec2| {
+            self.is_obstacle(tile_pos) && !covered[self.index(tile_pos)]
+        };
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let start = UVec2::new(x, y);
+                if !free(&covered, start) {
+                    continue;
+                }
+                let mut size = UVec2::ONE;
+                while free(&covered, start + UVec2::new(size.x, 0)) {
+                    size.x += 1;
+                }
+                while (0..size.x).all(|i| free(&covered, start + UVec2::new(i, size.y))) {
+                    size.y += 1;
+                }
+                for j in 0..size.y {
+                    for i in 0..size.x {
+                        covered[self.index(start + UVec2::new(i, j))] = true;
+                    }
+                }
+                rects.push((start, size));
+            }
+        }
+        rects
+    }
+
+    pub fn collide_rect(&self, pos: Vec3, dimensions: Vec2) -> Vec<Collision> {
+        let mut collisions = Vec::new();
+        let tile_size = Vec2::splat(self.tile_size);
+        let bottom_left = ((pos.truncate() - dimensions / 2.0 - self.origin) / self.tile_size)
+            .floor()
+            .max(Vec2::ZERO);
+        let top_right = ((pos.truncate() + dimensions / 2.0 - self.origin) / self.tile_size)
+            .floor()
+            .min(Vec2::new(self.width as f32 - 1.0, self.height as f32 - 1.0));
+        if top_right.x < 0.0 || top_right.y < 0.0 {
+            return collisions;
+        }
+        for i in bottom_left.x as u32..=top_right.x as u32 {
+            for j in bottom_left.y as u32..=top_right.y as u32 {
+                let tile_pos = UVec2::new(i, j);
+                if !self.is_obstacle(tile_pos) {
+                    continue;
+                }
+                let tile_world_pos = self.world_pos_from_tile_pos(tile_pos);
+                if let Some(collision) = collide(pos, dimensions, tile_world_pos, tile_size) {
+                    collisions.push(collision);
+                }
+            }
+        }
+        collisions
+    }
+}
@@ -0,0 +1,248 @@
+use bevy::prelude::*;
+
+/// Pheromone strengths below this are dropped to zero by `evaporate`.
+pub const PHEROMONE_MIN_STRENGTH: f32 = 0.01;
+/// Gaussian kernels reach out this many standard deviations.
+const KERNEL_RADIUS_SIGMAS: f32 = 3.0;
+
+/// Dense grids of pheromone strength, one channel per trail type, covering the same area as the
+/// obstacle grid.
+pub struct PheromoneField {
+    pub width: u32,
+    pub height: u32,
+    pub cell_size: f32,
+    /// World position of the bottom left corner of cell (0, 0).
+    pub origin: Vec2,
+    channels: Vec<Vec<f32>>,
+    scratch: Vec<f32>,
+}
+
+impl PheromoneField {
+    /// Creates an empty field of `width` x `height` cells centered on the world origin.
+    pub fn new(width: u32, height: u32, cell_size: f32, num_channels: usize) -> PheromoneField {
+        let num_cells = (width * height) as usize;
+        PheromoneField {
+            width,
+            height,
+            cell_size,
+            origin: -Vec2::new(width as f32, height as f32) * cell_size / 2.0,
+            channels: vec![vec![0.0; num_cells]; num_channels],
+            scratch: vec![0.0; num_cells],
+        }
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn channel(&self, channel: usize) -> &[f32] {
+        &self.channels[channel]
+    }
+
+    pub fn channel_mut(&mut self, channel: usize) -> &mut [f32] {
+        &mut self.channels[channel]
+    }
+
+    pub fn num_cells(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+
+    /// World position of the center of cell (`x`, `y`).
+    pub fn cell_center(&self, x: u32, y: u32) -> Vec2 {
+        self.origin + (Vec2::new(x as f32, y as f32) + Vec2::splat(0.5)) * self.cell_size
+    }
+
+    fn cell_from_world_pos(&self, world_pos: Vec2) -> Option<(u32, u32)> {
+        let local = (world_pos - self.origin) / self.cell_size;
+        if local.x < 0.0 || local.y < 0.0 {
+            return None;
+        }
+        let (x, y) = (local.x as u32, local.y as u32);
+        if x < self.width && y < self.height {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    /// Adds `amount` of pheromone to the cell containing `world_pos`.
+    pub fn deposit(&mut self, channel: usize, world_pos: Vec3, amount: f32) {
+        if let Some((x, y)) = self.cell_from_world_pos(world_pos.truncate()) {
+            let index = (y * self.width + x) as usize;
+            self.channels[channel][index] += amount;
+        }
+    }
+
+    /// Sums the pheromone of every cell whose center lies within `radius` of `world_pos`.
+    pub fn sample(&self, channel: usize, world_pos: Vec3, radius: f32) -> f32 {
+        let center = world_pos.truncate();
+        let min = ((center - Vec2::splat(radius) - self.origin) / self.cell_size)
+            .floor()
+            .max(Vec2::ZERO);
+        let max = ((center + Vec2::splat(radius) - self.origin) / self.cell_size)
+            .floor()
+            .min(Vec2::new(self.width as f32 - 1.0, self.height as f32 - 1.0));
+        if max.x < 0.0 || max.y < 0.0 {
+            return 0.0;
+        }
+        let cells = &self.channels[channel];
+        let mut total = 0.0;
+        for y in min.y as u32..=max.y as u32 {
+            for x in min.x as u32..=max.x as u32 {
+                if (self.cell_center(x, y) - center).length() < radius {
+                    total += cells[(y * self.width + x) as usize];
+                }
+            }
+        }
+        total
+    }
+
+    /// Multiplies every cell by `factor`, zeroing cells that fall below `PHEROMONE_MIN_STRENGTH`.
+    pub fn evaporate(&mut self, factor: f32) {
+        for cells in self.channels.iter_mut() {
+            for cell in cells.iter_mut() {
+                *cell *= factor;
+                if *cell < PHEROMONE_MIN_STRENGTH {
+                    *cell = 0.0;
+                }
+            }
+        }
+    }
+
+    /// Spreads each cell's pheromone over its neighbours with a Gaussian kernel of standard
+    /// deviation `sigma` cells, along rows and then along columns. Pheromone never crosses a
+    /// `blocked` cell or the border of the field; the share that would stays where it was.
+    pub fn diffuse(&mut self, sigma: f32, blocked: &[bool]) {
+        if sigma <= 0.0 {
+            return;
+        }
+        let kernel = gaussian_kernel(sigma);
+        let (width, height) = (self.width as usize, self.height as usize);
+        for cells in self.channels.iter_mut() {
+            spread_lines(
+                cells,
+                &mut self.scratch,
+                &kernel,
+                blocked,
+                width,
+                height,
+                |row, x| row * width + x,
+            );
+            spread_lines(
+                &self.scratch,
+                cells,
+                &kernel,
+                blocked,
+                height,
+                width,
+                |column, y| y * width + column,
+            );
+        }
+    }
+
+    /// Carries pheromone along `velocity`, given per cell in cells per step: each cell's
+    /// pheromone moves to where its center ends up and is shared bilinearly between the four
+    /// cells around that point. Pheromone carried past the border of the field is lost, while the
+    /// share that would land on a `blocked` cell stays where it was.
+    pub fn advect(&mut self, velocity: &[Vec2], blocked: &[bool]) {
+        let (width, height) = (self.width as i64, self.height as i64);
+        for cells in self.channels.iter_mut() {
+            self.scratch.iter_mut().for_each(|cell| *cell = 0.0);
+            for (index, &amount) in cells.iter().enumerate() {
+                if amount == 0.0 {
+                    continue;
+                }
+                let (x, y) = (index as i64 % width, index as i64 / width);
+                let target = Vec2::new(x as f32, y as f32) + velocity[index];
+                let base = target.floor();
+                let fraction = target - base;
+                let corners = [
+                    (0, 0, (1.0 - fraction.x) * (1.0 - fraction.y)),
+                    (1, 0, fraction.x * (1.0 - fraction.y)),
+                    (0, 1, (1.0 - fraction.x) * fraction.y),
+                    (1, 1, fraction.x * fraction.y),
+                ];
+                for (dx, dy, weight) in corners {
+                    let (target_x, target_y) = (base.x as i64 + dx, base.y as i64 + dy);
+                    if target_x < 0 || target_y < 0 || target_x >= width || target_y >= height {
+                        continue;
+                    }
+                    let target_index = (target_y * width + target_x) as usize;
+                    if blocked[target_index] {
+                        self.scratch[index] += amount * weight;
+                    } else {
+                        self.scratch[target_index] += amount * weight;
+                    }
+                }
+            }
+            std::mem::swap(cells, &mut self.scratch);
+        }
+    }
+
+    /// Zeroes every `blocked` cell.
+    pub fn clear_blocked(&mut self, blocked: &[bool]) {
+        for cells in self.channels.iter_mut() {
+            for (cell, &blocked) in cells.iter_mut().zip(blocked.iter()) {
+                if blocked {
+                    *cell = 0.0;
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for cells in self.channels.iter_mut() {
+            cells.iter_mut().for_each(|cell| *cell = 0.0);
+        }
+    }
+}
+
+/// Weights of a normalized Gaussian kernel for offsets 0, 1, 2, ... cells from its center.
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius = (sigma * KERNEL_RADIUS_SIGMAS).ceil().max(1.0) as usize;
+    let mut kernel: Vec<f32> = (0..=radius)
+        .map(|offset| (-((offset * offset) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total = kernel[0] + 2.0 * kernel[1..].iter().sum::<f32>();
+    kernel.iter_mut().for_each(|weight| *weight /= total);
+    kernel
+}
+
+/// Convolves each of `lines` lines of `len` cells of `src` with the symmetric `kernel` into
+/// `dst`, where `index(line, i)` is the index of the `i`th cell of `line`. A cell's share stops
+/// at the first blocked cell or the end of the line in each direction and stays in the cell.
+fn spread_lines(
+    src: &[f32],
+    dst: &mut [f32],
+    kernel: &[f32],
+    blocked: &[bool],
+    len: usize,
+    lines: usize,
+    index: impl Fn(usize, usize) -> usize,
+) {
+    dst.iter_mut().for_each(|cell| *cell = 0.0);
+    for line in 0..lines {
+        for i in 0..len {
+            let from = index(line, i);
+            let amount = src[from];
+            if amount == 0.0 {
+                continue;
+            }
+            let mut kept = amount * kernel[0];
+            for direction in [-1, 1] {
+                let mut open = true;
+                for (offset, &weight) in kernel.iter().enumerate().skip(1) {
+                    let j = i as isize + direction * offset as isize;
+                    open =
+                        open && j >= 0 && (j as usize) < len && !blocked[index(line, j as usize)];
+                    if open {
+                        dst[index(line, j as usize)] += amount * weight;
+                    } else {
+                        kept += amount * weight;
+                    }
+                }
+            }
+            dst[from] += kept;
+        }
+    }
+}
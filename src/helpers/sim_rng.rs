@@ -0,0 +1,121 @@
+use rand::RngCore;
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// Words each per-ant stream may consume per tick before running into the next tick's words.
+const ANT_STREAM_WORDS_PER_TICK: u128 = 16;
+/// Words each per-food-source stream may consume per tick, enough for every spot a respawn tries.
+const FOOD_STREAM_WORDS_PER_TICK: u128 = 256;
+/// First stream of the per-food-source streams, well past the streams of any ant.
+const FOOD_STREAM_BASE: u64 = 1 << 63;
+
+/// Independent random streams every ant draws from. Each ant has its own copy of each stream.
+#[derive(Clone, Copy)]
+pub enum AntStream {
+    TrailSpawn,
+    Wandering,
+    /// Drawn once, at the tick the ant is born.
+    Birth,
+}
+
+impl AntStream {
+    const COUNT: u64 = 3;
+}
+
+/// The single source of randomness for the simulation. Global draws, such as scattering food,
+/// come from the stateful stream 0. Per-ant draws come from `ant_rng` and per-food-source draws
+/// from `food_rng`, which depend only on the seed, the id and the tick, so they are the same
+/// whatever order ants and sources are visited in.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SimRng {
+    seed: i32,
+    rng: ChaCha8Rng,
+}
+
+impl SimRng {
+    pub fn new(seed: i32) -> SimRng {
+        SimRng {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed as u64),
+        }
+    }
+
+    pub fn seed(&self) -> i32 {
+        self.seed
+    }
+
+    /// Number of 32-bit words drawn from the global stream so far.
+    pub fn word_pos(&self) -> u128 {
+        self.rng.get_word_pos()
+    }
+
+    /// Returns the generator for `stream` of ant `ant_id` at `tick`.
+    pub fn ant_rng(&self, ant_id: u64, stream: AntStream, tick: usize) -> ChaCha8Rng {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed as u64);
+        rng.set_stream(1 + ant_id * AntStream::COUNT + stream as u64);
+        rng.set_word_pos(tick as u128 * ANT_STREAM_WORDS_PER_TICK);
+        rng
+    }
+
+    /// Returns the generator of food source `food_id` at `tick`.
+    pub fn food_rng(&self, food_id: u64, tick: usize) -> ChaCha8Rng {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed as u64);
+        rng.set_stream(FOOD_STREAM_BASE + food_id);
+        rng.set_word_pos(tick as u128 * FOOD_STREAM_WORDS_PER_TICK);
+        rng
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draws(mut rng: impl RngCore) -> [u64; 4] {
+        [(); 4].map(|_| rng.next_u64())
+    }
+
+    #[test]
+    fn ant_rng_depends_only_on_its_inputs() {
+        let rng = SimRng::new(7);
+        let mut used = SimRng::new(7);
+        draws(&mut used);
+        assert_eq!(
+            draws(rng.ant_rng(3, AntStream::Wandering, 10)),
+            draws(used.ant_rng(3, AntStream::Wandering, 10))
+        );
+    }
+
+    #[test]
+    fn ant_rng_streams_are_independent() {
+        let rng = SimRng::new(7);
+        let base = draws(rng.ant_rng(3, AntStream::Wandering, 10));
+        for other in [
+            rng.ant_rng(4, AntStream::Wandering, 10),
+            rng.ant_rng(3, AntStream::TrailSpawn, 10),
+            rng.ant_rng(3, AntStream::Wandering, 11),
+            SimRng::new(8).ant_rng(3, AntStream::Wandering, 10),
+        ] {
+            assert_ne!(draws(other), base);
+        }
+        assert_ne!(draws(SimRng::new(7)), base);
+    }
+}
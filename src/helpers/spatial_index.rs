@@ -0,0 +1,142 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// Uniform grid spatial hash of the positions of every entity with component `T`, rebuilt each
+/// simulation step by `spatial_index_system::<T>`.
+pub struct SpatialIndex<T> {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<(Entity, Vec2)>>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> SpatialIndex<T> {
+    pub fn new(cell_size: f32) -> SpatialIndex<T> {
+        SpatialIndex {
+            cell_size,
+            cells: HashMap::new(),
+            marker: PhantomData,
+        }
+    }
+
+    fn cell(&self, pos: Vec2) -> IVec2 {
+        (pos / self.cell_size).floor().as_ivec2()
+    }
+
+    /// Removes every entry while keeping the allocated cells for reuse.
+    pub fn clear(&mut self) {
+        for entries in self.cells.values_mut() {
+            entries.clear();
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity, pos: Vec2) {
+        let cell = self.cell(pos);
+        self.cells.entry(cell).or_default().push((entity, pos));
+    }
+
+    /// Returns every entry with a position inside the axis aligned box `[min, max]`.
+    pub fn query_aabb(&self, min: Vec2, max: Vec2) -> Vec<(Entity, Vec2)> {
+        let mut result = Vec::new();
+        let (min_cell, max_cell) = (self.cell(min), self.cell(max));
+        for y in min_cell.y..=max_cell.y {
+            for x in min_cell.x..=max_cell.x {
+                if let Some(entries) = self.cells.get(&IVec2::new(x, y)) {
+                    result.extend(entries.iter().filter(|(_, pos)| {
+                        pos.x >= min.x && pos.x <= max.x && pos.y >= min.y && pos.y <= max.y
+                    }));
+                }
+            }
+        }
+        result
+    }
+
+    /// Returns every entry with a position strictly within `radius` of `center`.
+    pub fn query_radius(&self, center: Vec2, radius: f32) -> Vec<(Entity, Vec2)> {
+        let mut result =
+            self.query_aabb(center - Vec2::splat(radius), center + Vec2::splat(radius));
+        result.retain(|(_, pos)| (*pos - center).length_squared() < radius * radius);
+        result
+    }
+}
+
+/// Rebuilds `SpatialIndex<T>` from the translation of every unparented entity with `T`.
+pub fn spatial_index_system<T: Component>(
+    mut index: ResMut<SpatialIndex<T>>,
+    query: Query<(Entity, &Transform), (With<T>, Without<Parent>)>,
+) {
+    index.clear();
+    for (entity, transform) in query.iter() {
+        index.insert(entity, transform.translation.truncate());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Marker;
+
+    fn index(positions: &[Vec2]) -> SpatialIndex<Marker> {
+        let mut index = SpatialIndex::new(10.0);
+        for (i, pos) in positions.iter().enumerate() {
+            index.insert(Entity::from_raw(i as u32), *pos);
+        }
+        index
+    }
+
+    fn ids(mut entries: Vec<(Entity, Vec2)>) -> Vec<u32> {
+        entries.sort_by_key(|(entity, _)| entity.id());
+        entries.iter().map(|(entity, _)| entity.id()).collect()
+    }
+
+    #[test]
+    fn query_aabb_includes_the_bounds() {
+        let index = index(&[
+            Vec2::new(0.0, 0.0),
+            Vec2::new(20.0, 20.0),
+            Vec2::new(20.1, 5.0),
+            Vec2::new(5.0, -0.1),
+        ]);
+        assert_eq!(
+            ids(index.query_aabb(Vec2::ZERO, Vec2::splat(20.0))),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn query_aabb_spans_negative_cells() {
+        let index = index(&[
+            Vec2::new(-25.0, -5.0),
+            Vec2::new(-0.5, 0.5),
+            Vec2::new(15.0, 35.0),
+            Vec2::new(-31.0, 0.0),
+        ]);
+        assert_eq!(
+            ids(index.query_aabb(Vec2::new(-30.0, -10.0), Vec2::new(20.0, 40.0))),
+            vec![0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn query_radius_excludes_the_boundary() {
+        let index = index(&[
+            Vec2::new(3.0, 4.0),
+            Vec2::new(4.9, 0.0),
+            Vec2::new(0.0, -5.0),
+            Vec2::new(4.0, 4.0),
+        ]);
+        assert_eq!(ids(index.query_radius(Vec2::ZERO, 5.0)), vec![1]);
+    }
+
+    #[test]
+    fn clear_removes_every_entry() {
+        let mut index = index(&[Vec2::new(1.0, 1.0), Vec2::new(-50.0, 80.0)]);
+        index.clear();
+        assert!(index
+            .query_aabb(Vec2::splat(-100.0), Vec2::splat(100.0))
+            .is_empty());
+        index.insert(Entity::from_raw(7), Vec2::new(1.0, 1.0));
+        assert_eq!(ids(index.query_radius(Vec2::ZERO, 2.0)), vec![7]);
+    }
+}
@@ -72,20 +72,150 @@ pub fn collide_tiles_with_rect(
     return collisions;
 }
 
-// pub fn collide_tiles_with_sector(
-//     pos: Vec3,
-//     radius: f32,
-//     angle1: f32,
-//     angle2: f32,
-//     map_query: &mut MapQuery,
-//     map_transform: &Transform,
-//     map_id: u16,
-//     layer_id: u16,
-// ) -> Vec<Collision> {
-//     // https://stackoverflow.com/questions/13652518/efficiently-find-points-inside-a-circle-sector
-//     let mut collisions = Vec::new();
-//     return collisions;
-// }
+/// Returns whether `point` lies within `radius` of `center` and counterclockwise from `angle1`
+/// and clockwise from `angle2`, angles being measured from the x axis.
+pub fn in_sector(point: Vec2, center: Vec2, radius: f32, angle1: f32, angle2: f32) -> bool {
+    // https://stackoverflow.com/questions/13652518/efficiently-find-points-inside-a-circle-sector
+    let relative = point - center;
+    if relative.length_squared() > radius * radius {
+        return false;
+    }
+    let start = Vec2::new(angle1.cos(), angle1.sin());
+    let end = Vec2::new(angle2.cos(), angle2.sin());
+    let clockwise_from = |arm: Vec2| -arm.x * relative.y + arm.y * relative.x > 0.0;
+    let counterclockwise_from_start = !clockwise_from(start);
+    let clockwise_from_end = clockwise_from(end);
+    if start.perp_dot(end) >= 0.0 {
+        // sector no wider than half a circle
+        counterclockwise_from_start && clockwise_from_end
+    } else {
+        counterclockwise_from_start || clockwise_from_end
+    }
+}
+
+/// Returns the position of every tile whose center lies in the sector of `radius` around `pos`
+/// going counterclockwise from `angle1` to `angle2`.
+pub fn collide_tiles_with_sector(
+    pos: Vec3,
+    radius: f32,
+    angle1: f32,
+    angle2: f32,
+    map_query: &mut MapQuery,
+    map_transform: &Transform,
+    map_id: u16,
+    layer_id: u16,
+) -> Vec<TilePos> {
+    let mut tiles = Vec::new();
+    let bounding_box = Vec2::splat(radius * 2.0);
+    let tile_pos_bottom_left = tile_pos_from_world_pos(
+        &(pos - bounding_box.extend(0.0) / 2.0),
+        map_query,
+        map_transform,
+        map_id,
+        layer_id,
+    );
+    let tile_pos_top_right = tile_pos_from_world_pos(
+        &(pos + bounding_box.extend(0.0) / 2.0),
+        map_query,
+        map_transform,
+        map_id,
+        layer_id,
+    );
+    for i in tile_pos_bottom_left.0..=tile_pos_top_right.0 {
+        for j in tile_pos_bottom_left.1..=tile_pos_top_right.1 {
+            if map_query
+                .get_tile_entity(TilePos(i, j), map_id, layer_id)
+                .is_err()
+            {
+                continue;
+            }
+            let tile_world_pos =
+                world_pos_from_tile_pos(TilePos(i, j), map_query, map_transform, map_id, layer_id);
+            if in_sector(
+                tile_world_pos.truncate(),
+                pos.truncate(),
+                radius,
+                angle1,
+                angle2,
+            ) {
+                tiles.push(TilePos(i, j));
+            }
+        }
+    }
+    tiles
+}
+
+/// The first solid tile a ray cast with `raycast_tiles` runs into.
+#[derive(Clone, Copy, Debug)]
+pub struct TileHit {
+    pub tile: IVec2,
+    /// Distance along the ray to where it enters the tile, in world units.
+    pub distance: f32,
+    /// Unit normal of the tile side the ray enters through.
+    pub normal: Vec2,
+}
+
+/// Walks a ray from `start` along `direction` through a grid of `tile_size` tiles whose tile
+/// (0, 0) has its bottom left corner at `grid_origin`, visiting tiles in the order the ray
+/// crosses them (DDA). Returns the first tile within `max_distance` for which `is_solid` is true.
+/// The tile containing `start` is never tested.
+pub fn raycast_tiles(
+    grid_origin: Vec2,
+    tile_size: f32,
+    start: Vec2,
+    direction: Vec2,
+    max_distance: f32,
+    mut is_solid: impl FnMut(IVec2) -> bool,
+) -> Option<TileHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec2::ZERO {
+        return None;
+    }
+    let local = (start - grid_origin) / tile_size;
+    let mut tile = local.floor().as_ivec2();
+    let step = IVec2::new(
+        if direction.x < 0.0 { -1 } else { 1 },
+        if direction.y < 0.0 { -1 } else { 1 },
+    );
+    // distance along the ray between two crossings of a vertical (x) or horizontal (y) tile side
+    let side_step = (tile_size / direction).abs();
+    let first_side = |local: f32, tile: i32, direction: f32, side_step: f32| {
+        if direction > 0.0 {
+            (tile as f32 + 1.0 - local) * side_step
+        } else if direction < 0.0 {
+            (local - tile as f32) * side_step
+        } else {
+            f32::INFINITY
+        }
+    };
+    let mut next_side = Vec2::new(
+        first_side(local.x, tile.x, direction.x, side_step.x),
+        first_side(local.y, tile.y, direction.y, side_step.y),
+    );
+    loop {
+        let (distance, normal) = if next_side.x < next_side.y {
+            let distance = next_side.x;
+            next_side.x += side_step.x;
+            tile.x += step.x;
+            (distance, Vec2::new(-step.x as f32, 0.0))
+        } else {
+            let distance = next_side.y;
+            next_side.y += side_step.y;
+            tile.y += step.y;
+            (distance, Vec2::new(0.0, -step.y as f32))
+        };
+        if distance > max_distance {
+            return None;
+        }
+        if is_solid(tile) {
+            return Some(TileHit {
+                tile,
+                distance,
+                normal,
+            });
+        }
+    }
+}
 
 pub fn despawn_layer_tiles_and_notify_chunks(
     commands: &mut Commands,
@@ -109,6 +239,113 @@ pub fn despawn_layer_tiles_and_notify_chunks(
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    fn at_angle(angle: f32, distance: f32) -> Vec2 {
+        Vec2::new(angle.cos(), angle.sin()) * distance
+    }
+
+    #[test]
+    fn in_sector_narrow() {
+        let center = Vec2::new(5.0, -3.0);
+        let in_sector = |point| in_sector(center + point, center, 10.0, 0.0, FRAC_PI_2);
+        assert!(in_sector(at_angle(FRAC_PI_4, 5.0)));
+        assert!(in_sector(at_angle(0.1, 9.9)));
+        assert!(!in_sector(at_angle(FRAC_PI_4, 10.1)));
+        assert!(!in_sector(at_angle(-0.1, 5.0)));
+        assert!(!in_sector(at_angle(FRAC_PI_2 + 0.1, 5.0)));
+        assert!(!in_sector(at_angle(PI + FRAC_PI_4, 5.0)));
+    }
+
+    #[test]
+    fn in_sector_across_the_x_axis() {
+        let in_sector = |point| in_sector(point, Vec2::ZERO, 10.0, -FRAC_PI_4, FRAC_PI_4);
+        assert!(in_sector(at_angle(0.0, 5.0)));
+        assert!(in_sector(at_angle(-0.5, 5.0)));
+        assert!(!in_sector(at_angle(-1.0, 5.0)));
+        assert!(!in_sector(at_angle(PI, 5.0)));
+    }
+
+    #[test]
+    fn in_sector_wider_than_half_a_circle() {
+        // three quarters of a circle, everything but the fourth quadrant
+        let in_sector = |point| in_sector(point, Vec2::ZERO, 10.0, 0.0, 3.0 * FRAC_PI_2);
+        assert!(in_sector(at_angle(FRAC_PI_4, 5.0)));
+        assert!(in_sector(at_angle(PI, 5.0)));
+        assert!(in_sector(at_angle(PI + FRAC_PI_4, 5.0)));
+        assert!(!in_sector(at_angle(-FRAC_PI_4, 5.0)));
+        assert!(!in_sector(at_angle(PI, 10.5)));
+    }
+
+    /// A grid of 10 unit tiles with its origin at (-50, -50), solid where `solid` says.
+    fn raycast(
+        start: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        solid: impl Fn(IVec2) -> bool,
+    ) -> Option<TileHit> {
+        raycast_tiles(
+            Vec2::splat(-50.0),
+            10.0,
+            start,
+            direction,
+            max_distance,
+            solid,
+        )
+    }
+
+    #[test]
+    fn raycast_along_the_axes() {
+        // a ring of solid tiles around tile (5, 5), which spans 0..10 on both axes
+        let ring = |tile: IVec2| (tile - IVec2::new(5, 5)).abs().max_element() == 2;
+        let start = Vec2::new(4.0, 3.0);
+        for (direction, tile, distance, normal) in [
+            (Vec2::X, IVec2::new(7, 5), 16.0, Vec2::new(-1.0, 0.0)),
+            (-Vec2::X, IVec2::new(3, 5), 14.0, Vec2::new(1.0, 0.0)),
+            (Vec2::Y, IVec2::new(5, 7), 17.0, Vec2::new(0.0, -1.0)),
+            (-Vec2::Y, IVec2::new(5, 3), 13.0, Vec2::new(0.0, 1.0)),
+        ] {
+            let hit = raycast(start, direction, 100.0, ring).unwrap();
+            assert_eq!(hit.tile, tile, "{}", direction);
+            assert!((hit.distance - distance).abs() < 1e-4, "{}", direction);
+            assert_eq!(hit.normal, normal, "{}", direction);
+        }
+    }
+
+    #[test]
+    fn raycast_diagonal() {
+        let wall = |tile: IVec2| tile.x == 8;
+        let hit = raycast(Vec2::new(5.0, 2.0), Vec2::new(1.0, 1.0), 100.0, wall).unwrap();
+        assert_eq!(hit.tile, IVec2::new(8, 7));
+        assert!((hit.distance - 25.0 * 2f32.sqrt()).abs() < 1e-3);
+        assert_eq!(hit.normal, Vec2::new(-1.0, 0.0));
+    }
+
+    #[test]
+    fn raycast_from_a_tile_edge() {
+        let wall = |tile: IVec2| tile.x == 4;
+        // on the edge between tiles 4 and 5, so inside tile 5, which is never tested
+        let start = Vec2::new(0.0, 5.0);
+        let hit = raycast(start, -Vec2::X, 100.0, wall).unwrap();
+        assert_eq!(hit.tile, IVec2::new(4, 5));
+        assert_eq!(hit.distance, 0.0);
+        assert!(raycast(start, Vec2::X, 100.0, wall).is_none());
+    }
+
+    #[test]
+    fn raycast_stops_at_max_distance() {
+        let wall = |tile: IVec2| tile.x == 7;
+        let start = Vec2::new(5.0, 5.0);
+        // the wall starts 15 units away
+        assert!(raycast(start, Vec2::X, 14.9, wall).is_none());
+        assert_eq!(raycast(start, Vec2::X, 15.0, wall).unwrap().distance, 15.0);
+        assert!(raycast(start, Vec2::ZERO, 100.0, |_| true).is_none());
+    }
+}
+
 // #[cfg(test)]
 // mod tests {
 //     use bevy::prelude::*;
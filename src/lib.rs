@@ -0,0 +1,19 @@
+pub mod ants_plugin;
+pub mod ants_render_plugin;
+pub mod arena;
+pub mod behavior;
+pub mod caste;
+pub mod config;
+pub mod console_commands;
+#[cfg(feature = "console")]
+pub mod console_debug_plugin;
+pub mod determinism;
+pub mod food;
+pub mod helpers;
+pub mod locomotion;
+pub mod pheromone_dynamics;
+pub mod scenario;
+pub mod sim_commands;
+pub mod snapshot;
+pub mod stats;
+pub mod vision;
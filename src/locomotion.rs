@@ -0,0 +1,157 @@
+use crate::ants_plugin::{Ant, ANT_SPEED, TIME_STEP};
+use crate::behavior::AntState;
+use crate::caste::{Caste, CasteProfile};
+use crate::config::Config;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use nalgebra::Vector2;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// How an ant turns its `DesiredHeading` into motion. Steering is the same for every ant; only
+/// the backend differs.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Locomotion {
+    /// Faces the heading and walks at the speed of its `AntState` and `Caste` by editing
+    /// `Transform` directly.
+    Kinematic,
+    /// Pushed towards the heading by the motor, grip and turning forces of a `RigidBodyMotor`,
+    /// so it bumps into other ants and slides along walls.
+    RigidBody,
+}
+
+/// Locomotion new ants are born with, set by the loaded scenario.
+pub struct DefaultLocomotion(pub Locomotion);
+
+impl Default for DefaultLocomotion {
+    fn default() -> DefaultLocomotion {
+        DefaultLocomotion(Locomotion::Kinematic)
+    }
+}
+
+/// Direction steering wants the ant to face this tick, as an angle from the x axis.
+#[derive(Component, Clone, Copy, Default)]
+pub struct DesiredHeading(pub f32);
+
+/// Force parameters of an ant with `Locomotion::RigidBody`, in physics units. The default is the
+/// motor of an ant of speed 1.0.
+#[derive(Component)]
+pub struct RigidBodyMotor {
+    motor_force: f32,
+    grip_force: f32,
+    turning_torque: f32,
+}
+
+impl Default for RigidBodyMotor {
+    fn default() -> RigidBodyMotor {
+        RigidBodyMotor {
+            motor_force: 4.0,
+            grip_force: 5.0,
+            turning_torque: 2.0,
+        }
+    }
+}
+
+impl RigidBodyMotor {
+    /// Motor of an ant born into a caste with `profile`. Motor force and turning torque scale with
+    /// the caste's speed, so faster castes also get up to speed and turn faster.
+    pub fn new(profile: &CasteProfile) -> RigidBodyMotor {
+        let motor = RigidBodyMotor::default();
+        RigidBodyMotor {
+            motor_force: motor.motor_force * profile.speed,
+            turning_torque: motor.turning_torque * profile.speed,
+            ..motor
+        }
+    }
+}
+
+/// Signed angle to turn by to get from `from` to `to`, in `[-PI, PI)`.
+pub fn angle_difference(to: f32, from: f32) -> f32 {
+    (to - from + PI).rem_euclid(2.0 * PI) - PI
+}
+
+pub fn kinematic_locomotion_system(
+    config: Res<Config>,
+    mut ant_query: Query<
+        (
+            &Locomotion,
+            &Caste,
+            &AntState,
+            &DesiredHeading,
+            &mut Transform,
+        ),
+        With<Ant>,
+    >,
+) {
+    let speed = config.get(&ANT_SPEED);
+    for (&locomotion, caste, state, heading, mut transform) in ant_query.iter_mut() {
+        if locomotion != Locomotion::Kinematic {
+            continue;
+        }
+        transform.rotation = Quat::from_rotation_z(heading.0);
+        let velocity = transform.rotation
+            * Vec3::X
+            * speed
+            * state.params(&config).speed
+            * caste.profile(&config).speed;
+        transform.translation += velocity * TIME_STEP;
+    }
+}
+
+/// Drives rigid body ants forward at the speed of their `AntState` and `Caste` and turns them
+/// towards their heading, slowing down while the heading is far off. The arrow keys add manual
+/// turning and braking when a keyboard is available.
+pub fn rigid_body_locomotion_system(
+    keys: Option<Res<Input<KeyCode>>>,
+    config: Res<Config>,
+    rapier_configuration: Res<RapierConfiguration>,
+    mut rigid_bodies: Query<(
+        &RigidBodyMotor,
+        &Caste,
+        &AntState,
+        &DesiredHeading,
+        &mut RigidBodyForcesComponent,
+        &RigidBodyVelocityComponent,
+        &RigidBodyPositionComponent,
+    )>,
+) {
+    let pressed = |key_code| keys.as_ref().map_or(false, |keys| keys.pressed(key_code));
+    let speed = config.get(&ANT_SPEED) / rapier_configuration.scale;
+    for (motor, caste, state, heading, mut rb_forces, rb_vel, rb_pos) in rigid_bodies.iter_mut() {
+        let heading_error = angle_difference(heading.0, rb_pos.position.rotation.angle());
+
+        // Motor forces
+        let object_x_axis = rb_pos.position.rotation * Vector2::x_axis();
+        let object_x_velocity = rb_vel.linvel.dot(&object_x_axis) * object_x_axis.into_inner();
+        let mut force = Vector2::zeros();
+        if !pressed(KeyCode::Down) {
+            let target_speed = speed
+                * state.params(&config).speed
+                * caste.profile(&config).speed
+                * heading_error.cos().max(0.0);
+            force += object_x_axis.into_inner()
+                * (target_speed - object_x_velocity.norm())
+                * motor.motor_force;
+        }
+
+        // Grip forces
+        let object_y_axis = rb_pos.position.rotation * Vector2::y_axis();
+        let object_y_velocity = rb_vel.linvel.dot(&object_y_axis) * object_y_axis.into_inner();
+        force -= object_y_velocity * motor.grip_force;
+
+        // Steering towards the desired heading
+        let mut torque = motor.turning_torque * heading_error;
+
+        // Turning input
+        if pressed(KeyCode::Left) {
+            torque += motor.turning_torque;
+        }
+        if pressed(KeyCode::Right) {
+            torque -= motor.turning_torque;
+        }
+
+        // set rather than added, so each tick's step applies that tick's forces only
+        rb_forces.force = force;
+        rb_forces.torque = torque;
+    }
+}
@@ -1,16 +1,140 @@
-mod ants_plugin;
-// mod console_debug_plugin;
-mod helpers;
-
+use ants_sim::config::ConfigFileError;
+use ants_sim::{ants_plugin, ants_render_plugin, arena, snapshot};
 // use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use bevy::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
+    let matches = clap::App::new("ants_sim")
+        .arg(clap::arg!(--headless "run the simulation without a window or renderer"))
+        .arg(
+            clap::arg!(--ticks <TICKS> "number of ticks to simulate in headless mode")
+                .default_value("3600"),
+        )
+        .arg(clap::arg!(--scenario [FILE] "scenario file to load instead of the default world"))
+        .arg(clap::arg!(--seed [SEED] "seed for all simulation randomness"))
+        .arg(clap::arg!(--stats [FILE] "CSV or JSON lines file to write colony statistics to"))
+        .arg(clap::arg!(--snapshot [FILE] "snapshot file to resume a checkpointed run from"))
+        .arg(clap::arg!(
+            --"save-snapshot" [FILE] "snapshot file to write when headless mode finishes"
+        ))
+        .arg(clap::arg!(--"arena-size" [SIZE] "arena width and height, as WIDTHxHEIGHT"))
+        .arg(clap::arg!(--"arena-shape" [SHAPE] "arena shape: rectangle or circle"))
+        .arg(clap::arg!(--"arena-boundary" [BOUNDARY] "arena boundary: solid, wrap or absorb"))
+        .arg(clap::arg!(--config [FILE] "TOML file of config entries to apply over the defaults"))
+        .arg(
+            clap::arg!(--set [ENTRY] "config entry to set, as KEY=VALUE; applied after --config \
+                and ANTS_SIM_* environment variables")
+            .multiple_occurrences(true),
+        )
+        .arg(clap::arg!(
+            --"dump-config" [FILE] "TOML file to write the effective config to after startup"
+        ))
+        .get_matches();
+    let mut arena = arena::Arena::default();
+    if let Some(size) = matches.value_of("arena-size") {
+        arena.size = arena::parse_size(size).unwrap_or_else(|e| exit_with_error(e));
+    }
+    if matches.is_present("arena-shape") {
+        arena.shape = matches
+            .value_of_t("arena-shape")
+            .unwrap_or_else(|e| e.exit());
+    }
+    if matches.is_present("arena-boundary") {
+        arena.boundary = matches
+            .value_of_t("arena-boundary")
+            .unwrap_or_else(|e| e.exit());
+    }
+    let sets: Vec<(String, String)> = matches
+        .values_of("set")
+        .into_iter()
+        .flatten()
+        .map(|entry| match entry.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => exit_with_error(format!("--set {} is not of the form KEY=VALUE", entry)),
+        })
+        .collect();
+    let config_file = matches.value_of("config").map(PathBuf::from);
+    let config_overrides = ants_plugin::ConfigOverrides::new(config_file.clone(), sets);
+    let plugin = ants_plugin::AntsPlugin {
+        scenario: matches.value_of("scenario").map(PathBuf::from),
+        snapshot: matches.value_of("snapshot").map(PathBuf::from),
+        seed: matches.is_present("seed").then(|| {
+            matches
+                .value_of_t::<i32>("seed")
+                .unwrap_or_else(|e| e.exit())
+        }),
+        config_overrides,
+        dump_config: matches.value_of("dump-config").map(PathBuf::from),
+        stats: matches.value_of("stats").map(PathBuf::from),
+        arena,
+        ..Default::default()
+    };
+
+    if matches.is_present("headless") {
+        let ticks = matches
+            .value_of_t::<usize>("ticks")
+            .unwrap_or_else(|e| e.exit());
+        let save_snapshot = matches.value_of("save-snapshot").map(PathBuf::from);
+        run_headless(ticks, plugin, save_snapshot, config_file.as_deref());
+        return;
+    }
+
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
         // .add_plugin(LogDiagnosticsPlugin::default())
         // .add_plugin(FrameTimeDiagnosticsPlugin::default())
-        // .add_plugin(console_debug_plugin::ConsoleDebugPlugin)
-        .add_plugin(ants_plugin::AntsPlugin)
-        .run()
+        .add_plugin(plugin)
+        .add_plugin(ants_render_plugin::AntsRenderPlugin);
+    #[cfg(feature = "console")]
+    app.add_plugin(ants_sim::console_debug_plugin::ConsoleDebugPlugin);
+    resolve_config_overrides(&mut app, config_file.as_deref());
+    app.run();
+}
+
+fn exit_with_error(e: impl std::fmt::Display) -> ! {
+    eprintln!("error: {}", e);
+    std::process::exit(2);
+}
+
+/// Validates the command line config against the keys registered by every plugin of `app`,
+/// exiting on a bad value before the app runs.
+fn resolve_config_overrides(app: &mut App, config_file: Option<&Path>) {
+    ants_plugin::resolve_config_overrides(&mut app.world).unwrap_or_else(|e| {
+        match (e, config_file) {
+            // only the file can fail to read or parse
+            (e @ (ConfigFileError::Io(_) | ConfigFileError::Toml(_)), Some(path)) => {
+                exit_with_error(format!("failed to load config {}: {}", path.display(), e))
+            }
+            (e, _) => exit_with_error(e),
+        }
+    });
+}
+
+/// Steps the simulation `ticks` times as fast as possible, without a window or renderer, then
+/// optionally checkpoints the result to `save_snapshot`.
+fn run_headless(
+    ticks: usize,
+    plugin: ants_plugin::AntsPlugin,
+    save_snapshot: Option<PathBuf>,
+    config_file: Option<&Path>,
+) {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(ants_plugin::AntsPlugin {
+            tick_mode: ants_plugin::TickMode::EveryUpdate,
+            ..plugin
+        });
+    resolve_config_overrides(&mut app, config_file);
+    let start = Instant::now();
+    for _ in 0..ticks {
+        app.update();
+    }
+    println!("Simulated {} ticks in {:?}", ticks, start.elapsed());
+    if let Some(path) = save_snapshot {
+        if let Err(e) = snapshot::save_snapshot(&mut app.world, &path) {
+            println!("failed to save snapshot {}: {}", path.display(), e);
+        }
+    }
 }
@@ -0,0 +1,434 @@
+use crate::ants_plugin::{SIM_SEED, TIME_STEP};
+use crate::config::{Config, ConfigKey, ConfigSpec};
+use crate::helpers::obstacle_grid::ObstacleGrid;
+use crate::helpers::pheromone_field::PheromoneField;
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin, Seedable};
+
+/// Offset between the noise samples of the two wind components, so they vary independently.
+const WIND_COMPONENT_OFFSET: f64 = 1000.0;
+
+pub const TRAIL_OBSTACLE_BLOCKING: ConfigKey<bool> = ConfigKey {
+    key: "trail.obstacle_blocking",
+    default: true,
+    min: None,
+    max: None,
+    units: "",
+    description: "Whether obstacles keep pheromone out",
+};
+
+pub const TRAIL_EVAPORATION_RATE: ConfigKey<f32> = ConfigKey {
+    key: "trail.evaporation_rate",
+    default: 0.06,
+    min: Some(0.0),
+    max: None,
+    units: "1/s",
+    description: "Rate pheromone evaporates at",
+};
+
+pub const TRAIL_DIFFUSION: ConfigKey<f32> = ConfigKey {
+    key: "trail.diffusion",
+    default: 0.0,
+    min: Some(0.0),
+    max: None,
+    units: "world units^2/s",
+    description: "Diffusion coefficient of pheromone",
+};
+
+pub const WIND_X: ConfigKey<f32> = ConfigKey {
+    key: "wind.x",
+    default: 0.0,
+    min: None,
+    max: None,
+    units: "world units/s",
+    description: "Steady wind blowing pheromone along x",
+};
+
+pub const WIND_Y: ConfigKey<f32> = ConfigKey {
+    key: "wind.y",
+    default: 0.0,
+    min: None,
+    max: None,
+    units: "world units/s",
+    description: "Steady wind blowing pheromone along y",
+};
+
+pub const WIND_TURBULENCE: ConfigKey<f32> = ConfigKey {
+    key: "wind.turbulence",
+    default: 0.0,
+    min: Some(0.0),
+    max: None,
+    units: "world units/s",
+    description: "Largest gust added to the steady wind",
+};
+
+pub const WIND_SCALE: ConfigKey<f32> = ConfigKey {
+    key: "wind.scale",
+    default: 150.0,
+    min: Some(1.0),
+    max: None,
+    units: "world units",
+    description: "Feature size of wind gusts",
+};
+
+/// What every `FieldDynamics` step gets to know about the world besides the field itself.
+pub struct FieldEnvironment {
+    /// Per pheromone cell, whether pheromone is kept out of it. Set for cells whose center lies on
+    /// an obstacle tile while `trail.obstacle_blocking` is on.
+    pub blocked: Vec<bool>,
+    pub seed: i32,
+    /// Seconds one step lasts.
+    pub dt: f32,
+}
+
+impl FieldEnvironment {
+    pub fn new(
+        pheromone_field: &PheromoneField,
+        obstacle_grid: &ObstacleGrid,
+        config: &Config,
+    ) -> FieldEnvironment {
+        let blocking = config.get(&TRAIL_OBSTACLE_BLOCKING);
+        let mut blocked = vec![false; pheromone_field.num_cells()];
+        if blocking {
+            for y in 0..pheromone_field.height {
+                for x in 0..pheromone_field.width {
+                    let center = pheromone_field.cell_center(x, y).extend(0.0);
+                    blocked[(y * pheromone_field.width + x) as usize] = obstacle_grid
+                        .tile_pos_from_world_pos(&center)
+                        .map_or(false, |tile| obstacle_grid.is_obstacle(tile));
+                }
+            }
+        }
+        FieldEnvironment {
+            blocked,
+            seed: config.get(&SIM_SEED),
+            dt: TIME_STEP,
+        }
+    }
+}
+
+/// One physical process acting on the `PheromoneField` every simulation step, tuned by its own
+/// config entries.
+pub trait FieldDynamics: Send + Sync {
+    /// Config entries the model reads, registered at startup.
+    fn config_specs(&self) -> Vec<ConfigSpec>;
+
+    fn step(&mut self, field: &mut PheromoneField, environment: &FieldEnvironment, config: &Config);
+}
+
+/// Exponential decay at `trail.evaporation_rate` per second, dropping what falls below
+/// `PHEROMONE_MIN_STRENGTH`.
+pub struct Evaporation;
+
+impl FieldDynamics for Evaporation {
+    fn config_specs(&self) -> Vec<ConfigSpec> {
+        vec![TRAIL_EVAPORATION_RATE.spec()]
+    }
+
+    fn step(
+        &mut self,
+        field: &mut PheromoneField,
+        environment: &FieldEnvironment,
+        config: &Config,
+    ) {
+        let rate = config.get(&TRAIL_EVAPORATION_RATE);
+        field.evaporate((-rate * environment.dt).exp());
+    }
+}
+
+/// Gaussian diffusion with the diffusion coefficient `trail.diffusion`, in square world units
+/// per second. Pheromone doesn't diffuse through blocked cells.
+pub struct Diffusion;
+
+impl FieldDynamics for Diffusion {
+    fn config_specs(&self) -> Vec<ConfigSpec> {
+        vec![TRAIL_DIFFUSION.spec()]
+    }
+
+    fn step(
+        &mut self,
+        field: &mut PheromoneField,
+        environment: &FieldEnvironment,
+        config: &Config,
+    ) {
+        let diffusion = config.get(&TRAIL_DIFFUSION);
+        if diffusion <= 0.0 {
+            return;
+        }
+        // a random walk spreads by a standard deviation of sqrt(2 D t)
+        let sigma = (2.0 * diffusion * environment.dt).sqrt() / field.cell_size;
+        field.diffuse(sigma, &environment.blocked);
+    }
+}
+
+/// Advection by a wind of `wind.x`, `wind.y` world units per second, plus gusts of up to
+/// `wind.turbulence` varying over the arena with Perlin noise of feature size `wind.scale`.
+/// Pheromone blown past the edge of the field is lost.
+#[derive(Default)]
+pub struct Wind {
+    /// Wind velocity of every cell in cells per step, with the parameters it was computed for.
+    velocity: Vec<Vec2>,
+    parameters: Option<([f32; 4], i32)>,
+}
+
+impl Wind {
+    fn velocity_at(perlin: &Perlin, pos: Vec2, base: Vec2, turbulence: f32, scale: f32) -> Vec2 {
+        if turbulence == 0.0 || scale <= 0.0 {
+            return base;
+        }
+        let [x, y] = [(pos.x / scale) as f64, (pos.y / scale) as f64];
+        let gust = Vec2::new(
+            perlin.get([x, y]) as f32,
+            perlin.get([x + WIND_COMPONENT_OFFSET, y + WIND_COMPONENT_OFFSET]) as f32,
+        );
+        base + gust * turbulence
+    }
+}
+
+impl FieldDynamics for Wind {
+    fn config_specs(&self) -> Vec<ConfigSpec> {
+        [WIND_X, WIND_Y, WIND_TURBULENCE, WIND_SCALE]
+            .iter()
+            .map(ConfigKey::spec)
+            .collect()
+    }
+
+    fn step(
+        &mut self,
+        field: &mut PheromoneField,
+        environment: &FieldEnvironment,
+        config: &Config,
+    ) {
+        let [x, y, turbulence, scale] =
+            [WIND_X, WIND_Y, WIND_TURBULENCE, WIND_SCALE].map(|key| config.get(&key));
+        if x == 0.0 && y == 0.0 && turbulence == 0.0 {
+            return;
+        }
+        let parameters = ([x, y, turbulence, scale], environment.seed);
+        if self.parameters != Some(parameters) || self.velocity.len() != field.num_cells() {
+            let perlin = Perlin::new().set_seed(environment.seed as u32);
+            let base = Vec2::new(x, y);
+            let to_cells = environment.dt / field.cell_size;
+            self.velocity = (0..field.height)
+                .flat_map(|cell_y| (0..field.width).map(move |cell_x| (cell_x, cell_y)))
+                .map(|(cell_x, cell_y)| {
+                    let pos = field.cell_center(cell_x, cell_y);
+                    Wind::velocity_at(&perlin, pos, base, turbulence, scale) * to_cells
+                })
+                .collect();
+            self.parameters = Some(parameters);
+        }
+        field.advect(&self.velocity, &environment.blocked);
+    }
+}
+
+/// The `FieldDynamics` the pheromone field evolves by, stepped in order every simulation step.
+/// Plugins can `add` their own models before startup.
+pub struct PheromoneDynamics {
+    models: Vec<Box<dyn FieldDynamics>>,
+}
+
+impl Default for PheromoneDynamics {
+    fn default() -> PheromoneDynamics {
+        let mut dynamics = PheromoneDynamics { models: Vec::new() };
+        dynamics
+            .add(Wind::default())
+            .add(Diffusion)
+            .add(Evaporation);
+        dynamics
+    }
+}
+
+impl PheromoneDynamics {
+    pub fn add(&mut self, model: impl FieldDynamics + 'static) -> &mut PheromoneDynamics {
+        self.models.push(Box::new(model));
+        self
+    }
+
+    /// Registers the config entries of obstacle blocking and of every model.
+    pub fn register_config(&self, config: &mut Config) {
+        config.register(&TRAIL_OBSTACLE_BLOCKING);
+        for model in self.models.iter() {
+            for spec in model.config_specs() {
+                config.register_spec(spec);
+            }
+        }
+    }
+}
+
+/// Steps every model of the `PheromoneDynamics`, then clears the blocked cells of any pheromone
+/// dropped on them. The `FieldEnvironment` is kept between steps and only rebuilt when the
+/// obstacles or the config change.
+pub fn pheromone_dynamics_system(
+    config: Res<Config>,
+    obstacle_grid: Res<ObstacleGrid>,
+    mut dynamics: ResMut<PheromoneDynamics>,
+    mut pheromone_field: ResMut<PheromoneField>,
+    mut environment: Local<Option<FieldEnvironment>>,
+) {
+    let stale = match environment.as_ref() {
+        Some(environment) => {
+            obstacle_grid.is_changed()
+                || config.is_changed()
+                || environment.blocked.len() != pheromone_field.num_cells()
+        }
+        None => true,
+    };
+    if stale {
+        *environment = Some(FieldEnvironment::new(
+            &pheromone_field,
+            &obstacle_grid,
+            &config,
+        ));
+    }
+    let environment = environment.as_ref().unwrap();
+    for model in dynamics.models.iter_mut() {
+        model.step(&mut pheromone_field, environment, &config);
+    }
+    pheromone_field.clear_blocked(&environment.blocked);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Side of the test field in cells.
+    const SIZE: u32 = 20;
+    const CELL_SIZE: f32 = 5.0;
+    /// Column of the wall in `walled_environment`.
+    const WALL: u32 = 10;
+
+    fn test_config() -> Config {
+        let mut config = Config::default();
+        config.register(&SIM_SEED);
+        PheromoneDynamics::default().register_config(&mut config);
+        config
+    }
+
+    fn test_field() -> PheromoneField {
+        PheromoneField::new(SIZE, SIZE, CELL_SIZE, 1)
+    }
+
+    /// The environment of `field` with a wall of obstacle tiles across column `WALL` of cells.
+    fn walled_environment(field: &PheromoneField, config: &Config) -> FieldEnvironment {
+        let tile_size = 2.0 * CELL_SIZE;
+        let mut obstacle_grid = ObstacleGrid::new(SIZE / 2, SIZE / 2, tile_size);
+        for y in 0..SIZE / 2 {
+            obstacle_grid.set_obstacle(UVec2::new(WALL / 2, y), true);
+        }
+        let environment = FieldEnvironment::new(field, &obstacle_grid, config);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let blocked = environment.blocked[(y * SIZE + x) as usize];
+                assert_eq!(blocked, x / 2 == WALL / 2, "cell ({}, {})", x, y);
+            }
+        }
+        environment
+    }
+
+    fn open_environment(field: &PheromoneField, config: &Config) -> FieldEnvironment {
+        FieldEnvironment::new(field, &ObstacleGrid::new(SIZE / 2, SIZE / 2, 10.0), config)
+    }
+
+    fn deposit(field: &mut PheromoneField, x: u32, y: u32, amount: f32) {
+        let center = field.cell_center(x, y).extend(0.0);
+        field.deposit(0, center, amount);
+    }
+
+    fn total(field: &PheromoneField) -> f32 {
+        field.channel(0).iter().sum()
+    }
+
+    /// Total pheromone in the cells right of the wall.
+    fn past_wall(field: &PheromoneField) -> f32 {
+        field
+            .channel(0)
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index as u32 % SIZE >= WALL)
+            .map(|(_, amount)| amount)
+            .sum()
+    }
+
+    #[test]
+    fn diffusion_conserves_mass() {
+        let mut config = test_config();
+        config.set(&TRAIL_DIFFUSION, 200.0).unwrap();
+        let mut field = test_field();
+        let environment = open_environment(&field, &config);
+        deposit(&mut field, 10, 10, 100.0);
+        // a corner deposit spreads against the border of the field
+        deposit(&mut field, 0, 0, 100.0);
+        for _ in 0..60 {
+            Diffusion.step(&mut field, &environment, &config);
+        }
+        assert!((total(&field) - 200.0).abs() < 1e-2, "{}", total(&field));
+        assert!(field.channel(0)[(10 * SIZE + 10) as usize] < 10.0);
+    }
+
+    #[test]
+    fn diffusion_does_not_flow_into_blocked_cells() {
+        let mut config = test_config();
+        config.set(&TRAIL_DIFFUSION, 200.0).unwrap();
+        let mut field = test_field();
+        let environment = walled_environment(&field, &config);
+        deposit(&mut field, WALL - 1, 10, 100.0);
+        for _ in 0..60 {
+            Diffusion.step(&mut field, &environment, &config);
+        }
+        assert_eq!(past_wall(&field), 0.0);
+        assert!((total(&field) - 100.0).abs() < 1e-2, "{}", total(&field));
+    }
+
+    #[test]
+    fn evaporation_decays_exponentially() {
+        let mut config = test_config();
+        config.set(&TRAIL_EVAPORATION_RATE, 0.5).unwrap();
+        let mut field = test_field();
+        let environment = open_environment(&field, &config);
+        deposit(&mut field, 10, 10, 100.0);
+        for _ in 0..120 {
+            Evaporation.step(&mut field, &environment, &config);
+        }
+        let expected = 100.0 * (-0.5 * 120.0 * TIME_STEP).exp();
+        assert!((total(&field) - expected).abs() < 1e-2, "{}", total(&field));
+    }
+
+    #[test]
+    fn wind_carries_pheromone_downwind() {
+        let mut config = test_config();
+        // half a cell per step
+        config.set(&WIND_X, 0.5 * CELL_SIZE / TIME_STEP).unwrap();
+        let mut field = test_field();
+        let environment = open_environment(&field, &config);
+        deposit(&mut field, 2, 10, 100.0);
+        let mut wind = Wind::default();
+        for _ in 0..10 {
+            wind.step(&mut field, &environment, &config);
+        }
+        let centroid_x = field
+            .channel(0)
+            .iter()
+            .enumerate()
+            .map(|(index, amount)| (index as u32 % SIZE) as f32 * amount)
+            .sum::<f32>()
+            / total(&field);
+        assert!((centroid_x - 7.0).abs() < 1e-3, "{}", centroid_x);
+        assert!((total(&field) - 100.0).abs() < 1e-2, "{}", total(&field));
+    }
+
+    #[test]
+    fn wind_does_not_blow_into_blocked_cells() {
+        let mut config = test_config();
+        config.set(&WIND_X, 0.5 * CELL_SIZE / TIME_STEP).unwrap();
+        let mut field = test_field();
+        let environment = walled_environment(&field, &config);
+        deposit(&mut field, 2, 10, 100.0);
+        let mut wind = Wind::default();
+        for _ in 0..40 {
+            wind.step(&mut field, &environment, &config);
+        }
+        assert_eq!(past_wall(&field), 0.0);
+        assert!((total(&field) - 100.0).abs() < 1e-2, "{}", total(&field));
+    }
+}
@@ -0,0 +1,592 @@
+use crate::ants_plugin::{
+    spawn_ant_with_locomotion, spawn_food_source, spawn_home, vec3_angle, Ant, AntBundle, ColonyId,
+    Home, MapGenerator, NextAntId, MAX_COLONIES,
+};
+use crate::arena::{Arena, ArenaShape};
+use crate::caste::Caste;
+use crate::config::{Config, ConfigError, ConfigValue};
+use crate::food::{FoodId, FoodSource, NextFoodId};
+use crate::helpers::obstacle_grid::ObstacleGrid;
+use crate::helpers::pheromone_field::PheromoneField;
+use crate::locomotion::{DefaultLocomotion, Locomotion};
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+use bevy::transform::hierarchy::despawn_with_children_recursive;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Bumped whenever the scenario format changes incompatibly.
+pub const SCENARIO_VERSION: u32 = 8;
+
+/// A whole world setup, stored as RON. Positions are in world units.
+#[derive(Serialize, Deserialize)]
+pub struct Scenario {
+    pub version: u32,
+    /// Its size must match the running simulation's arena; shape and boundary are replaced.
+    pub arena: Arena,
+    pub obstacle_tile_size: f32,
+    /// Noise parameters the obstacles were generated with, before any hand painting.
+    pub map_generator: MapGenerator,
+    pub obstacles: Vec<[u32; 2]>,
+    pub homes: Vec<HomeSpawn>,
+    pub food: Vec<FoodSpawn>,
+    pub ants: Vec<AntSpawn>,
+    /// Locomotion of ants that do not choose their own, including ants born later.
+    pub locomotion: Locomotion,
+    pub config: BTreeMap<String, ConfigValue>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HomeSpawn {
+    pub position: [f32; 2],
+    pub colony: ColonyId,
+    /// Food the home starts with in its store.
+    pub food_store: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FoodSpawn {
+    pub position: [f32; 2],
+    pub source: FoodSource,
+    /// Keeps the source's random stream when a world is restored. Sources without one are given
+    /// the ids following the highest id in the scenario, in order.
+    #[serde(default)]
+    pub id: Option<FoodId>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AntSpawn {
+    pub position: [f32; 2],
+    pub rotation: f32,
+    pub colony: ColonyId,
+    pub caste: Caste,
+    /// Overrides the scenario's `locomotion` for this ant.
+    pub locomotion: Option<Locomotion>,
+    /// Overrides the food perception radius of the ant's caste.
+    pub food_perception: Option<f32>,
+    /// Overrides the nest perception radius of the ant's caste.
+    pub nest_perception: Option<f32>,
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    Ron(ron::Error),
+    UnsupportedVersion(u32),
+    ArenaSizeMismatch {
+        scenario: [f32; 2],
+        simulation: [f32; 2],
+    },
+    /// A polygon arena with fewer than three vertices.
+    InvalidArena,
+    /// A polygon arena vertex with an infinite or NaN coordinate.
+    NonFiniteArenaVertex([f32; 2]),
+    /// A polygon arena vertex outside the arena's bounding rectangle.
+    ArenaVertexOutOfBounds([f32; 2]),
+    /// A polygon arena whose outline encloses no area.
+    DegenerateArena,
+    /// An obstacle tile outside the obstacle grid covering the arena.
+    ObstacleOutOfGrid([u32; 2]),
+    Config(ConfigError),
+    InvalidColony(ColonyId),
+    DuplicateFoodId(FoodId),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScenarioError::Io(e) => write!(fmt, "{}", e),
+            ScenarioError::Ron(e) => write!(fmt, "{}", e),
+            ScenarioError::UnsupportedVersion(version) => write!(
+                fmt,
+                "unsupported scenario version {} (expected {})",
+                version, SCENARIO_VERSION
+            ),
+            ScenarioError::ArenaSizeMismatch {
+                scenario,
+                simulation,
+            } => write!(
+                fmt,
+                "scenario arena {}x{} does not match the simulation arena {}x{}",
+                scenario[0], scenario[1], simulation[0], simulation[1]
+            ),
+            ScenarioError::InvalidArena => {
+                write!(fmt, "polygon arenas need at least three vertices")
+            }
+            ScenarioError::NonFiniteArenaVertex([x, y]) => {
+                write!(fmt, "arena vertex ({}, {}) is not finite", x, y)
+            }
+            ScenarioError::ArenaVertexOutOfBounds([x, y]) => write!(
+                fmt,
+                "arena vertex ({}, {}) lies outside the arena's bounding rectangle",
+                x, y
+            ),
+            ScenarioError::DegenerateArena => write!(fmt, "polygon arena encloses no area"),
+            ScenarioError::ObstacleOutOfGrid([x, y]) => {
+                write!(
+                    fmt,
+                    "obstacle tile ({}, {}) is outside the obstacle grid",
+                    x, y
+                )
+            }
+            ScenarioError::Config(e) => write!(fmt, "{}", e),
+            ScenarioError::InvalidColony(colony) => write!(
+                fmt,
+                "colony {} is out of range (at most {} colonies)",
+                colony.0, MAX_COLONIES
+            ),
+            ScenarioError::DuplicateFoodId(id) => {
+                write!(fmt, "food source id {} is used more than once", id.0)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for ScenarioError {
+    fn from(e: std::io::Error) -> ScenarioError {
+        ScenarioError::Io(e)
+    }
+}
+
+impl From<ron::Error> for ScenarioError {
+    fn from(e: ron::Error) -> ScenarioError {
+        ScenarioError::Ron(e)
+    }
+}
+
+impl From<ConfigError> for ScenarioError {
+    fn from(e: ConfigError) -> ScenarioError {
+        ScenarioError::Config(e)
+    }
+}
+
+impl Scenario {
+    /// Captures the current obstacles, homes, food sources, ants and config of `world`.
+    pub fn capture(world: &mut World) -> Scenario {
+        let rapier_scale = world.get_resource::<RapierConfiguration>().unwrap().scale;
+        let mut ants = Vec::new();
+        let mut ant_query = world.query::<(
+            &Ant,
+            &Transform,
+            &ColonyId,
+            &Caste,
+            &Locomotion,
+            Option<&RigidBodyPositionComponent>,
+        )>();
+        for (ant, transform, &colony, &caste, &locomotion, rb_pos) in ant_query.iter(world) {
+            let (position, rotation) = match rb_pos {
+                Some(rb_pos) => (
+                    [
+                        rb_pos.position.translation.x * rapier_scale,
+                        rb_pos.position.translation.y * rapier_scale,
+                    ],
+                    rb_pos.position.rotation.angle(),
+                ),
+                None => (
+                    [transform.translation.x, transform.translation.y],
+                    vec3_angle(transform.rotation * Vec3::X),
+                ),
+            };
+            ants.push(AntSpawn {
+                position,
+                rotation,
+                colony,
+                caste,
+                locomotion: Some(locomotion),
+                food_perception: Some(ant.food_perception),
+                nest_perception: Some(ant.nest_perception),
+            });
+        }
+        let mut home_query = world.query::<(&Transform, &ColonyId, &Home)>();
+        let homes = home_query
+            .iter(world)
+            .map(|(transform, &colony, home)| HomeSpawn {
+                position: [transform.translation.x, transform.translation.y],
+                colony,
+                food_store: home.food_store,
+            })
+            .collect();
+        let mut food_query = world.query::<(&Transform, &FoodSource, &FoodId)>();
+        let mut food: Vec<FoodSpawn> = food_query
+            .iter(world)
+            .map(|(transform, source, &id)| FoodSpawn {
+                position: [transform.translation.x, transform.translation.y],
+                source: source.clone(),
+                id: Some(id),
+            })
+            .collect();
+        food.sort_by_key(|food| food.id);
+
+        let obstacle_grid = world.get_resource::<ObstacleGrid>().unwrap();
+        let obstacles = obstacle_grid
+            .tiles()
+            .iter()
+            .enumerate()
+            .filter(|&(_, &obstacle)| obstacle)
+            .map(|(index, _)| {
+                let tile_pos = obstacle_grid.tile_pos_from_index(index);
+                [tile_pos.x, tile_pos.y]
+            })
+            .collect();
+        let config = world
+            .get_resource::<Config>()
+            .unwrap()
+            .entries()
+            .map(|entry| (entry.spec.key.to_string(), entry.value().clone()))
+            .collect();
+
+        Scenario {
+            version: SCENARIO_VERSION,
+            arena: world.get_resource::<Arena>().unwrap().clone(),
+            obstacle_tile_size: obstacle_grid.tile_size,
+            map_generator: world.get_resource::<MapGenerator>().unwrap().clone(),
+            obstacles,
+            homes,
+            food,
+            ants,
+            locomotion: world.get_resource::<DefaultLocomotion>().unwrap().0,
+            config,
+        }
+    }
+
+    /// Writes the scenario to `path` as RON.
+    pub fn save(&self, path: &Path) -> Result<(), ScenarioError> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())?;
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Reads the RON scenario at `path`, without applying it.
+    pub fn load(path: &Path) -> Result<Scenario, ScenarioError> {
+        let text = fs::read_to_string(path)?;
+        Ok(ron::from_str(&text)?)
+    }
+
+    /// Replaces the obstacles, homes, food, ants and config of `world` with this scenario and
+    /// clears all pheromone. Nothing is changed if the scenario fails validation.
+    pub fn apply(&self, world: &mut World) -> Result<(), ScenarioError> {
+        if self.version != SCENARIO_VERSION {
+            return Err(ScenarioError::UnsupportedVersion(self.version));
+        }
+        let obstacle_tile_size = world.get_resource::<ObstacleGrid>().unwrap().tile_size;
+        let arena_size = world.get_resource::<Arena>().unwrap().size;
+        if self.arena.size != arena_size || self.obstacle_tile_size != obstacle_tile_size {
+            return Err(ScenarioError::ArenaSizeMismatch {
+                scenario: self.arena.size,
+                simulation: arena_size,
+            });
+        }
+        validate_arena(&self.arena)?;
+        {
+            let obstacle_grid = world.get_resource::<ObstacleGrid>().unwrap();
+            if let Some(&tile) = self
+                .obstacles
+                .iter()
+                .find(|&&[x, y]| !obstacle_grid.in_grid(UVec2::new(x, y)))
+            {
+                return Err(ScenarioError::ObstacleOutOfGrid(tile));
+            }
+        }
+        if let Some(colony) = self
+            .homes
+            .iter()
+            .map(|home| home.colony)
+            .chain(self.ants.iter().map(|ant| ant.colony))
+            .find(|colony| colony.0 as usize >= MAX_COLONIES)
+        {
+            return Err(ScenarioError::InvalidColony(colony));
+        }
+        let mut food_ids: Vec<FoodId> = self.food.iter().filter_map(|food| food.id).collect();
+        food_ids.sort();
+        if let Some(pair) = food_ids.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(ScenarioError::DuplicateFoodId(pair[0]));
+        }
+        {
+            let config = world.get_resource::<Config>().unwrap();
+            for (key, value) in self.config.iter() {
+                config.entry(key)?.spec.validate(value)?;
+            }
+        }
+
+        let mut config = world.get_resource_mut::<Config>().unwrap();
+        for (key, value) in self.config.iter() {
+            config.set_value(key, value.clone())?;
+        }
+        // Matching the generator to the loaded parameters keeps map_generator_system from
+        // regenerating over the loaded obstacles.
+        *world.get_resource_mut::<MapGenerator>().unwrap() = self.map_generator.clone();
+        if *world.get_resource::<Arena>().unwrap() != self.arena {
+            world.insert_resource(self.arena.clone());
+        }
+        let mut obstacle_grid = world.get_resource_mut::<ObstacleGrid>().unwrap();
+        obstacle_grid.clear();
+        for &[x, y] in self.obstacles.iter() {
+            obstacle_grid.set_obstacle(UVec2::new(x, y), true);
+        }
+        world.get_resource_mut::<PheromoneField>().unwrap().clear();
+
+        let mut despawned = Vec::new();
+        despawned.extend(world.query_filtered::<Entity, With<Ant>>().iter(world));
+        despawned.extend(
+            world
+                .query_filtered::<Entity, With<FoodSource>>()
+                .iter(world),
+        );
+        despawned.extend(world.query_filtered::<Entity, With<Home>>().iter(world));
+        for entity in despawned {
+            despawn_with_children_recursive(world, entity);
+        }
+
+        let rapier_scale = world.get_resource::<RapierConfiguration>().unwrap().scale;
+        let mut next_ant_id = NextAntId::default();
+        let mut next_food_id = NextFoodId(food_ids.last().map_or(0, |id| id.0 + 1));
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, world);
+        let config = world.get_resource::<Config>().unwrap();
+        for home in self.homes.iter() {
+            let [x, y] = home.position;
+            let entity = spawn_home(Vec3::new(x, y, 0.0), home.colony, &mut commands);
+            commands.entity(entity).insert(Home {
+                food_store: home.food_store,
+            });
+        }
+        for food in self.food.iter() {
+            spawn_food_source(
+                Vec2::from(food.position),
+                food.id.unwrap_or_else(|| next_food_id.next()),
+                food.source.clone(),
+                &mut commands,
+            );
+        }
+        for ant in self.ants.iter() {
+            let mut bundle = AntBundle::new(next_ant_id.next(), ant.colony, ant.caste, config);
+            if let Some(food_perception) = ant.food_perception {
+                bundle.ant.food_perception = food_perception;
+            }
+            if let Some(nest_perception) = ant.nest_perception {
+                bundle.ant.nest_perception = nest_perception;
+            }
+            spawn_ant_with_locomotion(
+                ant.locomotion.unwrap_or(self.locomotion),
+                bundle,
+                Vec2::from(ant.position),
+                ant.rotation,
+                rapier_scale,
+                config,
+                &mut commands,
+            );
+        }
+        queue.apply(world);
+        world.insert_resource(next_ant_id);
+        world.insert_resource(next_food_id);
+        world.insert_resource(DefaultLocomotion(self.locomotion));
+        Ok(())
+    }
+}
+
+/// Checks that a polygon arena outlines a proper region of its bounding rectangle.
+fn validate_arena(arena: &Arena) -> Result<(), ScenarioError> {
+    let vertices = match &arena.shape {
+        ArenaShape::Polygon(vertices) => vertices,
+        _ => return Ok(()),
+    };
+    if vertices.len() < 3 {
+        return Err(ScenarioError::InvalidArena);
+    }
+    let half_size = arena.half_size();
+    for &[x, y] in vertices.iter() {
+        if !x.is_finite() || !y.is_finite() {
+            return Err(ScenarioError::NonFiniteArenaVertex([x, y]));
+        }
+        if x.abs() > half_size.x || y.abs() > half_size.y {
+            return Err(ScenarioError::ArenaVertexOutOfBounds([x, y]));
+        }
+    }
+    // shoelace formula, twice the signed area
+    let area: f32 = vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .map(|(a, b)| a[0] * b[1] - b[0] * a[1])
+        .sum();
+    if area == 0.0 {
+        return Err(ScenarioError::DegenerateArena);
+    }
+    Ok(())
+}
+
+/// Writes the current world of `world` to `path` as a RON scenario.
+pub fn save_scenario(world: &mut World, path: &Path) -> Result<(), ScenarioError> {
+    Scenario::capture(world).save(path)
+}
+
+/// Replaces the world of `world` with the RON scenario at `path`.
+pub fn load_scenario(world: &mut World, path: &Path) -> Result<(), ScenarioError> {
+    Scenario::load(path)?.apply(world)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::food::FoodType;
+
+    fn sample() -> Scenario {
+        Scenario {
+            version: SCENARIO_VERSION,
+            arena: Arena {
+                shape: ArenaShape::Polygon(vec![[-450.0, -300.0], [450.0, -300.0], [0.0, 300.0]]),
+                ..Default::default()
+            },
+            obstacle_tile_size: 10.0,
+            map_generator: MapGenerator {
+                octaves: 4,
+                frequency: 0.01,
+                threshold: 0.3,
+                ..Default::default()
+            },
+            obstacles: vec![[0, 0], [3, 7]],
+            homes: vec![HomeSpawn {
+                position: [100.0, -50.5],
+                colony: ColonyId(1),
+                food_store: 12,
+            }],
+            food: vec![
+                FoodSpawn {
+                    position: [-0.1, 300.0],
+                    source: FoodSource {
+                        food_type: FoodType::Fruit,
+                        amount: 7,
+                        capacity: 20,
+                        take: 2,
+                        regrowth: 0.5,
+                        regrowth_progress: 0.25,
+                        respawn: true,
+                    },
+                    id: Some(FoodId(3)),
+                },
+                FoodSpawn {
+                    position: [12.0, 12.0],
+                    source: FoodSource {
+                        food_type: FoodType::Seed,
+                        amount: 1,
+                        capacity: 1,
+                        take: 1,
+                        regrowth: 0.0,
+                        regrowth_progress: 0.0,
+                        respawn: false,
+                    },
+                    id: None,
+                },
+            ],
+            ants: vec![
+                AntSpawn {
+                    position: [1.5, 2.25],
+                    rotation: -1.2,
+                    colony: ColonyId(0),
+                    caste: Caste::Scout,
+                    locomotion: None,
+                    food_perception: Some(42.5),
+                    nest_perception: None,
+                },
+                AntSpawn {
+                    position: [-40.0, 0.1],
+                    rotation: 3.0,
+                    colony: ColonyId(1),
+                    caste: Caste::Soldier,
+                    locomotion: Some(Locomotion::RigidBody),
+                    food_perception: None,
+                    nest_perception: Some(0.0),
+                },
+            ],
+            locomotion: Locomotion::Kinematic,
+            config: [
+                ("ant.speed".to_string(), ConfigValue::Float(0.1)),
+                ("ant.count".to_string(), ConfigValue::Int(3)),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    #[test]
+    fn save_then_load_round_trips() {
+        let path = std::env::temp_dir().join(format!(
+            "ants_scenario_round_trip_{}.ron",
+            std::process::id()
+        ));
+        let scenario = sample();
+        scenario.save(&path).unwrap();
+        let loaded = Scenario::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            ron::to_string(&loaded.unwrap()).unwrap(),
+            ron::to_string(&scenario).unwrap()
+        );
+    }
+
+    #[test]
+    fn load_reports_malformed_files() {
+        let path = std::env::temp_dir().join(format!(
+            "ants_scenario_malformed_{}.ron",
+            std::process::id()
+        ));
+        fs::write(&path, "(version: 1, bounds: [").unwrap();
+        let loaded = Scenario::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(ScenarioError::Ron(_))));
+        assert!(matches!(Scenario::load(&path), Err(ScenarioError::Io(_))));
+    }
+
+    #[test]
+    fn apply_rejects_obstacles_outside_the_grid() {
+        let arena = Arena::default();
+        let mut world = World::new();
+        world.insert_resource(ObstacleGrid::new(
+            (arena.size[0] / 10.0) as u32,
+            (arena.size[1] / 10.0) as u32,
+            10.0,
+        ));
+        world.insert_resource(arena);
+        let mut scenario = sample();
+        scenario.obstacles.push([90, 0]);
+        assert!(matches!(
+            scenario.apply(&mut world),
+            Err(ScenarioError::ObstacleOutOfGrid([90, 0]))
+        ));
+        assert!(world
+            .get_resource::<ObstacleGrid>()
+            .unwrap()
+            .tiles()
+            .iter()
+            .all(|&tile| !tile));
+    }
+
+    #[test]
+    fn validate_arena_rejects_bad_polygons() {
+        let polygon = |vertices: Vec<[f32; 2]>| Arena {
+            shape: ArenaShape::Polygon(vertices),
+            ..Default::default()
+        };
+        assert!(validate_arena(&sample().arena).is_ok());
+        assert!(validate_arena(&polygon(vec![[0.0, 0.0], [10.0, 0.0]])).is_err());
+        for vertex in [[f32::NAN, 0.0], [0.0, f32::INFINITY]] {
+            assert!(matches!(
+                validate_arena(&polygon(vec![[0.0, 0.0], [10.0, 0.0], vertex])),
+                Err(ScenarioError::NonFiniteArenaVertex(_))
+            ));
+        }
+        for vertex in [[450.5, 0.0], [0.0, -301.0]] {
+            assert!(matches!(
+                validate_arena(&polygon(vec![[0.0, 0.0], [10.0, 0.0], vertex])),
+                Err(ScenarioError::ArenaVertexOutOfBounds(_))
+            ));
+        }
+        assert!(matches!(
+            validate_arena(&polygon(vec![[0.0, 0.0], [10.0, 10.0], [20.0, 20.0]])),
+            Err(ScenarioError::DegenerateArena)
+        ));
+    }
+}
@@ -0,0 +1,388 @@
+use crate::ants_plugin::{
+    spawn_ant_with_locomotion, spawn_food_source, vec3_angle, Ant, AntBundle, AntId, ColonyId,
+    Home, MapGenerator, NextAntId, SimulationControl, SimulationTick, MAP_SEED, MAX_COLONIES,
+};
+use crate::arena::Arena;
+use crate::behavior::AntState;
+use crate::caste::Caste;
+use crate::config::Config;
+use crate::console_commands::{parse_arg, parse_position, AddConsoleCommand, CommandFn};
+use crate::food::{FoodSource, FoodType, NextFoodId};
+use crate::helpers::obstacle_grid::ObstacleGrid;
+use crate::helpers::pheromone_field::PheromoneField;
+use crate::helpers::sim_rng::{AntStream, SimRng};
+use crate::locomotion::DefaultLocomotion;
+use crate::stats::ColonyStats;
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::RapierConfiguration;
+use clap::{AppSettings, ArgMatches};
+use rand::Rng;
+
+/// Registers the console commands controlling and inspecting the simulation.
+pub fn add_sim_commands(app: &mut App) {
+    app.add_console_command(CommandFn::new(
+        clap::App::new("pause").about("pause the simulation"),
+        pause,
+    ))
+    .add_console_command(CommandFn::new(
+        clap::App::new("resume").about("resume the paused simulation"),
+        resume,
+    ))
+    .add_console_command(CommandFn::new(
+        clap::App::new("step")
+            .about("pause the simulation and run it for a number of ticks")
+            .arg(clap::arg!([ticks] "ticks to run, 1 by default")),
+        step,
+    ))
+    .add_console_command(CommandFn::new(
+        clap::App::new("speed")
+            .about("set simulated seconds per wall-clock second")
+            .arg(clap::arg!(<multiplier> "speed multiplier, 1 for real time")),
+        speed,
+    ))
+    .add_console_command(CommandFn::new(
+        clap::App::new("spawn")
+            .about("spawn ants or food")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("ants")
+                    .about("spawn ants of castes drawn by their ratios")
+                    .arg(clap::arg!(<count> "number of ants"))
+                    .arg(clap::arg!(<at> "the word 'at'").possible_values(["at"]))
+                    .arg(
+                        clap::arg!(<position> "position in world units, as X,Y")
+                            .allow_hyphen_values(true),
+                    )
+                    .arg(clap::arg!(--colony [COLONY] "colony of the ants, 0 by default")),
+            )
+            .subcommand(
+                clap::App::new("food")
+                    .about("spawn a food source")
+                    .arg(
+                        clap::arg!(<position> "position in world units, as X,Y")
+                            .allow_hyphen_values(true),
+                    )
+                    .arg(clap::arg!([count] "units of food, food.amount by default"))
+                    .arg(
+                        clap::arg!(--"type" [TYPE] "seed, fruit or insect; seed by default")
+                            .possible_values(FoodType::ALL.map(|food_type| food_type.name())),
+                    ),
+            ),
+        spawn,
+    ))
+    .add_console_command(CommandFn::new(
+        clap::App::new("clear")
+            .about("clear simulation state")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(clap::App::new("trails").about("remove all pheromone")),
+        clear,
+    ))
+    .add_console_command(CommandFn::new(
+        clap::App::new("regen")
+            .about("regenerate world state")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("map")
+                    .about("regenerate the obstacles, replacing any painted ones")
+                    .arg(
+                        clap::arg!([seed] "new map.seed, the current one by default")
+                            .allow_hyphen_values(true),
+                    ),
+            ),
+        regen,
+    ))
+    .add_console_command(CommandFn::new(
+        clap::App::new("stats").about("show the latest colony stats sample"),
+        |_, world| Ok(stats(world)),
+    ))
+    .add_console_command(CommandFn::new(
+        clap::App::new("inspect")
+            .about("show the simulation components of an entity")
+            .arg(clap::arg!(<entity> "entity index, as shown by bevy, e.g. 12 or 12v0")),
+        |matches, world| inspect(world, matches.value_of("entity").unwrap()),
+    ));
+}
+
+fn pause(_: &ArgMatches, world: &mut World) -> Result<String, String> {
+    world
+        .get_resource_mut::<SimulationControl>()
+        .unwrap()
+        .paused = true;
+    Ok(format!("paused at tick {}", current_tick(world)))
+}
+
+fn resume(_: &ArgMatches, world: &mut World) -> Result<String, String> {
+    world
+        .get_resource_mut::<SimulationControl>()
+        .unwrap()
+        .paused = false;
+    Ok(format!("resumed at tick {}", current_tick(world)))
+}
+
+fn step(matches: &ArgMatches, world: &mut World) -> Result<String, String> {
+    let ticks = parse_arg(matches, "ticks")?.unwrap_or(1);
+    let tick = current_tick(world);
+    let mut control = world.get_resource_mut::<SimulationControl>().unwrap();
+    control.paused = true;
+    control.pending_steps = control.pending_steps.saturating_add(ticks);
+    Ok(format!("stepping {} ticks from tick {}", ticks, tick))
+}
+
+fn speed(matches: &ArgMatches, world: &mut World) -> Result<String, String> {
+    let multiplier: f32 = parse_arg(matches, "multiplier")?.unwrap();
+    if !(multiplier > 0.0 && multiplier.is_finite()) {
+        return Err(format!("speed must be positive, not {}", multiplier));
+    }
+    world.get_resource_mut::<SimulationControl>().unwrap().speed = multiplier;
+    Ok(format!("running at {}x speed", multiplier))
+}
+
+fn spawn(matches: &ArgMatches, world: &mut World) -> Result<String, String> {
+    match matches.subcommand() {
+        Some(("ants", s_matches)) => {
+            let count = parse_arg(s_matches, "count")?.unwrap();
+            let position = parse_position(s_matches.value_of("position").unwrap())?;
+            let colony = ColonyId(parse_arg(s_matches, "colony")?.unwrap_or(0));
+            spawn_ants(world, count, position, colony)
+        }
+        Some(("food", s_matches)) => {
+            let position = parse_position(s_matches.value_of("position").unwrap())?;
+            let amount = parse_arg(s_matches, "count")?;
+            let food_type = FoodType::ALL
+                .into_iter()
+                .find(|food_type| Some(food_type.name()) == s_matches.value_of("type"))
+                .unwrap_or(FoodType::Seed);
+            spawn_food(world, position, amount, food_type)
+        }
+        _ => unreachable!("clap requires a spawn subcommand"),
+    }
+}
+
+fn clear(_: &ArgMatches, world: &mut World) -> Result<String, String> {
+    world.get_resource_mut::<PheromoneField>().unwrap().clear();
+    Ok("cleared all trails".to_string())
+}
+
+fn regen(matches: &ArgMatches, world: &mut World) -> Result<String, String> {
+    let seed = match matches.subcommand() {
+        Some(("map", s_matches)) => parse_arg(s_matches, "seed")?,
+        _ => unreachable!("clap requires a regen subcommand"),
+    };
+    regenerate_map(world, seed)
+}
+
+fn current_tick(world: &World) -> usize {
+    world.get_resource::<SimulationTick>().unwrap().0
+}
+
+fn spawn_ants(
+    world: &mut World,
+    count: usize,
+    position: Vec2,
+    colony: ColonyId,
+) -> Result<String, String> {
+    if colony.0 as usize >= MAX_COLONIES {
+        return Err(format!(
+            "colony {} is out of range (at most {} colonies)",
+            colony.0, MAX_COLONIES
+        ));
+    }
+    if !world.get_resource::<Arena>().unwrap().contains(position) {
+        return Err(format!(
+            "{},{} is outside the arena",
+            position.x, position.y
+        ));
+    }
+    let ids: Vec<AntId> = {
+        let mut next_ant_id = world.get_resource_mut::<NextAntId>().unwrap();
+        (0..count).map(|_| next_ant_id.next()).collect()
+    };
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, world);
+    let config = world.get_resource::<Config>().unwrap();
+    let sim_rng = world.get_resource::<SimRng>().unwrap();
+    let locomotion = world.get_resource::<DefaultLocomotion>().unwrap().0;
+    let rapier_scale = world.get_resource::<RapierConfiguration>().unwrap().scale;
+    let tick = current_tick(world);
+    for id in ids {
+        // drawn like a birth, so the same ant id always gets the same caste and heading
+        let mut rng = sim_rng.ant_rng(id.0, AntStream::Birth, tick);
+        let rotation = rng.gen::<f32>() * 2.0 * std::f32::consts::PI;
+        let caste = Caste::choose(config, rng.gen::<f32>());
+        spawn_ant_with_locomotion(
+            locomotion,
+            AntBundle::new(id, colony, caste, config),
+            position,
+            rotation,
+            rapier_scale,
+            config,
+            &mut commands,
+        );
+    }
+    queue.apply(world);
+    Ok(format!(
+        "spawned {} ants of colony {} at {},{}",
+        count, colony.0, position.x, position.y
+    ))
+}
+
+fn spawn_food(
+    world: &mut World,
+    position: Vec2,
+    amount: Option<usize>,
+    food_type: FoodType,
+) -> Result<String, String> {
+    if amount == Some(0) {
+        return Err("a food source needs at least 1 unit of food".to_string());
+    }
+    if !world.get_resource::<Arena>().unwrap().contains(position) {
+        return Err(format!(
+            "{},{} is outside the arena",
+            position.x, position.y
+        ));
+    }
+    let mut source = FoodSource::new(food_type, world.get_resource::<Config>().unwrap());
+    if let Some(amount) = amount {
+        source.amount = amount;
+        source.capacity = amount;
+    }
+    let amount = source.amount;
+    let id = world.get_resource_mut::<NextFoodId>().unwrap().next();
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, world);
+    spawn_food_source(position, id, source, &mut commands);
+    queue.apply(world);
+    Ok(format!(
+        "spawned {} units of {} at {},{}",
+        amount,
+        food_type.name(),
+        position.x,
+        position.y
+    ))
+}
+
+/// Regenerates the obstacles from the `map.*` config, after setting `map.seed` to `seed` if
+/// given.
+fn regenerate_map(world: &mut World, seed: Option<i32>) -> Result<String, String> {
+    let map_generator = {
+        let mut config = world.get_resource_mut::<Config>().unwrap();
+        if let Some(seed) = seed {
+            config.set(&MAP_SEED, seed).map_err(|e| e.to_string())?;
+        }
+        MapGenerator::from_config(&config)
+    };
+    let arena = world.get_resource::<Arena>().unwrap().clone();
+    map_generator.generate(
+        &mut world.get_resource_mut::<ObstacleGrid>().unwrap(),
+        &arena,
+    );
+    let seed = map_generator.seed;
+    // matching the generator keeps map_generator_system from regenerating again
+    *world.get_resource_mut::<MapGenerator>().unwrap() = map_generator;
+    Ok(format!("regenerated obstacles with map.seed {}", seed))
+}
+
+/// The latest `ColonyStats` sample of every colony.
+fn stats(world: &World) -> String {
+    let stats = world.get_resource::<ColonyStats>().unwrap();
+    let last_tick = match stats.samples.back() {
+        Some(sample) => sample.tick,
+        None => return format!("no stats sampled yet at tick {}", current_tick(world)),
+    };
+    let mut lines = vec![format!(
+        "tick {}, sampled at tick {}",
+        current_tick(world),
+        last_tick
+    )];
+    for sample in stats
+        .samples
+        .iter()
+        .rev()
+        .take_while(|sample| sample.tick == last_tick)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+    {
+        let alive = sample.ants_by_state.iter().sum::<usize>()
+            - sample.ants_by_state[AntState::Dead as usize];
+        lines.push(format!(
+            "colony {}: {} ants ({} carrying food), {} food delivered, {} stored, {} left in \
+             sources, {} births, {} deaths, {} trips",
+            sample.colony.0,
+            alive,
+            sample.ants_carrying,
+            sample.food_delivered,
+            sample.food_stored,
+            sample.food_remaining,
+            sample.births,
+            sample.deaths,
+            sample.trips_completed
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Every simulation component of the entity with index `text`.
+fn inspect(world: &mut World, text: &str) -> Result<String, String> {
+    let index: u32 = text
+        .split('v')
+        .next()
+        .and_then(|index| index.parse().ok())
+        .ok_or_else(|| {
+            format!(
+                "invalid entity '{}', expected an index like 12 or 12v0",
+                text
+            )
+        })?;
+    let entity = world
+        .query::<Entity>()
+        .iter(world)
+        .find(|entity| entity.id() == index)
+        .ok_or_else(|| format!("no entity with index {}", index))?;
+    let entity_ref = world.entity(entity);
+    let mut lines = vec![format!("entity {:?}", entity)];
+    if let Some(transform) = entity_ref.get::<Transform>() {
+        lines.push(format!(
+            "position {},{} heading {:.2} rad",
+            transform.translation.x,
+            transform.translation.y,
+            vec3_angle(transform.rotation * Vec3::X)
+        ));
+    }
+    if let Some(id) = entity_ref.get::<AntId>() {
+        lines.push(format!("ant id {}", id.0));
+    }
+    if let Some(colony) = entity_ref.get::<ColonyId>() {
+        lines.push(format!("colony {}", colony.0));
+    }
+    if let Some(caste) = entity_ref.get::<Caste>() {
+        lines.push(format!("caste {}", caste.name()));
+    }
+    if let Some(state) = entity_ref.get::<AntState>() {
+        lines.push(format!("state {}", state.name()));
+    }
+    if let Some(ant) = entity_ref.get::<Ant>() {
+        lines.push(format!(
+            "energy {:.3}, carrying {} food, food perception {}, nest perception {}",
+            ant.energy, ant.carried_food, ant.food_perception, ant.nest_perception
+        ));
+    }
+    if let Some(home) = entity_ref.get::<Home>() {
+        lines.push(format!("home storing {} food", home.food_store));
+    }
+    if let Some(source) = entity_ref.get::<FoodSource>() {
+        lines.push(format!(
+            "{} source with {}/{} food, regrowing {}/s{}",
+            source.food_type.name(),
+            source.amount,
+            source.capacity,
+            source.regrowth,
+            if source.respawn { ", respawning" } else { "" }
+        ));
+    }
+    if lines.len() == 1 {
+        lines.push("no simulation components".to_string());
+    }
+    Ok(lines.join("\n"))
+}
@@ -0,0 +1,355 @@
+use crate::ants_plugin::{
+    spawn_ant, spawn_rigid_body_ant, Ant, AntBorn, AntBundle, AntDied, AntId, ColonyId,
+    FoodDelivered, NextAntId, SimulationTick, MAX_COLONIES,
+};
+use crate::behavior::{AntState, AntStateChanged, StateTicks};
+use crate::caste::Caste;
+use crate::config::Config;
+use crate::food::NextFoodId;
+use crate::helpers::pheromone_field::PheromoneField;
+use crate::helpers::sim_rng::SimRng;
+use crate::scenario::{Scenario, ScenarioError};
+use crate::stats::{ColonyStats, ColonyTotals, Trip};
+use bevy::app::Events;
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use nalgebra::{Isometry2, Translation2, UnitComplex};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Bumped whenever the snapshot format changes incompatibly.
+pub const SNAPSHOT_VERSION: u32 = 11;
+
+/// A running world, stored as RON: the static scenario plus every piece of state the simulation
+/// systems carry from one tick to the next. Restoring a snapshot and stepping it gives the same
+/// ticks as the world it was captured from.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// Obstacles, homes, food sources and config. Its `ants` are always empty; see `ants`.
+    pub scenario: Scenario,
+    pub tick: usize,
+    pub next_ant_id: u64,
+    pub next_food_id: u64,
+    pub rng: SimRng,
+    /// In `AntId` order.
+    pub ants: Vec<AntSnapshot>,
+    /// Cell strengths of every `PheromoneField` channel.
+    pub pheromones: Vec<Vec<f32>>,
+    /// Running totals of `ColonyStats`. Samples already taken are not part of the snapshot.
+    pub colony_stats: BTreeMap<ColonyId, ColonyTotals>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AntSnapshot {
+    pub id: AntId,
+    pub colony: ColonyId,
+    pub caste: Caste,
+    pub carrying_food: bool,
+    pub state: AntState,
+    /// Simulation steps spent in `state`.
+    pub state_ticks: usize,
+    pub energy: f32,
+    pub food_perception: f32,
+    pub nest_perception: f32,
+    /// Food store units the food the ant carries is worth.
+    pub carried_food: usize,
+    pub translation: [f32; 3],
+    /// Quaternion as `[x, y, z, w]`.
+    pub rotation: [f32; 4],
+    /// Present for ants driven by a rapier rigid body.
+    pub rigid_body: Option<RigidBodySnapshot>,
+    /// Absent for ants born this tick, whose trip starts next tick.
+    pub trip: Option<TripSnapshot>,
+}
+
+/// The ant's current `Trip`.
+#[derive(Serialize, Deserialize)]
+pub struct TripSnapshot {
+    pub started: usize,
+    pub distance: f32,
+    pub last_position: [f32; 2],
+}
+
+/// Rapier body state in physics units. Isometries are stored as `[x, y, cos, sin]` so they
+/// round trip without renormalizing the rotation.
+#[derive(Serialize, Deserialize)]
+pub struct RigidBodySnapshot {
+    pub position: [f32; 4],
+    pub next_position: [f32; 4],
+    pub linvel: [f32; 2],
+    pub angvel: f32,
+    pub force: [f32; 2],
+    pub torque: f32,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Ron(ron::Error),
+    UnsupportedVersion(u32),
+    Scenario(ScenarioError),
+    PheromoneFieldMismatch,
+    /// An ant of a colony at or past `MAX_COLONIES`.
+    InvalidColony(AntId, ColonyId),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(fmt, "{}", e),
+            SnapshotError::Ron(e) => write!(fmt, "{}", e),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                fmt,
+                "unsupported snapshot version {} (expected {})",
+                version, SNAPSHOT_VERSION
+            ),
+            SnapshotError::Scenario(e) => write!(fmt, "{}", e),
+            SnapshotError::PheromoneFieldMismatch => write!(
+                fmt,
+                "snapshot pheromones do not match the simulation's pheromone field"
+            ),
+            SnapshotError::InvalidColony(ant, colony) => write!(
+                fmt,
+                "ant {} is of colony {}, which is out of range (at most {} colonies)",
+                ant.0, colony.0, MAX_COLONIES
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> SnapshotError {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<ron::Error> for SnapshotError {
+    fn from(e: ron::Error) -> SnapshotError {
+        SnapshotError::Ron(e)
+    }
+}
+
+impl From<ScenarioError> for SnapshotError {
+    fn from(e: ScenarioError) -> SnapshotError {
+        SnapshotError::Scenario(e)
+    }
+}
+
+fn isometry_to_array(isometry: &Isometry2<f32>) -> [f32; 4] {
+    [
+        isometry.translation.x,
+        isometry.translation.y,
+        isometry.rotation.cos_angle(),
+        isometry.rotation.sin_angle(),
+    ]
+}
+
+fn isometry_from_array([x, y, cos, sin]: [f32; 4]) -> Isometry2<f32> {
+    Isometry2::from_parts(
+        Translation2::new(x, y),
+        UnitComplex::from_cos_sin_unchecked(cos, sin),
+    )
+}
+
+impl Snapshot {
+    /// Captures the complete simulation state of `world`.
+    pub fn capture(world: &mut World) -> Snapshot {
+        let mut scenario = Scenario::capture(world);
+        scenario.ants.clear();
+
+        let mut ants = Vec::new();
+        let mut ant_query = world.query::<(
+            &AntId,
+            &ColonyId,
+            &Caste,
+            &Ant,
+            &AntState,
+            &StateTicks,
+            &Transform,
+            Option<&Trip>,
+            Option<(
+                &RigidBodyPositionComponent,
+                &RigidBodyVelocityComponent,
+                &RigidBodyForcesComponent,
+            )>,
+        )>();
+        for (&id, &colony, &caste, ant, &state, state_ticks, transform, trip, rigid_body) in
+            ant_query.iter(world)
+        {
+            let rigid_body = rigid_body.map(|(rb_pos, rb_vel, rb_forces)| RigidBodySnapshot {
+                position: isometry_to_array(&rb_pos.position),
+                next_position: isometry_to_array(&rb_pos.next_position),
+                linvel: [rb_vel.linvel.x, rb_vel.linvel.y],
+                angvel: rb_vel.angvel,
+                force: [rb_forces.force.x, rb_forces.force.y],
+                torque: rb_forces.torque,
+            });
+            ants.push(AntSnapshot {
+                id,
+                colony,
+                caste,
+                carrying_food: ant.carrying_food,
+                state,
+                state_ticks: state_ticks.0,
+                energy: ant.energy,
+                food_perception: ant.food_perception,
+                nest_perception: ant.nest_perception,
+                carried_food: ant.carried_food,
+                translation: transform.translation.to_array(),
+                rotation: transform.rotation.to_array(),
+                rigid_body,
+                trip: trip.map(|trip| TripSnapshot {
+                    started: trip.started,
+                    distance: trip.distance,
+                    last_position: trip.last_position.to_array(),
+                }),
+            });
+        }
+        // restoring spawns ants in this order
+        ants.sort_by_key(|ant| ant.id);
+
+        let pheromone_field = world.get_resource::<PheromoneField>().unwrap();
+        let pheromones = (0..pheromone_field.num_channels())
+            .map(|channel| pheromone_field.channel(channel).to_vec())
+            .collect();
+
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            scenario,
+            tick: world.get_resource::<SimulationTick>().unwrap().0,
+            next_ant_id: world.get_resource::<NextAntId>().unwrap().0,
+            next_food_id: world.get_resource::<NextFoodId>().unwrap().0,
+            rng: world.get_resource::<SimRng>().unwrap().clone(),
+            ants,
+            pheromones,
+            colony_stats: world
+                .get_resource::<ColonyStats>()
+                .unwrap()
+                .colonies
+                .clone(),
+        }
+    }
+
+    /// Replaces the simulation state of `world` with this snapshot. Nothing is changed if the
+    /// snapshot fails validation.
+    pub fn restore(&self, world: &mut World) -> Result<(), SnapshotError> {
+        if self.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(self.version));
+        }
+        {
+            let pheromone_field = world.get_resource::<PheromoneField>().unwrap();
+            let num_cells = (pheromone_field.width * pheromone_field.height) as usize;
+            if self.pheromones.len() != pheromone_field.num_channels()
+                || self.pheromones.iter().any(|cells| cells.len() != num_cells)
+            {
+                return Err(SnapshotError::PheromoneFieldMismatch);
+            }
+        }
+        // castes are checked when the snapshot is parsed, as `Caste` has no invalid values
+        if let Some(ant) = self
+            .ants
+            .iter()
+            .find(|ant| ant.colony.0 as usize >= MAX_COLONIES)
+        {
+            return Err(SnapshotError::InvalidColony(ant.id, ant.colony));
+        }
+        self.scenario.apply(world)?;
+
+        let mut pheromone_field = world.get_resource_mut::<PheromoneField>().unwrap();
+        for (channel, cells) in self.pheromones.iter().enumerate() {
+            pheromone_field.channel_mut(channel).copy_from_slice(cells);
+        }
+        world.insert_resource(SimulationTick(self.tick));
+        world.insert_resource(NextAntId(self.next_ant_id));
+        world.insert_resource(NextFoodId(self.next_food_id));
+        world.insert_resource(self.rng.clone());
+        world.get_resource_mut::<ColonyStats>().unwrap().colonies = self.colony_stats.clone();
+        // events about the replaced world must not reach the stats of the restored one
+        world
+            .get_resource_mut::<Events<FoodDelivered>>()
+            .unwrap()
+            .clear();
+        world.get_resource_mut::<Events<AntBorn>>().unwrap().clear();
+        world.get_resource_mut::<Events<AntDied>>().unwrap().clear();
+        world
+            .get_resource_mut::<Events<AntStateChanged>>()
+            .unwrap()
+            .clear();
+
+        let mut spawned = Vec::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, world);
+        let config = world.get_resource::<Config>().unwrap();
+        for ant in self.ants.iter() {
+            let [x, y, z] = ant.translation;
+            let bundle = AntBundle {
+                id: ant.id,
+                colony: ant.colony,
+                caste: ant.caste,
+                ant: Ant {
+                    carrying_food: ant.carrying_food,
+                    carried_food: ant.carried_food,
+                    energy: ant.energy,
+                    food_perception: ant.food_perception,
+                    nest_perception: ant.nest_perception,
+                },
+            };
+            let entity = match &ant.rigid_body {
+                Some(rigid_body) => {
+                    let [x, y, ..] = rigid_body.position;
+                    spawn_rigid_body_ant(bundle, Vec2::new(x, y), 0.0, config, &mut commands)
+                }
+                None => spawn_ant(bundle, Vec3::new(x, y, z), 0.0, &mut commands),
+            };
+            spawned.push(entity);
+        }
+        queue.apply(world);
+
+        for (ant, &entity) in self.ants.iter().zip(spawned.iter()) {
+            *world.get_mut::<AntState>(entity).unwrap() = ant.state;
+            world.get_mut::<StateTicks>(entity).unwrap().0 = ant.state_ticks;
+            let mut transform = world.get_mut::<Transform>(entity).unwrap();
+            transform.translation = Vec3::from(ant.translation);
+            transform.rotation = Quat::from_array(ant.rotation);
+            if let Some(trip) = &ant.trip {
+                world.entity_mut(entity).insert(Trip {
+                    started: trip.started,
+                    distance: trip.distance,
+                    last_position: Vec2::from(trip.last_position),
+                });
+            }
+            if let Some(rigid_body) = &ant.rigid_body {
+                let mut rb_pos = world.get_mut::<RigidBodyPositionComponent>(entity).unwrap();
+                rb_pos.position = isometry_from_array(rigid_body.position);
+                rb_pos.next_position = isometry_from_array(rigid_body.next_position);
+                let mut rb_vel = world.get_mut::<RigidBodyVelocityComponent>(entity).unwrap();
+                rb_vel.linvel = Vector::new(rigid_body.linvel[0], rigid_body.linvel[1]);
+                rb_vel.angvel = rigid_body.angvel;
+                let mut rb_forces = world.get_mut::<RigidBodyForcesComponent>(entity).unwrap();
+                rb_forces.force = Vector::new(rigid_body.force[0], rigid_body.force[1]);
+                rb_forces.torque = rigid_body.torque;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the complete simulation state of `world` to `path` as a RON snapshot.
+pub fn save_snapshot(world: &mut World, path: &Path) -> Result<(), SnapshotError> {
+    let snapshot = Snapshot::capture(world);
+    let text = ron::ser::to_string_pretty(&snapshot, ron::ser::PrettyConfig::new())?;
+    fs::write(path, text)?;
+    Ok(())
+}
+
+/// Restores `world` from the RON snapshot at `path`.
+pub fn load_snapshot(world: &mut World, path: &Path) -> Result<(), SnapshotError> {
+    let text = fs::read_to_string(path)?;
+    let snapshot: Snapshot = ron::from_str(&text)?;
+    snapshot.restore(world)
+}
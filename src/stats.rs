@@ -0,0 +1,384 @@
+use crate::ants_plugin::{
+    Ant, AntBorn, AntDied, ColonyId, FoodDelivered, Home, SimulationTick, TrailType,
+};
+use crate::behavior::AntState;
+use crate::caste::Caste;
+use crate::config::{Config, ConfigKey};
+use crate::food::FoodSource;
+use crate::helpers::pheromone_field::PheromoneField;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub const STATS_SAMPLE_PERIOD: ConfigKey<usize> = ConfigKey {
+    key: "stats.sample_period",
+    default: 60,
+    min: Some(1),
+    max: None,
+    units: "ticks",
+    description: "Ticks between colony stats samples",
+};
+
+/// Most samples `ColonyStats` keeps in memory. Older ones are dropped, whether or not they were
+/// written to the `StatsFile`.
+pub const MAX_SAMPLES: usize = 4096;
+
+pub fn register_config(config: &mut Config) {
+    config.register(&STATS_SAMPLE_PERIOD);
+}
+
+/// Time series of per-colony measurements, sampled every `stats.sample_period` ticks.
+#[derive(Default)]
+pub struct ColonyStats {
+    /// Running totals the samples are taken from, part of the state a snapshot restores.
+    pub colonies: BTreeMap<ColonyId, ColonyTotals>,
+    /// The latest `MAX_SAMPLES` samples, oldest first: one per colony per sample tick, in colony
+    /// order within a tick.
+    pub samples: VecDeque<StatsSample>,
+    /// Number of samples at the back of `samples` not yet written to the `StatsFile`.
+    unexported: usize,
+}
+
+/// Running totals of one colony. The `window_*` totals cover the trips completed since the
+/// previous sample.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct ColonyTotals {
+    pub food_delivered: usize,
+    pub food_delivered_by_caste: [usize; Caste::ALL.len()],
+    pub births: usize,
+    pub births_by_caste: [usize; Caste::ALL.len()],
+    pub deaths: usize,
+    pub deaths_by_caste: [usize; Caste::ALL.len()],
+    pub trips_completed: usize,
+    pub window_trips: usize,
+    pub window_trip_ticks: usize,
+    pub window_trip_distance: f32,
+}
+
+#[derive(Serialize)]
+pub struct StatsSample {
+    pub tick: usize,
+    pub colony: ColonyId,
+    /// Food store units delivered so far to the colony's homes.
+    pub food_delivered: usize,
+    /// `food_delivered` by the caste of the delivering ant, in `Caste::ALL` order.
+    pub food_delivered_by_caste: Vec<usize>,
+    /// Food held in the colony's homes' stores.
+    pub food_stored: usize,
+    /// Food left in the food sources, not carried by an ant. Shared by all colonies.
+    pub food_remaining: usize,
+    pub ants_carrying: usize,
+    pub ants_searching: usize,
+    /// Ants in each `AntState`, in `AntState::ALL` order.
+    pub ants_by_state: Vec<usize>,
+    /// Ants of each `Caste`, in `Caste::ALL` order.
+    pub ants_by_caste: Vec<usize>,
+    /// Ants raised by the colony's homes so far.
+    pub births: usize,
+    /// `births` of each caste, in `Caste::ALL` order.
+    pub births_by_caste: Vec<usize>,
+    /// Ants of the colony that died so far, whether they starved or were absorbed by the arena
+    /// boundary.
+    pub deaths: usize,
+    /// `deaths` of each caste, in `Caste::ALL` order.
+    pub deaths_by_caste: Vec<usize>,
+    /// Pheromone cells holding any of the colony's trails of each type, in `TrailType::ALL`
+    /// order.
+    pub active_trails: Vec<usize>,
+    pub trips_completed: usize,
+    /// Mean duration in ticks of the trips completed since the previous sample.
+    pub average_trip_ticks: Option<f32>,
+    /// Mean distance walked in world units on the trips completed since the previous sample.
+    pub average_trip_distance: Option<f32>,
+}
+
+/// An ant's current round trip, which ends when it delivers food home.
+#[derive(Component)]
+pub struct Trip {
+    /// Tick the trip started at.
+    pub started: usize,
+    /// World units walked so far.
+    pub distance: f32,
+    pub last_position: Vec2,
+}
+
+impl Trip {
+    fn new(tick: usize, position: Vec2) -> Trip {
+        Trip {
+            started: tick,
+            distance: 0.0,
+            last_position: position,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StatsFormat {
+    Csv,
+    JsonLines,
+}
+
+impl StatsFormat {
+    /// CSV for `.csv` files, JSON lines for anything else.
+    pub fn from_path(path: &Path) -> StatsFormat {
+        match path.extension() {
+            Some(extension) if extension == "csv" => StatsFormat::Csv,
+            _ => StatsFormat::JsonLines,
+        }
+    }
+}
+
+/// File the stats samples are streamed to as they are taken.
+pub struct StatsFile(pub Option<PathBuf>);
+
+const CSV_HEADER: &str = "tick,colony,food_delivered,food_delivered_by_caste,food_stored,\
+food_remaining,ants_carrying,ants_searching,ants_by_state,ants_by_caste,births,births_by_caste,\
+deaths,deaths_by_caste,active_trails,trips_completed,average_trip_ticks,average_trip_distance";
+
+fn csv_list(values: &[usize]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn csv_optional(value: Option<f32>) -> String {
+    value.map_or(String::new(), |value| value.to_string())
+}
+
+impl StatsSample {
+    /// Formats the sample as a CSV row. Per-trail, per-state and per-caste counts are joined with
+    /// `;`.
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.tick,
+            self.colony.0,
+            self.food_delivered,
+            csv_list(&self.food_delivered_by_caste),
+            self.food_stored,
+            self.food_remaining,
+            self.ants_carrying,
+            self.ants_searching,
+            csv_list(&self.ants_by_state),
+            csv_list(&self.ants_by_caste),
+            self.births,
+            csv_list(&self.births_by_caste),
+            self.deaths,
+            csv_list(&self.deaths_by_caste),
+            csv_list(&self.active_trails),
+            self.trips_completed,
+            csv_optional(self.average_trip_ticks),
+            csv_optional(self.average_trip_distance),
+        )
+    }
+}
+
+/// Follows every ant's trip and records the ones that end in a delivery.
+pub fn trip_system(
+    mut commands: Commands,
+    tick: Res<SimulationTick>,
+    mut stats: ResMut<ColonyStats>,
+    mut deliveries: EventReader<FoodDelivered>,
+    mut ant_query: Query<(Entity, &Transform, Option<&mut Trip>), With<Ant>>,
+) {
+    for (entity, transform, trip) in ant_query.iter_mut() {
+        let position = transform.translation.truncate();
+        match trip {
+            Some(mut trip) => {
+                trip.distance += (position - trip.last_position).length();
+                trip.last_position = position;
+            }
+            None => {
+                commands.entity(entity).insert(Trip::new(tick.0, position));
+            }
+        }
+    }
+    for delivery in deliveries.iter() {
+        let totals = stats.colonies.entry(delivery.colony).or_default();
+        totals.food_delivered += delivery.amount;
+        totals.food_delivered_by_caste[delivery.caste as usize] += delivery.amount;
+        if let Ok((_, transform, Some(mut trip))) = ant_query.get_mut(delivery.ant) {
+            totals.trips_completed += 1;
+            totals.window_trips += 1;
+            totals.window_trip_ticks += tick.0 - trip.started;
+            totals.window_trip_distance += trip.distance;
+            *trip = Trip::new(tick.0, transform.translation.truncate());
+        }
+    }
+}
+
+/// Counts the ants born and died in each colony, by caste.
+pub fn population_system(
+    mut stats: ResMut<ColonyStats>,
+    mut births: EventReader<AntBorn>,
+    mut deaths: EventReader<AntDied>,
+) {
+    for birth in births.iter() {
+        let totals = stats.colonies.entry(birth.colony).or_default();
+        totals.births += 1;
+        totals.births_by_caste[birth.caste as usize] += 1;
+    }
+    for death in deaths.iter() {
+        let totals = stats.colonies.entry(death.colony).or_default();
+        totals.deaths += 1;
+        totals.deaths_by_caste[death.caste as usize] += 1;
+    }
+}
+
+/// Takes one sample for every colony with a home or an ant.
+pub fn colony_stats_system(
+    tick: Res<SimulationTick>,
+    config: Res<Config>,
+    pheromone_field: Res<PheromoneField>,
+    mut stats: ResMut<ColonyStats>,
+    ant_query: Query<(&Ant, &AntState, &Caste, &ColonyId)>,
+    source_query: Query<&FoodSource>,
+    home_query: Query<(&ColonyId, &Home)>,
+) {
+    let sample_period = config.get(&STATS_SAMPLE_PERIOD);
+    if tick.0 % sample_period != 0 {
+        return;
+    }
+    let food_remaining = source_query.iter().map(|source| source.amount).sum();
+    let colonies: BTreeSet<ColonyId> = home_query
+        .iter()
+        .map(|(colony, _)| colony)
+        .chain(ant_query.iter().map(|(_, _, _, colony)| colony))
+        .copied()
+        .collect();
+    for colony in colonies {
+        let (mut ants_carrying, mut ants_searching) = (0, 0);
+        let mut ants_by_state = vec![0; AntState::ALL.len()];
+        let mut ants_by_caste = vec![0; Caste::ALL.len()];
+        for (ant, &state, &caste, _) in ant_query
+            .iter()
+            .filter(|&(_, _, _, &ant_colony)| ant_colony == colony)
+        {
+            if ant.carrying_food {
+                ants_carrying += 1;
+            } else {
+                ants_searching += 1;
+            }
+            ants_by_state[state as usize] += 1;
+            ants_by_caste[caste as usize] += 1;
+        }
+        let food_stored = home_query
+            .iter()
+            .filter(|&(&home_colony, _)| home_colony == colony)
+            .map(|(_, home)| home.food_store)
+            .sum();
+        let totals = stats.colonies.entry(colony).or_default();
+        let window_trips = totals.window_trips as f32;
+        let (average_trip_ticks, average_trip_distance) = if totals.window_trips > 0 {
+            (
+                Some(totals.window_trip_ticks as f32 / window_trips),
+                Some(totals.window_trip_distance / window_trips),
+            )
+        } else {
+            (None, None)
+        };
+        let sample = StatsSample {
+            tick: tick.0,
+            colony,
+            food_delivered: totals.food_delivered,
+            food_delivered_by_caste: totals.food_delivered_by_caste.to_vec(),
+            food_stored,
+            food_remaining,
+            ants_carrying,
+            ants_searching,
+            ants_by_state,
+            ants_by_caste,
+            births: totals.births,
+            births_by_caste: totals.births_by_caste.to_vec(),
+            deaths: totals.deaths,
+            deaths_by_caste: totals.deaths_by_caste.to_vec(),
+            active_trails: TrailType::ALL
+                .iter()
+                .map(|trail_type| {
+                    pheromone_field
+                        .channel(trail_type.channel(colony))
+                        .iter()
+                        .filter(|&&strength| strength > 0.0)
+                        .count()
+                })
+                .collect(),
+            trips_completed: totals.trips_completed,
+            average_trip_ticks,
+            average_trip_distance,
+        };
+        totals.window_trips = 0;
+        totals.window_trip_ticks = 0;
+        totals.window_trip_distance = 0.0;
+        stats.samples.push_back(sample);
+        stats.unexported += 1;
+    }
+    let excess = stats.samples.len().saturating_sub(MAX_SAMPLES);
+    stats.samples.drain(..excess);
+    stats.unexported = stats.unexported.min(stats.samples.len());
+}
+
+fn write_samples<'a>(
+    path: &Path,
+    samples: impl IntoIterator<Item = &'a StatsSample>,
+    truncate: bool,
+) -> io::Result<()> {
+    let format = StatsFormat::from_path(path);
+    let mut file = if truncate {
+        File::create(path)?
+    } else {
+        OpenOptions::new().append(true).open(path)?
+    };
+    if truncate && format == StatsFormat::Csv {
+        writeln!(file, "{}", CSV_HEADER)?;
+    }
+    for sample in samples {
+        match format {
+            StatsFormat::Csv => writeln!(file, "{}", sample.to_csv())?,
+            StatsFormat::JsonLines => writeln!(file, "{}", serde_json::to_string(sample)?)?,
+        }
+    }
+    Ok(())
+}
+
+/// How far `stats_export_system` got writing the `StatsFile`.
+#[derive(Default)]
+pub struct StatsExport {
+    /// Whether the file was created, with its header.
+    started: bool,
+    /// Tick of the newest sample at the last failed write, which is retried once a later sample
+    /// is taken.
+    failed_at: Option<usize>,
+}
+
+/// Appends the samples taken since the last successful export to the `StatsFile`, if any.
+pub fn stats_export_system(
+    mut stats: ResMut<ColonyStats>,
+    stats_file: Res<StatsFile>,
+    mut export: Local<StatsExport>,
+) {
+    let path = match &stats_file.0 {
+        Some(path) => path,
+        None => return,
+    };
+    let newest_tick = stats.samples.back().map(|sample| sample.tick);
+    if stats.unexported == 0 || export.failed_at == newest_tick {
+        return;
+    }
+    let first = stats.samples.len() - stats.unexported;
+    match write_samples(path, stats.samples.range(first..), !export.started) {
+        Ok(()) => {
+            stats.unexported = 0;
+            export.started = true;
+            export.failed_at = None;
+        }
+        Err(e) => {
+            eprintln!("error: failed to write stats {}: {}", path.display(), e);
+            export.failed_at = newest_tick;
+        }
+    }
+}
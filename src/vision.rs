@@ -0,0 +1,230 @@
+use crate::ants_plugin::{vec3_angle, Ant, ColonyId, Home};
+use crate::arena::{Arena, Boundary};
+use crate::config::{Config, ConfigKey};
+use crate::food::FoodSource;
+use crate::helpers::obstacle_grid::ObstacleGrid;
+use crate::helpers::spatial_index::SpatialIndex;
+use crate::helpers::tilemap_utils::{raycast_tiles, TileHit};
+use bevy::prelude::*;
+
+pub const VISION_DISTANCE: ConfigKey<f32> = ConfigKey {
+    key: "vision.distance",
+    default: 40.0,
+    min: Some(0.0),
+    max: None,
+    units: "world units",
+    description: "How far vision rays reach for walls",
+};
+
+pub const VISION_ANGLE: ConfigKey<f32> = ConfigKey {
+    key: "vision.angle",
+    default: std::f32::consts::PI / 3.0,
+    min: Some(0.0),
+    max: Some(std::f32::consts::PI),
+    units: "radians",
+    description: "Half width of the vision cone",
+};
+
+pub const VISION_RAYS: ConfigKey<usize> = ConfigKey {
+    key: "vision.rays",
+    default: 5,
+    min: Some(0),
+    max: Some(64),
+    units: "",
+    description: "Rays cast across the vision cone to find walls",
+};
+
+pub const VISION_WALL_AVOIDANCE: ConfigKey<f32> = ConfigKey {
+    key: "vision.wall_avoidance",
+    default: 0.5,
+    min: Some(0.0),
+    max: None,
+    units: "",
+    description: "Weight of turning away from seen walls when steering",
+};
+
+pub const ANT_THREAT_DISTANCE: ConfigKey<f32> = ConfigKey {
+    key: "ant.threat_distance",
+    default: 20.0,
+    min: Some(0.0),
+    max: None,
+    units: "world units",
+    description: "How close an ant of another colony must be to count as a threat",
+};
+
+pub fn register_config(config: &mut Config) {
+    config.register(&VISION_DISTANCE);
+    config.register(&VISION_ANGLE);
+    config.register(&VISION_RAYS);
+    config.register(&VISION_WALL_AVOIDANCE);
+    config.register(&ANT_THREAT_DISTANCE);
+}
+
+/// What an ant saw this tick, refreshed by `vision_system` before steering.
+#[derive(Component, Default)]
+pub struct Sight {
+    /// Nearest food source with food left within the ant's `food_perception` radius with a clear
+    /// line of sight.
+    pub food: Option<Vec2>,
+    /// Nearest home of the ant's own colony within its `nest_perception` radius with a clear line
+    /// of sight.
+    pub home: Option<Vec2>,
+    /// Nearest ant of another colony within `ant.threat_distance` with a clear line of sight.
+    pub threat: Option<Vec2>,
+    /// Distance to the first wall along each vision ray, in `Sight::ray_angles` order.
+    pub walls: Vec<Option<f32>>,
+}
+
+impl Sight {
+    /// Angles of the `vision.rays` rays relative to the ant's facing, spread evenly across the
+    /// `vision.angle` cone from left to right.
+    pub fn ray_angles(config: &Config) -> Vec<f32> {
+        let half_angle = config.get(&VISION_ANGLE);
+        let rays = config.get(&VISION_RAYS);
+        match rays {
+            0 => Vec::new(),
+            1 => vec![0.0],
+            _ => (0..rays)
+                .map(|i| half_angle - 2.0 * half_angle * i as f32 / (rays - 1) as f32)
+                .collect(),
+        }
+    }
+
+    /// Direction, relative to the ant's facing, that turns it away from the walls it sees, each
+    /// weighted by how close it is. Zero when no wall is in sight.
+    pub fn wall_avoidance(&self, ray_angles: &[f32], vision_distance: f32) -> Vec2 {
+        let mut avoidance = Vec2::ZERO;
+        for (&angle, wall) in ray_angles.iter().zip(self.walls.iter()) {
+            if let Some(distance) = wall {
+                let closeness = 1.0 - distance / vision_distance;
+                avoidance -= Vec2::new(angle.cos(), angle.sin()) * closeness;
+            }
+        }
+        avoidance
+    }
+}
+
+/// Whether `tile` blocks sight: obstacle tiles, and with a solid boundary every tile whose center
+/// lies outside the arena.
+fn blocks_sight(obstacle_grid: &ObstacleGrid, arena: &Arena, tile: IVec2) -> bool {
+    let solid_boundary = arena.boundary == Boundary::Solid;
+    if tile.x < 0 || tile.y < 0 || !obstacle_grid.in_grid(tile.as_uvec2()) {
+        return solid_boundary;
+    }
+    let tile = tile.as_uvec2();
+    obstacle_grid.is_obstacle(tile)
+        || (solid_boundary
+            && !arena.contains(obstacle_grid.world_pos_from_tile_pos(tile).truncate()))
+}
+
+/// Casts a ray from `start` along `direction` and returns the first wall it hits within
+/// `max_distance`.
+pub fn raycast(
+    obstacle_grid: &ObstacleGrid,
+    arena: &Arena,
+    start: Vec2,
+    direction: Vec2,
+    max_distance: f32,
+) -> Option<TileHit> {
+    raycast_tiles(
+        obstacle_grid.origin,
+        obstacle_grid.tile_size,
+        start,
+        direction,
+        max_distance,
+        |tile| blocks_sight(obstacle_grid, arena, tile),
+    )
+}
+
+/// Returns whether no wall lies between `from` and `to`.
+pub fn line_of_sight(obstacle_grid: &ObstacleGrid, arena: &Arena, from: Vec2, to: Vec2) -> bool {
+    raycast(obstacle_grid, arena, from, to - from, (to - from).length()).is_none()
+}
+
+/// Nearest of `candidates` that an ant at `pos` has a clear line of sight to.
+fn nearest_visible(
+    candidates: impl Iterator<Item = Vec2>,
+    pos: Vec2,
+    obstacle_grid: &ObstacleGrid,
+    arena: &Arena,
+) -> Option<Vec2> {
+    let mut nearest = None;
+    let mut nearest_distance = f32::INFINITY;
+    for candidate in candidates {
+        let candidate_distance = candidate.distance_squared(pos);
+        if candidate_distance < nearest_distance
+            && line_of_sight(obstacle_grid, arena, pos, candidate)
+        {
+            nearest = Some(candidate);
+            nearest_distance = candidate_distance;
+        }
+    }
+    nearest
+}
+
+/// Looks along every ant's vision cone for walls and around it for food, its colony's homes and
+/// ants of other colonies. Walls are found with `vision.rays` raycasts against the obstacle grid,
+/// rather than with `collide_tiles_with_sector`, since steering needs a distance per direction and
+/// that query reads the rendered tilemap, which headless runs don't have.
+/// The rest is sensed in every direction within the ant's perception radii and
+/// `ant.threat_distance`, but only when no wall stands between it and the ant.
+pub fn vision_system(
+    config: Res<Config>,
+    obstacle_grid: Res<ObstacleGrid>,
+    arena: Res<Arena>,
+    food_index: Res<SpatialIndex<FoodSource>>,
+    home_index: Res<SpatialIndex<Home>>,
+    ant_index: Res<SpatialIndex<Ant>>,
+    colony_query: Query<&ColonyId>,
+    source_query: Query<&FoodSource>,
+    mut ant_query: Query<(&Ant, &ColonyId, &Transform, &mut Sight)>,
+) {
+    let distance = config.get(&VISION_DISTANCE);
+    let threat_distance = config.get(&ANT_THREAT_DISTANCE);
+    let ray_angles = Sight::ray_angles(&config);
+    for (ant, &colony, transform, mut sight) in ant_query.iter_mut() {
+        let pos = transform.translation.truncate();
+        let angle = vec3_angle(transform.rotation * Vec3::X);
+        sight.walls = ray_angles
+            .iter()
+            .map(|ray_angle| {
+                let direction = Vec2::new((angle + ray_angle).cos(), (angle + ray_angle).sin());
+                raycast(&obstacle_grid, &arena, pos, direction, distance).map(|hit| hit.distance)
+            })
+            .collect();
+        sight.food = nearest_visible(
+            food_index
+                .query_radius(pos, ant.food_perception)
+                .into_iter()
+                .filter(|&(source, _)| {
+                    source_query
+                        .get(source)
+                        .map_or(false, |source| !source.is_depleted())
+                })
+                .map(|(_, food_pos)| food_pos),
+            pos,
+            &obstacle_grid,
+            &arena,
+        );
+        sight.home = nearest_visible(
+            home_index
+                .query_radius(pos, ant.nest_perception)
+                .into_iter()
+                .filter(|&(home, _)| colony_query.get(home).map_or(false, |&c| c == colony))
+                .map(|(_, home_pos)| home_pos),
+            pos,
+            &obstacle_grid,
+            &arena,
+        );
+        sight.threat = nearest_visible(
+            ant_index
+                .query_radius(pos, threat_distance)
+                .into_iter()
+                .filter(|&(other, _)| colony_query.get(other).map_or(false, |&c| c != colony))
+                .map(|(_, other_pos)| other_pos),
+            pos,
+            &obstacle_grid,
+            &arena,
+        );
+    }
+}
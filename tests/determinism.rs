@@ -0,0 +1,93 @@
+use ants_sim::ants_plugin::{SimulationControl, TickMode};
+use ants_sim::console_commands::ConsoleCommands;
+use ants_sim::determinism::{compare_runs, first_difference, state_entries, Run};
+use ants_sim::snapshot::Snapshot;
+use bevy::prelude::*;
+
+const TICKS: usize = 300;
+/// Ticks run before and after taking the snapshot in `restored_snapshot_resumes_exactly`.
+const TICKS_BEFORE_SNAPSHOT: usize = 150;
+const TICKS_AFTER_SNAPSHOT: usize = 150;
+
+fn run(seed: i32, threads: usize) -> Run {
+    Run {
+        scenario: None,
+        seed: Some(seed),
+        threads,
+        tick_mode: TickMode::EveryUpdate,
+    }
+}
+
+/// Starts a paused fixed timestep app, then runs `frames` frames that each `step` `ticks` ticks.
+fn step_frames(frames: usize, ticks: usize) -> App {
+    let mut app = Run {
+        tick_mode: TickMode::FixedTimestep,
+        ..run(7, 1)
+    }
+    .app();
+    app.world
+        .get_resource_mut::<SimulationControl>()
+        .unwrap()
+        .paused = true;
+    app.update();
+    for _ in 0..frames {
+        let line = format!("step {}", ticks);
+        app.world
+            .resource_scope(|world, mut commands: Mut<ConsoleCommands>| commands.run(&line, world));
+        app.update();
+    }
+    app
+}
+
+#[test]
+fn same_seed_agrees_across_thread_counts() {
+    if let Err(divergence) = compare_runs(&run(7, 1), &run(7, 4), TICKS) {
+        panic!("{}", divergence);
+    }
+}
+
+#[test]
+fn different_seeds_diverge() {
+    assert!(compare_runs(&run(7, 1), &run(8, 1), TICKS).is_err());
+}
+
+#[test]
+fn restored_snapshot_resumes_exactly() {
+    let mut uninterrupted = run(7, 1).app();
+    for _ in 0..TICKS_BEFORE_SNAPSHOT {
+        uninterrupted.update();
+    }
+    let snapshot = Snapshot::capture(&mut uninterrupted.world);
+
+    // a fresh app, started up and then replaced by the snapshot
+    let mut restored = run(7, 1).app();
+    restored.update();
+    snapshot.restore(&mut restored.world).unwrap();
+
+    for tick in 1..=TICKS_AFTER_SNAPSHOT {
+        uninterrupted.update();
+        restored.update();
+        let entries_a = state_entries(&mut uninterrupted.world);
+        let entries_b = state_entries(&mut restored.world);
+        if let Some(difference) = first_difference(&entries_a, &entries_b) {
+            panic!(
+                "restored run diverges {} ticks after the snapshot: {}",
+                tick, difference
+            );
+        }
+    }
+}
+
+#[test]
+fn stepping_ticks_in_one_frame_matches_one_tick_per_frame() {
+    let mut batched = step_frames(1, 5);
+    let mut single = step_frames(5, 1);
+    let entries_a = state_entries(&mut batched.world);
+    let entries_b = state_entries(&mut single.world);
+    assert!(entries_a
+        .iter()
+        .any(|entry| entry.label.ends_with("RigidBody")));
+    if let Some(difference) = first_difference(&entries_a, &entries_b) {
+        panic!("5 ticks in one frame differ from 5 frames: {}", difference);
+    }
+}